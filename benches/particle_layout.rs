@@ -0,0 +1,57 @@
+// Compares the standard and packed particle layouts by running the CPU particle simulator over
+// a large emitter. Run with `cargo bench --bench particle_layout`.
+//
+// The packed layout moves half the bytes per step. On the CPU its half float conversions are
+// done in software, so it's slower here; the GPU converts them in hardware, where bandwidth is
+// what counts.
+use rust_wasm_gltf::particle::{CpuSimulator, EmitterOptions, ParticleLayout};
+use std::time::Instant;
+
+const NUM_PARTICLES: u32 = 100_000;
+const STEPS: u32 = 120;
+const TIME_DELTA: f32 = 1.0 / 60.0;
+
+fn main() {
+    let options = EmitterOptions {
+        num_particles: NUM_PARTICLES,
+        gravity: glam::vec3(-7.0, 0.0, 0.0),
+        min_speed: 0.02,
+        max_speed: 0.3,
+        ..Default::default()
+    };
+
+    for (name, layout) in [
+        ("standard", ParticleLayout::standard()),
+        ("packed", ParticleLayout::packed()),
+    ] {
+        let stride = layout.stride();
+        let mut simulator = CpuSimulator::new(layout, options, 0x5eed);
+
+        // warm up, so every particle has been spawned at least once
+        for _ in 0..10 {
+            simulator.step(TIME_DELTA);
+        }
+
+        let start = Instant::now();
+        for _ in 0..STEPS {
+            simulator.step(TIME_DELTA);
+        }
+        let elapsed = start.elapsed();
+
+        // every step reads one buffer and writes the other
+        let bytes_per_step = 2 * stride * NUM_PARTICLES as usize;
+        let per_step = elapsed / STEPS;
+        println!(
+            "{:>8}: {:>2} bytes/particle, {:>6.2} MiB moved/step, {:>8.2?}/step, {:>8.1} MiB/s",
+            name,
+            stride,
+            bytes_per_step as f64 / (1024.0 * 1024.0),
+            per_step,
+            bytes_per_step as f64 / (1024.0 * 1024.0) / per_step.as_secs_f64(),
+        );
+
+        // keep the optimizer from discarding the simulation
+        let max_age = simulator.particles().map(|p| p.age).fold(0.0, f32::max);
+        assert!(max_age.is_finite());
+    }
+}
@@ -1,4 +1,29 @@
 // For more comments about what's going on here, check out the `hello_world`
 // example.
 import('./pkg')
+  .then(pkg => {
+    const engine = new pkg.Engine(document.getElementById('canvas'));
+    engine.spawn_emitter({
+      gravity: [-7, 0, 0],
+      minSpeed: 0.02,
+      maxSpeed: 0.3,
+      light: true,
+      colors: [
+        [1, 1, 1, 1],
+        [1, 0.83, 0, 0.9],
+        [0.75, 0.25, 0.05, 0.8],
+        [0.18, 0, 0.02, 0.5],
+        [0, 0, 0, 0],
+      ],
+    });
+    engine.set_camera([0, 0.5, 1.5], [0, 0, 0]);
+    engine.start();
+    window.engine = engine;
+
+    // With the `hot-reload` feature, shaders can be replaced from the console
+    // or a dev server, e.g. `setShaderSource("particle-render-frag.glsl", src)`
+    if (pkg.set_shader_source) {
+      window.setShaderSource = pkg.set_shader_source;
+    }
+  })
   .catch(console.error);
@@ -0,0 +1,537 @@
+use glam::{IVec2, Mat4, Vec2, Vec3, Vec3Swizzles, Vec4Swizzles};
+
+// The map lies on the XY plane at z = 0, one world unit per tile, with tile (x, y) covering
+// [x, x + 1) × [y, y + 1). Cameras looking down at it use +Z as "towards the viewer".
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    // Infinite far plane
+    Perspective { fov_y_degrees: f32, near: f32 },
+    // `height` world units fit the viewport vertically
+    Orthographic { height: f32, near: f32, far: f32 },
+    // Orthographic, with every world unit (tile) exactly `tile_size` pixels across and tile edges
+    // on pixel boundaries, so tile art is drawn without blurring or shimmering
+    PixelPerfect { tile_size: f32, near: f32, far: f32 },
+}
+
+impl Projection {
+    // `viewport` is the size of the viewport in pixels
+    pub fn matrix(&self, viewport: Vec2) -> Mat4 {
+        match *self {
+            Projection::Perspective {
+                fov_y_degrees,
+                near,
+            } => Mat4::perspective_infinite_rh(
+                fov_y_degrees.to_radians(),
+                viewport.x / viewport.y,
+                near,
+            ),
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * viewport.x / viewport.y;
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+            Projection::PixelPerfect {
+                tile_size,
+                near,
+                far,
+            } => {
+                // With an odd number of pixels the centre is in the middle of one, so it's
+                // shifted half a pixel to keep pixel edges on whole multiples of 1 / tile_size
+                let left = -(viewport.x / 2.0).floor() / tile_size;
+                let bottom = -(viewport.y / 2.0).floor() / tile_size;
+                Mat4::orthographic_rh(
+                    left,
+                    left + viewport.x / tile_size,
+                    bottom,
+                    bottom + viewport.y / tile_size,
+                    near,
+                    far,
+                )
+            }
+        }
+    }
+
+    // A pixel-perfect projection for a camera looking down at the map from up to 1000 units away
+    pub fn pixel_perfect(tile_size: f32) -> Self {
+        Projection::PixelPerfect {
+            tile_size,
+            near: 0.01,
+            far: 1000.0,
+        }
+    }
+
+    pub fn orthographic(height: f32) -> Self {
+        Projection::Orthographic {
+            height,
+            near: 0.01,
+            far: 1000.0,
+        }
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective {
+            fov_y_degrees: 45.0,
+            near: 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    pub projection: Projection,
+    // Added to both `eye` and `target`, e.g. by `ScreenShake`
+    pub offset: Vec3,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            eye: Vec3::new(0.0, 0.5, 1.5),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            projection: Projection::default(),
+            offset: Vec3::ZERO,
+        }
+    }
+}
+
+// A line through the scene, e.g. from the camera through a point on the screen
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    // Normalized
+    pub direction: Vec3,
+}
+
+impl Ray {
+    // Where the ray crosses the plane z = `z`, if it does in front of its origin
+    pub fn intersect_z(&self, z: f32) -> Option<Vec3> {
+        if self.direction.z.abs() < f32::EPSILON {
+            return None;
+        }
+        let t = (z - self.origin.z) / self.direction.z;
+        (t >= 0.0).then(|| self.origin + self.direction * t)
+    }
+
+    // The map tile the ray hits
+    pub fn tile(&self) -> Option<IVec2> {
+        self.intersect_z(0.0)
+            .map(|point| point.xy().floor().as_ivec2())
+    }
+}
+
+impl Camera {
+    pub fn position(&self) -> Vec3 {
+        self.eye + self.offset
+    }
+
+    // With a pixel-perfect projection the view is snapped to whole pixels, so the map doesn't
+    // shimmer as the camera moves
+    pub fn view(&self) -> Mat4 {
+        let mut eye = self.eye + self.offset;
+        let mut target = self.target + self.offset;
+        if let Projection::PixelPerfect { tile_size, .. } = self.projection {
+            // Both move by the same amount, so the view direction is unchanged
+            let snapped = (target.xy() * tile_size).round() / tile_size;
+            let shift = (snapped - target.xy()).extend(0.0);
+            eye += shift;
+            target += shift;
+        }
+        Mat4::look_at_rh(eye, target, self.up)
+    }
+
+    pub fn projection_matrix(&self, viewport: Vec2) -> Mat4 {
+        self.projection.matrix(viewport)
+    }
+
+    pub fn view_projection(&self, viewport: Vec2) -> Mat4 {
+        self.projection_matrix(viewport) * self.view()
+    }
+
+    // The ray through `screen`, in pixels from the top left of a viewport `viewport` pixels big
+    pub fn screen_to_ray(&self, screen: Vec2, viewport: Vec2) -> Ray {
+        let ndc = Vec2::new(
+            screen.x / viewport.x * 2.0 - 1.0,
+            1.0 - screen.y / viewport.y * 2.0,
+        );
+        let inverse = self.view_projection(viewport).inverse();
+        // Depths 0 and 1/2 are both in front of the camera and finite, even with the infinite
+        // perspective projection
+        let near = inverse.project_point3(ndc.extend(0.0));
+        let far = inverse.project_point3(ndc.extend(0.5));
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    // Where `point` ends up on screen, in pixels from the top left of the viewport, or `None`
+    // if it is behind the camera
+    pub fn world_to_screen(&self, point: Vec3, viewport: Vec2) -> Option<Vec2> {
+        let clip = self.view_projection(viewport) * point.extend(1.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.xy() / clip.w;
+        Some(Vec2::new(
+            (ndc.x + 1.0) / 2.0 * viewport.x,
+            (1.0 - ndc.y) / 2.0 * viewport.y,
+        ))
+    }
+
+    // The map tile under `screen`
+    pub fn screen_to_tile(&self, screen: Vec2, viewport: Vec2) -> Option<IVec2> {
+        self.screen_to_ray(screen, viewport).tile()
+    }
+}
+
+// Turns mouse drags and wheel movement into a camera circling `target`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OrbitController {
+    pub target: Vec3,
+    pub distance: f32,
+    // Around +Y, in radians; 0 looks down -Z
+    pub yaw: f32,
+    // Above the horizon, in radians
+    pub pitch: f32,
+    // Radians per pixel dragged
+    pub sensitivity: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self {
+            target: Vec3::ZERO,
+            distance: 1.6,
+            yaw: 0.0,
+            pitch: 0.32,
+            sensitivity: 0.005,
+            min_distance: 0.1,
+            max_distance: 100.0,
+        }
+    }
+}
+
+impl OrbitController {
+    // Orbits by a drag of (`dx`, `dy`) pixels
+    pub fn drag(&mut self, dx: f32, dy: f32) {
+        let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+        self.yaw -= dx * self.sensitivity;
+        self.pitch = (self.pitch + dy * self.sensitivity).clamp(-limit, limit);
+    }
+
+    // Moves closer for negative `delta` (wheel pixels) and further away for positive ones
+    pub fn zoom(&mut self, delta: f32) {
+        self.distance =
+            (self.distance * (delta * 0.001).exp()).clamp(self.min_distance, self.max_distance);
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        let direction = Vec3::new(
+            self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            self.yaw.cos() * self.pitch.cos(),
+        );
+        camera.eye = self.target + direction * self.distance;
+        camera.target = self.target;
+        camera.up = Vec3::Y;
+    }
+}
+
+// The usual roguelike view: straight down at the map from `height` above it, following a tile
+// (usually the player) smoothly as it moves
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TopDownController {
+    pub height: f32,
+    // Centre of the followed tile
+    pub goal: Vec2,
+    pub position: Vec2,
+    // How quickly the camera closes in on the goal: the remaining distance halves every
+    // `1 / smoothing` seconds or so. 0 snaps straight to it.
+    pub smoothing: f32,
+}
+
+impl Default for TopDownController {
+    fn default() -> Self {
+        Self {
+            height: 20.0,
+            goal: Vec2::ZERO,
+            position: Vec2::ZERO,
+            smoothing: 8.0,
+        }
+    }
+}
+
+impl TopDownController {
+    pub fn follow(&mut self, tile: IVec2) {
+        self.goal = tile.as_vec2() + Vec2::splat(0.5);
+    }
+
+    // Jumps to the goal, e.g. after a teleport
+    pub fn snap(&mut self) {
+        self.position = self.goal;
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        if self.smoothing <= 0.0 {
+            self.snap();
+        } else {
+            let t = 1.0 - (-self.smoothing * delta).exp();
+            self.position = self.position.lerp(self.goal, t);
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.eye = self.position.extend(self.height);
+        camera.target = self.position.extend(0.0);
+        camera.up = Vec3::Y;
+    }
+}
+
+// Shakes the camera in proportion to the square of its "trauma", which wears off over time, so
+// small hits barely move it and big ones pile up
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScreenShake {
+    trauma: f32,
+    // Trauma lost per second
+    pub decay: f32,
+    // Offset at full trauma, in world units
+    pub amplitude: f32,
+    pub frequency: f32,
+    time: f32,
+}
+
+impl Default for ScreenShake {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            decay: 1.5,
+            amplitude: 0.3,
+            frequency: 25.0,
+            time: 0.0,
+        }
+    }
+}
+
+impl ScreenShake {
+    // Adds to the trauma, which is capped at 1. Non-finite amounts are ignored.
+    pub fn add_trauma(&mut self, amount: f32) {
+        if amount.is_finite() {
+            self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+        }
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    // Advances the shake and returns the offset to apply to the camera
+    pub fn update(&mut self, delta: f32) -> Vec3 {
+        self.time += delta;
+        self.trauma = (self.trauma - self.decay * delta).max(0.0);
+
+        let strength = self.trauma * self.trauma * self.amplitude;
+        let t = self.time * self.frequency;
+        // Sums of incommensurate sines stand in for noise
+        Vec3::new(
+            (t * 1.0).sin() * 0.6 + (t * 2.3 + 1.7).sin() * 0.4,
+            (t * 1.3 + 4.1).sin() * 0.6 + (t * 2.9 + 0.3).sin() * 0.4,
+            0.0,
+        ) * strength
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: Vec2 = Vec2::new(800.0, 600.0);
+
+    fn top_down(projection: Projection) -> Camera {
+        let mut controller = TopDownController::default();
+        let mut camera = Camera {
+            projection,
+            ..Default::default()
+        };
+        controller.follow(IVec2::new(3, -3));
+        controller.snap();
+        controller.apply(&mut camera);
+        camera
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn tiles_round_trip_through_the_screen() {
+        for projection in [
+            Projection::default(),
+            Projection::orthographic(12.0),
+            Projection::pixel_perfect(32.0),
+        ] {
+            let camera = top_down(projection);
+            for tile in [IVec2::new(3, -3), IVec2::new(0, 0), IVec2::new(7, 1)] {
+                let centre = (tile.as_vec2() + 0.5).extend(0.0);
+                let screen = camera.world_to_screen(centre, VIEWPORT).unwrap();
+                assert_eq!(camera.screen_to_tile(screen, VIEWPORT), Some(tile));
+            }
+        }
+    }
+
+    #[test]
+    fn followed_tile_is_in_the_middle_of_the_screen() {
+        let camera = top_down(Projection::default());
+        assert_eq!(
+            camera.screen_to_tile(VIEWPORT / 2.0, VIEWPORT),
+            Some(IVec2::new(3, -3))
+        );
+
+        let ray = camera.screen_to_ray(VIEWPORT / 2.0, VIEWPORT);
+        assert_close(ray.direction, -Vec3::Z);
+        assert_close(ray.intersect_z(0.0).unwrap(), Vec3::new(3.5, -2.5, 0.0));
+    }
+
+    #[test]
+    fn points_behind_the_camera_are_not_on_screen() {
+        let camera = top_down(Projection::default());
+        assert_eq!(
+            camera.world_to_screen(Vec3::new(3.5, -2.5, 30.0), VIEWPORT),
+            None
+        );
+        assert!(camera
+            .world_to_screen(Vec3::new(3.5, -2.5, 10.0), VIEWPORT)
+            .is_some());
+
+        // looking at the horizon, the sky doesn't hit the map
+        let camera = Camera {
+            eye: Vec3::new(0.0, 0.0, 1.0),
+            target: Vec3::new(0.0, 10.0, 1.0),
+            up: Vec3::Z,
+            ..Default::default()
+        };
+        assert_eq!(camera.screen_to_tile(Vec2::new(400.0, 0.0), VIEWPORT), None);
+        // the bottom edge looks 22.5° down, reaching the map 1 / tan(22.5°) ≈ 2.4 tiles out
+        assert_eq!(
+            camera.screen_to_tile(Vec2::new(400.0, 600.0), VIEWPORT),
+            Some(IVec2::new(0, 2))
+        );
+    }
+
+    #[test]
+    fn rays_only_hit_planes_in_front() {
+        let ray = Ray {
+            origin: Vec3::new(0.5, 0.5, 2.0),
+            direction: Vec3::new(1.0, 0.0, -1.0).normalize(),
+        };
+        assert_close(ray.intersect_z(0.0).unwrap(), Vec3::new(2.5, 0.5, 0.0));
+        assert_eq!(ray.tile(), Some(IVec2::new(2, 0)));
+        assert_eq!(ray.intersect_z(3.0), None);
+
+        let level = Ray {
+            origin: Vec3::ONE,
+            direction: Vec3::X,
+        };
+        assert_eq!(level.intersect_z(0.0), None);
+    }
+
+    #[test]
+    fn pixel_perfect_tile_edges_land_on_whole_pixels() {
+        for viewport in [
+            Vec2::new(800.0, 600.0),
+            Vec2::new(801.0, 601.0),
+            Vec2::new(800.0, 601.0),
+        ] {
+            for tile_size in [16.0, 24.0] {
+                // off the pixel grid, so the view has to snap
+                let mut camera = top_down(Projection::pixel_perfect(tile_size));
+                camera.offset = Vec3::new(0.013, -0.021, 0.0);
+                camera.eye += Vec3::new(0.37, 0.11, 0.0);
+                camera.target += Vec3::new(0.37, 0.11, 0.0);
+
+                for corner in [IVec2::new(0, 0), IVec2::new(3, -3), IVec2::new(-5, 7)] {
+                    let screen = camera
+                        .world_to_screen(corner.as_vec2().extend(0.0), viewport)
+                        .unwrap();
+                    assert!(
+                        (screen - screen.round()).abs().max_element() < 1e-3,
+                        "{} in {} at {} px per tile",
+                        screen,
+                        viewport,
+                        tile_size
+                    );
+                }
+                let next = camera
+                    .world_to_screen(Vec3::new(1.0, 1.0, 0.0), viewport)
+                    .unwrap();
+                let first = camera.world_to_screen(Vec3::ZERO, viewport).unwrap();
+                assert!((next - first - Vec2::new(tile_size, -tile_size)).length() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn orbit_pitch_and_distance_are_clamped() {
+        let mut orbit = OrbitController::default();
+        orbit.drag(0.0, 1e6);
+        assert!(orbit.pitch < std::f32::consts::FRAC_PI_2);
+        orbit.drag(0.0, -1e6);
+        assert!(orbit.pitch > -std::f32::consts::FRAC_PI_2);
+        orbit.zoom(1e6);
+        assert_eq!(orbit.distance, orbit.max_distance);
+        orbit.zoom(-1e6);
+        assert_eq!(orbit.distance, orbit.min_distance);
+
+        let mut camera = Camera::default();
+        orbit.target = Vec3::new(1.0, 2.0, 3.0);
+        orbit.apply(&mut camera);
+        assert_eq!(camera.target, orbit.target);
+        assert!((camera.eye.distance(orbit.target) - orbit.distance).abs() < 1e-5);
+    }
+
+    #[test]
+    fn top_down_closes_in_on_the_goal() {
+        let mut controller = TopDownController::default();
+        controller.follow(IVec2::new(4, 0));
+        assert_eq!(controller.goal, Vec2::new(4.5, 0.5));
+
+        // the remaining distance halves every ln 2 / smoothing seconds
+        controller.update(std::f32::consts::LN_2 / controller.smoothing);
+        assert!((controller.position.distance(controller.goal) - 2.25f32.hypot(0.25)).abs() < 1e-4);
+
+        controller.smoothing = 0.0;
+        controller.update(0.0);
+        assert_eq!(controller.position, controller.goal);
+    }
+
+    #[test]
+    fn trauma_ignores_non_finite_amounts_and_wears_off() {
+        let mut shake = ScreenShake::default();
+        shake.add_trauma(0.5);
+        shake.add_trauma(f32::NAN);
+        shake.add_trauma(f32::INFINITY);
+        assert_eq!(shake.trauma(), 0.5);
+        shake.add_trauma(2.0);
+        assert_eq!(shake.trauma(), 1.0);
+
+        assert!(shake.update(0.1).length() <= shake.amplitude);
+        shake.update(1.0);
+        assert_eq!(shake.trauma(), 0.0);
+        assert_eq!(shake.update(0.1), Vec3::ZERO);
+    }
+}
@@ -0,0 +1,654 @@
+use crate::font::BitmapFont;
+use crate::tilemap::Cell;
+use glam::Vec4;
+use std::fmt;
+use std::ops::Range;
+use wasm_bindgen::JsValue;
+
+// A grid of character cells to draw a roguelike's screen into, like libtcod's and bracket-lib's
+// consoles: `put_char`, `print` (with colour markup), `fill_rect` and `draw_box` write into the
+// active layer, and the layers are flattened by `compose`. Turned into tile map cells with
+// `to_cells`, it is drawn by the tile map shader.
+//
+// Unlike the tile map, rows count down from the top, as text runs. Anything drawn outside the
+// console is clipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Console {
+    width: u32,
+    height: u32,
+    // Bottom first
+    layers: Vec<Vec<ConsoleCell>>,
+    layer: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ConsoleCell {
+    // '\0' for none, which lets the layers below show through. ' ' is an opaque blank.
+    pub character: char,
+    pub foreground: Vec4,
+    // Laid over the layers below by its alpha
+    pub background: Vec4,
+}
+
+impl Default for ConsoleCell {
+    fn default() -> Self {
+        Self {
+            character: '\0',
+            foreground: Vec4::ONE,
+            background: Vec4::ZERO,
+        }
+    }
+}
+
+// Limits for consoles made through `Engine::create_console`, as each layer is a full grid of
+// cells: the most layers, and the most cells over all of them (about 150 MB)
+pub const MAX_LAYERS: u32 = 16;
+pub const MAX_CELLS: u64 = 1 << 22;
+
+// A console's cell count doesn't fit in a `u32`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ConsoleTooLarge {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for ConsoleTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "A {} × {} console has too many cells",
+            self.width, self.height
+        )
+    }
+}
+
+impl From<ConsoleTooLarge> for JsValue {
+    fn from(error: ConsoleTooLarge) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+// The lines `draw_box` draws with, from code page 437
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BoxStyle {
+    Single,
+    Double,
+}
+
+impl BoxStyle {
+    // Top left, top right, bottom left and bottom right corners, then horizontal and vertical
+    // edges
+    fn characters(self) -> [char; 6] {
+        match self {
+            BoxStyle::Single => ['┌', '┐', '└', '┘', '─', '│'],
+            BoxStyle::Double => ['╔', '╗', '╚', '╝', '═', '║'],
+        }
+    }
+}
+
+impl Console {
+    // A `width` × `height` console with a single, empty layer
+    pub fn new(width: u32, height: u32) -> Result<Self, ConsoleTooLarge> {
+        if width.checked_mul(height).is_none() {
+            return Err(ConsoleTooLarge { width, height });
+        }
+        Ok(Self {
+            width,
+            height,
+            layers: vec![Self::empty_layer(width, height)],
+            layer: 0,
+        })
+    }
+
+    fn empty_layer(width: u32, height: u32) -> Vec<ConsoleCell> {
+        vec![ConsoleCell::default(); (width * height) as usize]
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Adds an empty layer on top and returns its index. It doesn't become the active layer.
+    pub fn add_layer(&mut self) -> usize {
+        self.layers.push(Self::empty_layer(self.width, self.height));
+        self.layers.len() - 1
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    // Makes the layer drawing goes to `layer`. Returns false if there's no such layer.
+    pub fn set_layer(&mut self, layer: usize) -> bool {
+        let exists = layer < self.layers.len();
+        if exists {
+            self.layer = layer;
+        }
+        exists
+    }
+
+    pub fn layer(&self) -> usize {
+        self.layer
+    }
+
+    // The cells of the active layer, row by row from the top
+    pub fn cells(&self) -> &[ConsoleCell] {
+        &self.layers[self.layer]
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<&ConsoleCell> {
+        self.index(x, y).map(|i| &self.layers[self.layer][i])
+    }
+
+    // Empties the active layer
+    pub fn clear(&mut self) {
+        self.layers[self.layer].fill(ConsoleCell::default());
+    }
+
+    // Empties every layer
+    pub fn clear_all(&mut self) {
+        for layer in &mut self.layers {
+            layer.fill(ConsoleCell::default());
+        }
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, cell: ConsoleCell) {
+        if let Some(i) = self.index(x, y) {
+            self.layers[self.layer][i] = cell;
+        }
+    }
+
+    pub fn put_char(
+        &mut self,
+        x: i32,
+        y: i32,
+        character: char,
+        foreground: Vec4,
+        background: Vec4,
+    ) {
+        self.set(
+            x,
+            y,
+            ConsoleCell {
+                character,
+                foreground,
+                background,
+            },
+        );
+    }
+
+    // Fills a `width` × `height` rectangle with its top left corner at (`x`, `y`)
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, cell: ConsoleCell) {
+        let rows = clip(y, y.saturating_add(clamp_len(height)), self.height);
+        let columns = clip(x, x.saturating_add(clamp_len(width)), self.width);
+        for row in rows {
+            for column in columns.clone() {
+                self.set(column, row, cell);
+            }
+        }
+    }
+
+    // Draws the outline of a `width` × `height` rectangle, leaving the inside as it is
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_box(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        style: BoxStyle,
+        foreground: Vec4,
+        background: Vec4,
+    ) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let [top_left, top_right, bottom_left, bottom_right, horizontal, vertical] =
+            style.characters();
+        let right = x.saturating_add(clamp_len(width) - 1);
+        let bottom = y.saturating_add(clamp_len(height) - 1);
+        let columns = clip(x.saturating_add(1), right, self.width);
+        let rows = clip(y.saturating_add(1), bottom, self.height);
+        let mut put = |x, y, character| self.put_char(x, y, character, foreground, background);
+
+        for column in columns {
+            put(column, y, horizontal);
+            put(column, bottom, horizontal);
+        }
+        for row in rows {
+            put(x, row, vertical);
+            put(right, row, vertical);
+        }
+        put(x, y, top_left);
+        put(right, y, top_right);
+        put(x, bottom, bottom_left);
+        put(right, bottom, bottom_right);
+    }
+
+    // Prints `text` starting at (`x`, `y`), continuing at `x` on the next row after each '\n'.
+    // Colours change with markup:
+    //
+    //     "You hit the [fg=red]orc[/fg] for [fg=#ffd700][bg=black]12[/bg][/fg] damage"
+    //
+    // `[fg=...]` and `[bg=...]` take a colour name (see `named_color`) or #rgb, #rrggbb or
+    // #rrggbbaa, and last until the matching `[/fg]` or `[/bg]`. `[[` prints a '['. Anything
+    // else in brackets is printed as it is.
+    //
+    // Returns the number of rows printed on.
+    pub fn print(&mut self, x: i32, y: i32, text: &str, foreground: Vec4, background: Vec4) -> u32 {
+        let mut foregrounds = vec![foreground];
+        let mut backgrounds = vec![background];
+        let (mut column, mut row) = (x, y);
+        let mut rest = text;
+
+        while let Some(character) = rest.chars().next() {
+            if let Some(after) = rest.strip_prefix("[[") {
+                self.put_char(
+                    column,
+                    row,
+                    '[',
+                    *foregrounds.last().unwrap(),
+                    *backgrounds.last().unwrap(),
+                );
+                column = column.saturating_add(1);
+                rest = after;
+                continue;
+            }
+            if character == '[' {
+                if let Some((tag, after)) = parse_tag(rest) {
+                    match tag {
+                        Tag::Foreground(color) => foregrounds.push(color),
+                        Tag::Background(color) => backgrounds.push(color),
+                        // The colour the print started with can't be popped
+                        Tag::EndForeground if foregrounds.len() > 1 => {
+                            foregrounds.pop();
+                        }
+                        Tag::EndBackground if backgrounds.len() > 1 => {
+                            backgrounds.pop();
+                        }
+                        Tag::EndForeground | Tag::EndBackground => {}
+                    }
+                    rest = after;
+                    continue;
+                }
+            }
+
+            if character == '\n' {
+                column = x;
+                row = row.saturating_add(1);
+            } else {
+                self.put_char(
+                    column,
+                    row,
+                    character,
+                    *foregrounds.last().unwrap(),
+                    *backgrounds.last().unwrap(),
+                );
+                column = column.saturating_add(1);
+            }
+            rest = &rest[character.len_utf8()..];
+        }
+        row.abs_diff(y) + 1
+    }
+
+    // Flattens the layers, bottom to top: a layer's background is laid over the ones below by
+    // its alpha, and its character (with its foreground) replaces theirs unless it is '\0'.
+    // Row by row from the top.
+    pub fn compose(&self) -> Vec<ConsoleCell> {
+        let mut cells = self.layers[0].clone();
+        for layer in &self.layers[1..] {
+            for (cell, above) in cells.iter_mut().zip(layer) {
+                cell.background = blend(cell.background, above.background);
+                if above.character != '\0' {
+                    cell.character = above.character;
+                    cell.foreground = above.foreground;
+                }
+            }
+        }
+        cells
+    }
+
+    // The composed console as tile map cells, row by row from the bottom as the tile map has
+    // them, drawing characters with their glyphs in `font`. Characters the font lacks are drawn
+    // as '?', or left blank if it lacks that too.
+    pub fn to_cells(&self, font: &BitmapFont) -> Vec<Cell> {
+        let composed = self.compose();
+        let fallback = font.index('?');
+        composed
+            .chunks_exact(self.width.max(1) as usize)
+            .rev()
+            .flatten()
+            .map(|cell| {
+                let glyph = match cell.character {
+                    '\0' => None,
+                    character => font.index(character).or(fallback),
+                };
+                match glyph {
+                    Some(glyph) => Cell::new(glyph, cell.foreground, cell.background),
+                    // Nothing to draw: keep glyph 0 from covering the background
+                    None => Cell::new(0, Vec4::ZERO, cell.background),
+                }
+            })
+            .collect()
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let inside = x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height;
+        inside.then(|| (y as u32 * self.width + x as u32) as usize)
+    }
+}
+
+// A length as a coordinate offset. Anything longer than `i32::MAX` reaches past every cell.
+fn clamp_len(len: u32) -> i32 {
+    len.min(i32::MAX as u32) as i32
+}
+
+// `start..end` limited to the cells of a row or column `size` cells long, so shapes reaching far
+// outside the console don't loop over cells that are clipped anyway
+fn clip(start: i32, end: i32, size: u32) -> Range<i32> {
+    start.max(0)..end.min(clamp_len(size))
+}
+
+// `above` laid over `below` by its alpha
+fn blend(below: Vec4, above: Vec4) -> Vec4 {
+    let alpha = above.w + below.w * (1.0 - above.w);
+    if alpha <= 0.0 {
+        return Vec4::ZERO;
+    }
+    let color = (above.truncate() * above.w + below.truncate() * below.w * (1.0 - above.w)) / alpha;
+    color.extend(alpha)
+}
+
+enum Tag {
+    Foreground(Vec4),
+    Background(Vec4),
+    EndForeground,
+    EndBackground,
+}
+
+// Parses the markup tag `text` starts with, returning it and the text after it
+fn parse_tag(text: &str) -> Option<(Tag, &str)> {
+    let end = text.find(']')?;
+    let (tag, rest) = (&text[1..end], &text[end + 1..]);
+    let tag = match tag {
+        "/fg" => Tag::EndForeground,
+        "/bg" => Tag::EndBackground,
+        _ => {
+            let (name, value) = tag.split_once('=')?;
+            let color = parse_color(value)?;
+            match name {
+                "fg" => Tag::Foreground(color),
+                "bg" => Tag::Background(color),
+                _ => return None,
+            }
+        }
+    };
+    Some((tag, rest))
+}
+
+// A colour name or #rgb, #rrggbb or #rrggbbaa
+pub fn parse_color(value: &str) -> Option<Vec4> {
+    let hex = match value.strip_prefix('#') {
+        Some(hex) => hex,
+        None => return named_color(value),
+    };
+    if !hex.is_ascii() {
+        return None;
+    }
+    let digits: Vec<u8> = match hex.len() {
+        3 => hex
+            .chars()
+            .map(|digit| u8::from_str_radix(&digit.to_string(), 16).map(|d| d * 17))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        _ => return None,
+    };
+    let channel = |i: usize| digits.get(i).map_or(1.0, |&c| c as f32 / 255.0);
+    Some(Vec4::new(channel(0), channel(1), channel(2), channel(3)))
+}
+
+// The colours markup can refer to by name
+pub fn named_color(name: &str) -> Option<Vec4> {
+    let [r, g, b] = match name {
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "gray" | "grey" => [128, 128, 128],
+        "dark_gray" | "dark_grey" => [64, 64, 64],
+        "red" => [255, 0, 0],
+        "dark_red" => [128, 0, 0],
+        "green" => [0, 255, 0],
+        "dark_green" => [0, 128, 0],
+        "blue" => [0, 0, 255],
+        "dark_blue" => [0, 0, 128],
+        "yellow" => [255, 255, 0],
+        "orange" => [255, 165, 0],
+        "cyan" => [0, 255, 255],
+        "magenta" => [255, 0, 255],
+        "purple" => [128, 0, 128],
+        "brown" => [139, 69, 19],
+        "gold" => [255, 215, 0],
+        "transparent" => return Some(Vec4::ZERO),
+        _ => return None,
+    };
+    Some(Vec4::new(r as f32, g as f32, b as f32, 255.0) / 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Vec4 = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    const BLUE: Vec4 = Vec4::new(0.0, 0.0, 1.0, 1.0);
+
+    // The active layer's characters, a line per row with '.' for none
+    fn text(console: &Console) -> Vec<String> {
+        console
+            .cells()
+            .chunks_exact(console.width() as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|cell| match cell.character {
+                        '\0' => '.',
+                        character => character,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn cell(character: char) -> ConsoleCell {
+        ConsoleCell {
+            character,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn put_char_clips_to_the_console() {
+        let mut console = Console::new(3, 2).unwrap();
+        console.put_char(0, 0, 'a', RED, BLUE);
+        console.put_char(2, 1, 'b', RED, BLUE);
+        for (x, y) in [(-1, 0), (3, 0), (0, -1), (0, 2), (i32::MAX, i32::MIN)] {
+            console.put_char(x, y, 'x', RED, BLUE);
+        }
+        assert_eq!(text(&console), ["a..", "..b"]);
+        assert_eq!(
+            console.get(0, 0),
+            Some(&ConsoleCell {
+                character: 'a',
+                foreground: RED,
+                background: BLUE
+            })
+        );
+        assert_eq!(console.get(3, 0), None);
+    }
+
+    #[test]
+    fn too_many_cells_is_an_error() {
+        assert_eq!(
+            Console::new(u32::MAX, 2),
+            Err(ConsoleTooLarge {
+                width: u32::MAX,
+                height: 2
+            })
+        );
+    }
+
+    #[test]
+    fn print_wraps_on_newlines_and_clips() {
+        let mut console = Console::new(4, 3).unwrap();
+        let rows = console.print(1, 1, "abcdef\nxy", Vec4::ONE, Vec4::ZERO);
+        assert_eq!(rows, 2);
+        assert_eq!(text(&console), ["....", ".abc", ".xy."]);
+    }
+
+    #[test]
+    fn print_applies_nested_markup() {
+        let mut console = Console::new(8, 1).unwrap();
+        console.print(
+            0,
+            0,
+            "a[fg=red]b[fg=#00f]c[/fg]d[/fg]e[/fg][bg=blue]f",
+            Vec4::ONE,
+            Vec4::ZERO,
+        );
+        assert_eq!(text(&console), ["abcdef.."]);
+        let foregrounds: Vec<Vec4> = (0..5)
+            .map(|x| console.get(x, 0).unwrap().foreground)
+            .collect();
+        // the extra [/fg] can't pop the colour the print started with
+        assert_eq!(foregrounds, [Vec4::ONE, RED, BLUE, RED, Vec4::ONE]);
+        assert_eq!(console.get(4, 0).unwrap().background, Vec4::ZERO);
+        assert_eq!(console.get(5, 0).unwrap().background, BLUE);
+    }
+
+    #[test]
+    fn print_treats_malformed_markup_as_text() {
+        let mut console = Console::new(12, 3).unwrap();
+        console.print(0, 0, "[[x] [hp=3]", Vec4::ONE, Vec4::ZERO);
+        console.print(0, 1, "[fg=nope]", Vec4::ONE, Vec4::ZERO);
+        console.print(0, 2, "a[fg=red", Vec4::ONE, Vec4::ZERO);
+        assert_eq!(
+            text(&console),
+            ["[x] [hp=3]..", "[fg=nope]...", "a[fg=red...."]
+        );
+
+        // a tag left open only lasts until the end of its print
+        console.print(0, 0, "[fg=red]a", Vec4::ONE, Vec4::ZERO);
+        console.print(1, 0, "b", Vec4::ONE, Vec4::ZERO);
+        assert_eq!(console.get(0, 0).unwrap().foreground, RED);
+        assert_eq!(console.get(1, 0).unwrap().foreground, Vec4::ONE);
+    }
+
+    #[test]
+    fn fill_rect_clips_to_the_console() {
+        let mut console = Console::new(4, 3).unwrap();
+        console.fill_rect(-1, 1, 3, 5, cell('#'));
+        console.fill_rect(3, -5, u32::MAX, u32::MAX, cell('%'));
+        assert_eq!(text(&console), ["...%", "##.%", "##.%"]);
+    }
+
+    #[test]
+    fn draw_box_draws_corners_and_edges() {
+        let mut console = Console::new(5, 4).unwrap();
+        console.fill_rect(0, 0, 5, 4, cell(' '));
+        console.draw_box(0, 0, 5, 4, BoxStyle::Single, Vec4::ONE, Vec4::ZERO);
+        assert_eq!(text(&console), ["┌───┐", "│   │", "│   │", "└───┘"]);
+
+        console.draw_box(1, 1, 1, 1, BoxStyle::Double, Vec4::ONE, Vec4::ZERO);
+        console.draw_box(2, 1, 0, 3, BoxStyle::Double, Vec4::ONE, Vec4::ZERO);
+        assert_eq!(text(&console), ["┌───┐", "│╝  │", "│   │", "└───┘"]);
+    }
+
+    #[test]
+    fn draw_box_clips_huge_boxes() {
+        let mut console = Console::new(4, 3).unwrap();
+        console.draw_box(
+            1,
+            1,
+            u32::MAX,
+            u32::MAX,
+            BoxStyle::Double,
+            Vec4::ONE,
+            Vec4::ZERO,
+        );
+        console.draw_box(
+            i32::MIN,
+            i32::MIN,
+            u32::MAX,
+            u32::MAX,
+            BoxStyle::Single,
+            Vec4::ONE,
+            Vec4::ZERO,
+        );
+        assert_eq!(text(&console), ["....", ".╔══", ".║.."]);
+    }
+
+    #[test]
+    fn to_cells_flips_rows_and_blanks_missing_characters() {
+        // Cell n of the grid is U+n, so '\u{1}' is the only character and there's no '?'
+        let font = BitmapFont::grid(16, 8, 2, 1, 8, 8, crate::font::Charset::Unicode).unwrap();
+        let mut console = Console::new(2, 2).unwrap();
+        console.put_char(0, 0, '\u{1}', RED, BLUE);
+        console.put_char(1, 0, 'x', RED, BLUE);
+        console.put_char(0, 1, '\0', RED, BLUE);
+
+        let cells = console.to_cells(&font);
+        let clear = Cell::new(0, Vec4::ZERO, Vec4::ZERO);
+        assert_eq!(
+            cells,
+            [
+                Cell::new(0, Vec4::ZERO, BLUE),
+                clear,
+                Cell::new(1, RED, BLUE),
+                Cell::new(0, Vec4::ZERO, BLUE),
+            ]
+        );
+
+        // with a '?' in the font, missing characters are drawn with it
+        let font = BitmapFont::grid(512, 8, 64, 1, 8, 8, crate::font::Charset::Unicode).unwrap();
+        assert_eq!(console.to_cells(&font)[3], Cell::new(63, RED, BLUE));
+    }
+
+    #[test]
+    fn compose_layers_characters_and_blends_backgrounds() {
+        let mut console = Console::new(3, 1).unwrap();
+        console.print(0, 0, "abc", RED, Vec4::new(0.0, 0.0, 0.0, 1.0));
+        let top = console.add_layer();
+        assert_eq!(console.layer(), 0);
+        assert!(console.set_layer(top));
+        assert!(!console.set_layer(2));
+
+        console.put_char(0, 0, 'x', BLUE, Vec4::ZERO);
+        console.set(
+            1,
+            0,
+            ConsoleCell {
+                character: '\0',
+                foreground: BLUE,
+                background: Vec4::new(1.0, 1.0, 1.0, 0.5),
+            },
+        );
+
+        let composed = console.compose();
+        let characters: String = composed.iter().map(|cell| cell.character).collect();
+        assert_eq!(characters, "xbc");
+        assert_eq!(composed[0].foreground, BLUE);
+        assert_eq!(composed[0].background, Vec4::new(0.0, 0.0, 0.0, 1.0));
+        assert_eq!(composed[1].foreground, RED);
+        assert_eq!(composed[1].background, Vec4::new(0.5, 0.5, 0.5, 1.0));
+        assert_eq!(composed[2].foreground, RED);
+
+        console.clear_all();
+        assert!(console.compose().iter().all(|cell| cell.character == '\0'));
+    }
+}
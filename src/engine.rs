@@ -0,0 +1,1510 @@
+use crate::camera::{Camera, OrbitController, Projection, ScreenShake, TopDownController};
+use crate::console::{self, BoxStyle, Console, ConsoleCell};
+use crate::font::{BitmapFont, Charset};
+use crate::gl::{Gl, Texture2D};
+use crate::input::{Action, Binding, Button, InputState, Keymap};
+use crate::light::{LightList, SceneLighting};
+use crate::particle::{Emitter, EmitterOptions, EmitterType, ProgramCache, Render, UpdateSystem};
+use crate::shader::{FrameData, FrameUniformBuffer};
+use crate::tilemap::{Cell, TileAtlas, Tilemap, TilemapRender};
+use crate::time::Clock;
+use crate::{create_gradient_texture, log, log_error};
+use glam::{IVec2, Vec2, Vec3, Vec4};
+use js_sys::{Array, Function, Reflect};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{EventTarget, HtmlCanvasElement, OffscreenCanvas, WebGl2RenderingContext};
+
+mod hud;
+mod input;
+mod options;
+mod resize;
+
+use hud::Hud;
+use options::EmitterDescription;
+use resize::{CanvasSize, ResizeObserver};
+
+// The closure run every animation frame
+type FrameCallback = Rc<RefCell<Option<Closure<dyn FnMut(f64)>>>>;
+
+// An event listener on the canvas, and the event it listens for
+type Listener = (&'static str, Closure<dyn FnMut(web_sys::Event)>);
+
+// A view onto a canvas that draws particle emitters, controlled from JavaScript:
+//
+//     const engine = new Engine(canvas);
+//     const fireball = engine.spawn_emitter({ gravity: [-7, 0, 0], light: true });
+//     engine.set_camera([0, 0.5, 1.5], [0, 0, 0]);
+//     engine.start();
+//     ...
+//     engine.remove_emitter(fireball);
+//     engine.dispose();
+//
+// Each engine owns its canvas' WebGL context, so a page can host as many as it likes. The canvas
+// can be an `HtmlCanvasElement` or an `OffscreenCanvas` (including in a worker). An element's
+// drawing buffer follows its displayed size and the device pixel ratio; an `OffscreenCanvas`
+// can't be observed, so its host calls `resize` instead.
+#[wasm_bindgen]
+pub struct Engine {
+    state: Rc<RefCell<State>>,
+    // Runs a frame and requests the next one. Dropped on `dispose`, which breaks the cycle through
+    // the closure's own handle to it.
+    frame: FrameCallback,
+    canvas: EventTarget,
+    // Event listeners on the canvas, removed on `dispose`
+    listeners: Vec<Listener>,
+    resize_observer: Option<ResizeObserver>,
+    on_resize: Option<Closure<dyn FnMut(Array)>>,
+}
+
+enum Canvas {
+    Element(HtmlCanvasElement),
+    Offscreen(OffscreenCanvas),
+}
+
+impl Canvas {
+    fn size(&self) -> (u32, u32) {
+        match self {
+            Canvas::Element(canvas) => (canvas.width(), canvas.height()),
+            Canvas::Offscreen(canvas) => (canvas.width(), canvas.height()),
+        }
+    }
+
+    // Resizing clears the drawing buffer, so it is only done when the size changes
+    fn set_size(&self, (width, height): (u32, u32)) {
+        if self.size() == (width, height) {
+            return;
+        }
+        match self {
+            Canvas::Element(canvas) => {
+                canvas.set_width(width);
+                canvas.set_height(height);
+            }
+            Canvas::Offscreen(canvas) => {
+                canvas.set_width(width);
+                canvas.set_height(height);
+            }
+        }
+    }
+}
+
+struct State {
+    gl: Gl,
+    canvas: Canvas,
+    // The displayed size, applied to the drawing buffer at the start of every frame
+    size: Option<CanvasSize>,
+    // Caps the device pixels rendered per CSS pixel, e.g. to 1 on low-end devices
+    max_pixel_ratio: f64,
+    // The viewport set by the last frame, if it's still current
+    viewport: Option<(i32, i32)>,
+    cache: ProgramCache,
+    emitters: Vec<(u32, EmitterInstance)>,
+    next_id: u32,
+    // The map drawn under the particles, once it and an atlas have been set
+    tilemap: Option<Tilemap>,
+    atlas: Option<AtlasInstance>,
+    tilemap_render: Option<TilemapRender>,
+    // Drawn through the tile map, which is made for it. `console_dirty` says it has changed
+    // since it was last copied into the map.
+    console: Option<Console>,
+    console_dirty: bool,
+    // Text drawn over everything in the atlas' font
+    hud: Hud,
+    camera: Camera,
+    camera_mode: CameraMode,
+    shake: ScreenShake,
+    input: InputState,
+    ambient: Vec3,
+    lights: LightList,
+    frame_uniforms: FrameUniformBuffer,
+    frame_index: u32,
+    clock: Clock,
+    // rAF timestamps, in milliseconds. `previous_time` is reset on `start` so time spent stopped
+    // isn't simulated.
+    start_time: Option<f64>,
+    previous_time: Option<f64>,
+    running: bool,
+    context_lost: bool,
+    disposed: bool,
+    // The pending `requestAnimationFrame` handle
+    animation_frame: Option<i32>,
+    #[cfg(feature = "hot-reload")]
+    library_generation: u32,
+    // Why the programs couldn't be rebuilt from the shader library the last time it changed
+    #[cfg(feature = "hot-reload")]
+    last_shader_error: Option<String>,
+}
+
+// An emitter along with everything needed to draw it and to rebuild it after a context loss
+struct EmitterInstance {
+    emitter_type: EmitterType,
+    options: EmitterOptions,
+    colors: Vec<Vec4>,
+    update: Rc<UpdateSystem>,
+    render: Rc<Render>,
+    emitter: Emitter,
+    gradient: Texture2D,
+}
+
+// A tile atlas along with what it was made from, to rebuild it after a context loss
+struct AtlasInstance {
+    atlas: TileAtlas,
+    source: AtlasSource,
+}
+
+#[derive(Clone)]
+enum AtlasSource {
+    Pixels {
+        width: u32,
+        height: u32,
+        columns: u32,
+        rows: u32,
+        pixels: Vec<u8>,
+    },
+    // Anything `Texture2D::from_image` takes
+    Image(JsValue),
+}
+
+impl AtlasInstance {
+    fn new(gl: &Gl, source: AtlasSource, font: Option<BitmapFont>) -> Result<Self, JsValue> {
+        let atlas = match (&source, font) {
+            (
+                AtlasSource::Pixels {
+                    width,
+                    height,
+                    columns,
+                    rows,
+                    pixels,
+                },
+                _,
+            ) => TileAtlas::from_rgba(gl, *width, *height, *columns, *rows, pixels)?,
+            (AtlasSource::Image(image), Some(font)) => TileAtlas::from_image(gl, image, font)?,
+            (AtlasSource::Image(_), None) => return Err("An image atlas needs a font".into()),
+        };
+        Ok(Self { atlas, source })
+    }
+
+    // The atlas rebuilt from its source, after the context has been restored
+    fn restored(&self, gl: &Gl) -> Result<Self, JsValue> {
+        Self::new(gl, self.source.clone(), Some(self.atlas.font().clone()))
+    }
+}
+
+// What moves the camera between frames
+#[derive(Debug, Copy, Clone)]
+enum CameraMode {
+    // Only moved by `set_camera`
+    Fixed,
+    // Dragged around with the mouse
+    Orbit(OrbitController),
+    TopDown(TopDownController),
+}
+
+#[wasm_bindgen]
+impl Engine {
+    // `canvas` is an `HtmlCanvasElement` or an `OffscreenCanvas`
+    #[wasm_bindgen(constructor)]
+    pub fn new(canvas: JsValue) -> Result<Engine, JsValue> {
+        let target = if let Some(canvas) = canvas.dyn_ref::<HtmlCanvasElement>() {
+            Canvas::Element(canvas.clone())
+        } else if let Some(canvas) = canvas.dyn_ref::<OffscreenCanvas>() {
+            Canvas::Offscreen(canvas.clone())
+        } else {
+            return Err("Engine needs an HTMLCanvasElement or an OffscreenCanvas".into());
+        };
+        let context = match &target {
+            Canvas::Element(canvas) => canvas.get_context("webgl2")?,
+            Canvas::Offscreen(canvas) => canvas.get_context("webgl2")?,
+        };
+        let context = context
+            .ok_or("WebGL 2 is not supported")?
+            .dyn_into::<WebGl2RenderingContext>()?;
+        let gl = Gl::new(context);
+
+        let state = Rc::new(RefCell::new(State {
+            frame_uniforms: FrameUniformBuffer::new(&gl)?,
+            gl,
+            canvas: target,
+            size: None,
+            max_pixel_ratio: f64::INFINITY,
+            viewport: None,
+            cache: ProgramCache::new(),
+            emitters: Vec::new(),
+            next_id: 1,
+            tilemap: None,
+            atlas: None,
+            tilemap_render: None,
+            console: None,
+            console_dirty: false,
+            hud: Hud::default(),
+            camera: Camera::default(),
+            camera_mode: CameraMode::Fixed,
+            shake: ScreenShake::default(),
+            input: InputState::default(),
+            ambient: Vec3::splat(0.2),
+            lights: LightList::new(),
+            frame_index: 0,
+            clock: Clock::default(),
+            start_time: None,
+            previous_time: None,
+            running: false,
+            context_lost: false,
+            disposed: false,
+            animation_frame: None,
+            #[cfg(feature = "hot-reload")]
+            library_generation: crate::shader::library_generation(),
+            #[cfg(feature = "hot-reload")]
+            last_shader_error: None,
+        }));
+
+        let frame: FrameCallback = Rc::new(RefCell::new(None));
+        let frame_state = state.clone();
+        let next_frame = frame.clone();
+        *frame.borrow_mut() = Some(Closure::wrap(Box::new(move |time: f64| {
+            let mut state = frame_state.borrow_mut();
+            state.animation_frame = None;
+            if !state.running || state.context_lost {
+                return;
+            }
+            state.frame(time);
+            state.animation_frame = request_animation_frame(next_frame.borrow().as_ref());
+        }) as Box<dyn FnMut(f64)>));
+
+        // Preventing the default action of `webglcontextlost` is what allows the context to be
+        // restored later
+        let lost_state = state.clone();
+        let on_context_lost = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            event.prevent_default();
+            let mut state = lost_state.borrow_mut();
+            state.context_lost = true;
+            if let Some(handle) = state.animation_frame.take() {
+                cancel_animation_frame(handle);
+            }
+            log("WebGL context lost");
+        }) as Box<dyn FnMut(_)>);
+
+        let restored_state = state.clone();
+        let restart = frame.clone();
+        let on_context_restored = Closure::wrap(Box::new(move |_event: web_sys::Event| {
+            let mut state = restored_state.borrow_mut();
+            if let Err(error) = state.restore() {
+                log_error(&format!("Failed to restore WebGL resources: {:?}", error));
+                return;
+            }
+            state.context_lost = false;
+            log("WebGL context restored");
+            if state.running && state.animation_frame.is_none() {
+                state.previous_time = None;
+                state.animation_frame = request_animation_frame(restart.borrow().as_ref());
+            }
+        }) as Box<dyn FnMut(_)>);
+
+        // The observer calls back once straight away with the current size
+        let (resize_observer, on_resize) = match canvas.dyn_ref::<HtmlCanvasElement>() {
+            Some(element) => {
+                let resize_state = state.clone();
+                let on_resize = Closure::wrap(Box::new(move |entries: Array| {
+                    match CanvasSize::from_entries(&entries) {
+                        Ok(Some(size)) => resize_state.borrow_mut().size = Some(size),
+                        Ok(None) => {}
+                        Err(error) => log_error(&format!("Couldn't read canvas size: {:?}", error)),
+                    }
+                }) as Box<dyn FnMut(Array)>);
+                let observer = ResizeObserver::new(on_resize.as_ref().unchecked_ref())?;
+                if observer
+                    .observe(element, &observe_options("device-pixel-content-box")?)
+                    .is_err()
+                {
+                    // Older browsers only know the content box
+                    observer.observe(element, &observe_options("content-box")?)?;
+                }
+                (Some(observer), Some(on_resize))
+            }
+            None => (None, None),
+        };
+
+        let mut listeners = vec![
+            ("webglcontextlost", on_context_lost),
+            ("webglcontextrestored", on_context_restored),
+        ];
+        listeners.extend(input::listeners(&state));
+        if let Some(element) = canvas.dyn_ref::<HtmlCanvasElement>() {
+            input::make_focusable(element)?;
+        }
+
+        let canvas: EventTarget = canvas.unchecked_into();
+        for (event, listener) in &listeners {
+            canvas.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())?;
+        }
+
+        Ok(Engine {
+            state,
+            frame,
+            canvas,
+            listeners,
+            resize_observer,
+            on_resize,
+        })
+    }
+
+    // Adds an emitter described by `options` (see `EmitterDescription`) and returns its id
+    pub fn spawn_emitter(&self, options: JsValue) -> Result<u32, JsValue> {
+        let description = EmitterDescription::parse(&options)?;
+        let mut state = self.state.borrow_mut();
+        state.check_usable()?;
+
+        let instance = state.create_instance(
+            description.emitter_type,
+            description.options,
+            description.colors,
+        )?;
+        let id = state.next_id;
+        state.next_id += 1;
+        state.emitters.push((id, instance));
+        Ok(id)
+    }
+
+    // Returns false if there's no emitter with that id
+    pub fn remove_emitter(&self, id: u32) -> bool {
+        let mut state = self.state.borrow_mut();
+        let len = state.emitters.len();
+        state.emitters.retain(|(emitter_id, _)| *emitter_id != id);
+        state.emitters.len() != len
+    }
+
+    // Stops an emitter spawning particles; the live ones finish their lives and its light fades
+    // with them. Returns false if there's no emitter with that id.
+    pub fn stop_emitter(&self, id: u32) -> bool {
+        let mut state = self.state.borrow_mut();
+        match state
+            .emitters
+            .iter_mut()
+            .find(|(emitter_id, _)| *emitter_id == id)
+        {
+            Some((_, instance)) => {
+                instance.emitter.stop_spawning();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Points the camera from `eye` at `target`, both `[x, y, z]`, and keeps it there. Passing
+    // `fov_degrees` switches to a perspective projection with that vertical field of view.
+    pub fn set_camera(
+        &self,
+        eye: &[f32],
+        target: &[f32],
+        fov_degrees: Option<f32>,
+    ) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state.camera.eye = point(eye, "eye")?;
+        state.camera.target = point(target, "target")?;
+        state.camera.up = Vec3::Y;
+        if let Some(fov_degrees) = fov_degrees {
+            state.camera.projection = Projection::Perspective {
+                fov_y_degrees: fov_degrees,
+                near: 0.01,
+            };
+        }
+        state.camera_mode = CameraMode::Fixed;
+        Ok(())
+    }
+
+    // Replaces the tile map with an empty `width` × `height` one, with cell (0, 0) at `origin`
+    // (the world origin by default). Each cell covers one tile.
+    pub fn create_tilemap(
+        &self,
+        width: u32,
+        height: u32,
+        origin: Option<Vec<f32>>,
+    ) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state.check_usable()?;
+        let mut tilemap = Tilemap::new(&state.gl, width, height)?;
+        if let Some(origin) = origin {
+            tilemap.origin = point(&origin, "origin")?;
+        }
+        state.ensure_tilemap_render()?;
+        state.tilemap = Some(tilemap);
+        state.console = None;
+        Ok(())
+    }
+
+    // Removes the console too, if the map was drawing one
+    pub fn remove_tilemap(&self) {
+        let mut state = self.state.borrow_mut();
+        state.tilemap = None;
+        state.console = None;
+    }
+
+    // Replaces the tile map with a `width` × `height` console of `layers` layers (1 by default,
+    // at most 16 and 4194304 cells in all), drawn in the atlas' font as the tile map is, so its
+    // bottom left cell is at `origin`. The console_* methods draw into it with rows counting
+    // down from the top; `set_tile` changes are lost the next time the console changes.
+    pub fn create_console(
+        &self,
+        width: u32,
+        height: u32,
+        layers: Option<u32>,
+        origin: Option<Vec<f32>>,
+    ) -> Result<(), JsValue> {
+        let layers = layers.unwrap_or(1);
+        if !(1..=console::MAX_LAYERS).contains(&layers) {
+            return Err(format!(
+                "A console has 1 to {} layers, not {}",
+                console::MAX_LAYERS,
+                layers
+            )
+            .into());
+        }
+        let cells = (u64::from(width) * u64::from(height)).saturating_mul(u64::from(layers));
+        if cells > console::MAX_CELLS {
+            return Err(format!(
+                "A {} × {} console of {} layers has more than {} cells",
+                width,
+                height,
+                layers,
+                console::MAX_CELLS
+            )
+            .into());
+        }
+        // The tile map checks the size against the texture limit before the cells are allocated
+        self.create_tilemap(width, height, origin)?;
+        let mut console = Console::new(width, height)?;
+        for _ in 1..layers {
+            console.add_layer();
+        }
+        let mut state = self.state.borrow_mut();
+        state.console = Some(console);
+        state.console_dirty = true;
+        Ok(())
+    }
+
+    // Makes the console_* methods draw into `layer`, counting from the bottom. Higher layers
+    // cover lower ones where they have characters or backgrounds. Returns false if there's no
+    // such layer.
+    pub fn console_set_layer(&self, layer: usize) -> Result<bool, JsValue> {
+        self.with_console(|console| console.set_layer(layer))
+    }
+
+    // Empties the current layer, or every layer if `all` is true
+    pub fn console_clear(&self, all: Option<bool>) -> Result<(), JsValue> {
+        self.with_console(|console| match all {
+            Some(true) => console.clear_all(),
+            _ => console.clear(),
+        })
+    }
+
+    // Colours are [r, g, b] or [r, g, b, a]. The foreground defaults to white and the background
+    // to transparent, for the console_* methods below too.
+    pub fn console_put_char(
+        &self,
+        x: i32,
+        y: i32,
+        character: char,
+        foreground: Option<Vec<f32>>,
+        background: Option<Vec<f32>>,
+    ) -> Result<(), JsValue> {
+        let (foreground, background) = console_colors(foreground, background)?;
+        self.with_console(|console| console.put_char(x, y, character, foreground, background))
+    }
+
+    // Prints `text` from (`x`, `y`), with markup like "[fg=red]orc[/fg]" changing its colours
+    // (see `Console::print`). Returns the number of rows printed on.
+    pub fn console_print(
+        &self,
+        x: i32,
+        y: i32,
+        text: &str,
+        foreground: Option<Vec<f32>>,
+        background: Option<Vec<f32>>,
+    ) -> Result<u32, JsValue> {
+        let (foreground, background) = console_colors(foreground, background)?;
+        self.with_console(|console| console.print(x, y, text, foreground, background))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn console_fill_rect(
+        &self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        character: char,
+        foreground: Option<Vec<f32>>,
+        background: Option<Vec<f32>>,
+    ) -> Result<(), JsValue> {
+        let (foreground, background) = console_colors(foreground, background)?;
+        let cell = ConsoleCell {
+            character,
+            foreground,
+            background,
+        };
+        self.with_console(|console| console.fill_rect(x, y, width, height, cell))
+    }
+
+    // Outlines a rectangle in "single" (the default) or "double" lines
+    #[allow(clippy::too_many_arguments)]
+    pub fn console_draw_box(
+        &self,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        style: Option<String>,
+        foreground: Option<Vec<f32>>,
+        background: Option<Vec<f32>>,
+    ) -> Result<(), JsValue> {
+        let style = match style.as_deref() {
+            None | Some("single") => BoxStyle::Single,
+            Some("double") => BoxStyle::Double,
+            Some(style) => return Err(format!("Unknown box style {:?}", style).into()),
+        };
+        let (foreground, background) = console_colors(foreground, background)?;
+        self.with_console(|console| {
+            console.draw_box(x, y, width, height, style, foreground, background)
+        })
+    }
+
+    fn with_console<T>(&self, f: impl FnOnce(&mut Console) -> T) -> Result<T, JsValue> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let console = state.console.as_mut().ok_or("There is no console")?;
+        state.console_dirty = true;
+        Ok(f(console))
+    }
+
+    // Sets the glyphs the tile map draws from: a `width` × `height` image of RGBA `pixels`, top
+    // row first, split into `columns` × `rows` glyphs numbered left to right and top to bottom.
+    // White glyphs are drawn in each cell's foreground colour.
+    pub fn set_tile_atlas(
+        &self,
+        width: u32,
+        height: u32,
+        columns: u32,
+        rows: u32,
+        pixels: Vec<u8>,
+    ) -> Result<(), JsValue> {
+        let source = AtlasSource::Pixels {
+            width,
+            height,
+            columns,
+            rows,
+            pixels,
+        };
+        self.set_atlas(source, None)
+    }
+
+    // Sets the glyphs from a glyph sheet the browser has decoded (an `ImageBitmap`, loaded
+    // `HTMLImageElement`, `HTMLCanvasElement` or `ImageData`), e.g. a CP437 font:
+    //
+    //     const image = await createImageBitmap(await (await fetch('cp437_16x16.png')).blob());
+    //     engine.load_glyph_sheet(image, 16, 16);
+    //
+    // The sheet is `columns` × `rows` cells of `cell_width` × `cell_height` pixels from its top
+    // left corner, by default dividing the whole image. `charset` says which characters the
+    // cells hold, in order: "cp437" (the default) or "unicode" (cell n is U+n).
+    pub fn load_glyph_sheet(
+        &self,
+        image: JsValue,
+        columns: u32,
+        rows: u32,
+        cell_width: Option<u32>,
+        cell_height: Option<u32>,
+        charset: Option<String>,
+    ) -> Result<(), JsValue> {
+        let charset = match charset.as_deref() {
+            None | Some("cp437") => Charset::Cp437,
+            Some("unicode") => Charset::Unicode,
+            Some(charset) => return Err(format!("Unknown charset {:?}", charset).into()),
+        };
+        let (width, height) = image_size(&image)?;
+        let font = BitmapFont::grid(
+            width,
+            height,
+            columns,
+            rows,
+            cell_width.unwrap_or(width / columns.max(1)),
+            cell_height.unwrap_or(height / rows.max(1)),
+            charset,
+        )?;
+        self.set_atlas(AtlasSource::Image(image), Some(font))
+    }
+
+    // Sets the glyphs from a BMFont text file (.fnt) and its page image, decoded by the browser.
+    // Tile map cells stretch each glyph over the whole cell; text is laid out with the font's
+    // metrics.
+    pub fn load_bmfont(&self, fnt: &str, image: JsValue) -> Result<(), JsValue> {
+        let font = BitmapFont::parse_fnt(fnt)?;
+        self.set_atlas(AtlasSource::Image(image), Some(font))
+    }
+
+    // The glyph index the atlas draws `character` with, e.g. for `set_tile`
+    pub fn glyph_index(&self, character: char) -> Option<u16> {
+        let state = self.state.borrow();
+        state.atlas.as_ref()?.atlas.font().index(character)
+    }
+
+    // `set_tile` with a character of the atlas' font instead of a glyph index
+    pub fn set_tile_char(
+        &self,
+        x: u32,
+        y: u32,
+        character: char,
+        foreground: &[f32],
+        background: Option<Vec<f32>>,
+    ) -> Result<bool, JsValue> {
+        let glyph = self
+            .glyph_index(character)
+            .ok_or_else(|| format!("The atlas has no glyph for {:?}", character))?;
+        self.set_tile(x, y, glyph, foreground, background)
+    }
+
+    // Shows `text` with its top left corner at (`x`, `y`) CSS pixels from the top left of the
+    // canvas, in the atlas' font. Lines are broken at '\n'. `scale` is the CSS pixels per pixel of
+    // the font (1 by default) and `color` multiplies it (white by default). Returns an id for
+    // changing or removing the text.
+    pub fn show_text(
+        &self,
+        text: String,
+        x: f32,
+        y: f32,
+        color: Option<Vec<f32>>,
+        scale: Option<f32>,
+    ) -> Result<u32, JsValue> {
+        let color = match color {
+            Some(values) => self::color(&values, "color")?,
+            None => Vec4::ONE,
+        };
+        let mut state = self.state.borrow_mut();
+        state.check_usable()?;
+        let state = &mut *state;
+        state.hud.show(
+            &state.gl,
+            text,
+            Vec2::new(x, y),
+            color,
+            scale.unwrap_or(1.0),
+        )
+    }
+
+    // Returns false if there's no text with that id
+    pub fn set_text(&self, id: u32, text: String) -> bool {
+        self.state.borrow_mut().hud.set_text(id, text)
+    }
+
+    pub fn move_text(&self, id: u32, x: f32, y: f32) -> bool {
+        self.state
+            .borrow_mut()
+            .hud
+            .set_position(id, Vec2::new(x, y))
+    }
+
+    pub fn remove_text(&self, id: u32) -> bool {
+        self.state.borrow_mut().hud.remove(id)
+    }
+
+    pub fn clear_text(&self) {
+        self.state.borrow_mut().hud.clear();
+    }
+
+    // The size `text` would be shown at, as `[width, height]` in CSS pixels
+    pub fn measure_text(&self, text: &str, scale: Option<f32>) -> Option<Vec<f32>> {
+        let state = self.state.borrow();
+        let size = state
+            .atlas
+            .as_ref()?
+            .atlas
+            .font()
+            .measure(text, scale.unwrap_or(1.0));
+        Some(size.to_array().to_vec())
+    }
+
+    fn set_atlas(&self, source: AtlasSource, font: Option<BitmapFont>) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state.check_usable()?;
+        let instance = AtlasInstance::new(&state.gl, source, font)?;
+        state.ensure_tilemap_render()?;
+        state.atlas = Some(instance);
+        // Glyph indices may have changed
+        state.console_dirty = true;
+        state.hud.invalidate();
+        Ok(())
+    }
+
+    // Sets the cell at (`x`, `y`), counting from the bottom left. Colours are [r, g, b] or
+    // [r, g, b, a]; the background defaults to transparent. Returns false if the cell is outside
+    // the map.
+    pub fn set_tile(
+        &self,
+        x: u32,
+        y: u32,
+        glyph: u16,
+        foreground: &[f32],
+        background: Option<Vec<f32>>,
+    ) -> Result<bool, JsValue> {
+        let cell = cell(glyph, foreground, background)?;
+        let mut state = self.state.borrow_mut();
+        let tilemap = state.tilemap.as_mut().ok_or("There is no tile map")?;
+        Ok(tilemap.set(x, y, cell))
+    }
+
+    // Sets every cell of the map to the same thing, e.g. to clear it
+    pub fn fill_tilemap(
+        &self,
+        glyph: u16,
+        foreground: &[f32],
+        background: Option<Vec<f32>>,
+    ) -> Result<(), JsValue> {
+        let cell = cell(glyph, foreground, background)?;
+        let mut state = self.state.borrow_mut();
+        let tilemap = state.tilemap.as_mut().ok_or("There is no tile map")?;
+        tilemap.fill(cell);
+        Ok(())
+    }
+
+    // Lets the camera be dragged around `target` with the mouse or a finger, and zoomed with the
+    // wheel
+    pub fn use_orbit_camera(&self, target: &[f32], distance: Option<f32>) -> Result<(), JsValue> {
+        let mut orbit = OrbitController {
+            target: point(target, "target")?,
+            ..OrbitController::default()
+        };
+        if let Some(distance) = distance {
+            orbit.distance = distance.clamp(orbit.min_distance, orbit.max_distance);
+        }
+        self.state.borrow_mut().camera_mode = CameraMode::Orbit(orbit);
+        Ok(())
+    }
+
+    // Looks straight down at the map from `height` tiles above it, following the tile set with
+    // `follow_tile`
+    pub fn use_top_down_camera(&self, height: f32) {
+        let mut state = self.state.borrow_mut();
+        let mut top_down = match state.camera_mode {
+            CameraMode::TopDown(top_down) => top_down,
+            _ => TopDownController::default(),
+        };
+        top_down.height = height;
+        state.camera_mode = CameraMode::TopDown(top_down);
+    }
+
+    // Moves the top-down camera to tile (`x`, `y`): smoothly, or straight away with `snap`
+    pub fn follow_tile(&self, x: i32, y: i32, snap: Option<bool>) {
+        if let CameraMode::TopDown(top_down) = &mut self.state.borrow_mut().camera_mode {
+            top_down.follow(IVec2::new(x, y));
+            if snap.unwrap_or(false) {
+                top_down.snap();
+            }
+        }
+    }
+
+    // Shakes the camera, e.g. 0.3 for a hit and 1 for an explosion. Shakes add up.
+    pub fn shake_camera(&self, trauma: f32) {
+        self.state.borrow_mut().shake.add_trauma(trauma);
+    }
+
+    // The map tile under a point on the canvas, in CSS pixels from its top left corner, as
+    // `[x, y]`
+    pub fn pick_tile(&self, x: f32, y: f32) -> Option<Vec<i32>> {
+        let state = self.state.borrow();
+        // The projection is built for the drawing buffer, which can be a different size
+        let viewport = state.buffer_size();
+        let point = Vec2::new(x, y) * viewport / state.css_size();
+        state
+            .camera
+            .screen_to_tile(point, viewport)
+            .map(|tile| tile.to_array().to_vec())
+    }
+
+    // Draws with a pixel-perfect orthographic projection where each tile is `tile_size` drawing
+    // buffer pixels across, for strictly 2D games. Best used with the top-down camera.
+    pub fn use_pixel_perfect_projection(&self, tile_size: f32) -> Result<(), JsValue> {
+        if tile_size.is_nan() || tile_size <= 0.0 {
+            return Err("Tile size must be positive".into());
+        }
+        self.state.borrow_mut().camera.projection = Projection::pixel_perfect(tile_size);
+        Ok(())
+    }
+
+    // Draws with an orthographic projection showing `height` tiles vertically
+    pub fn use_orthographic_projection(&self, height: f32) -> Result<(), JsValue> {
+        if height.is_nan() || height <= 0.0 {
+            return Err("Height must be positive".into());
+        }
+        self.state.borrow_mut().camera.projection = Projection::orthographic(height);
+        Ok(())
+    }
+
+    // Goes back to a perspective projection with a vertical field of view of `fov_degrees`
+    pub fn use_perspective_projection(&self, fov_degrees: Option<f32>) {
+        self.state.borrow_mut().camera.projection = Projection::Perspective {
+            fov_y_degrees: fov_degrees.unwrap_or(45.0),
+            near: 0.01,
+        };
+    }
+
+    // Sets how many simulation steps run per second of (scaled) time. Defaults to 60.
+    pub fn set_tick_rate(&self, tick_rate: f32) -> Result<(), JsValue> {
+        if !(tick_rate.is_finite() && tick_rate > 0.0) {
+            return Err("Tick rate must be positive".into());
+        }
+        self.state
+            .borrow_mut()
+            .clock
+            .timestep_mut()
+            .set_tick_rate(tick_rate);
+        Ok(())
+    }
+
+    // Sets how many steps a single frame may run to catch up after a hitch. Defaults to 5.
+    pub fn set_max_steps(&self, max_steps: u32) {
+        self.state
+            .borrow_mut()
+            .clock
+            .timestep_mut()
+            .set_max_steps(max_steps);
+    }
+
+    // Scales how fast the simulation runs: 1 is real time, below 1 slow motion and 0 pauses it
+    // while still drawing
+    pub fn set_time_scale(&self, time_scale: f32) {
+        self.state
+            .borrow_mut()
+            .clock
+            .timestep_mut()
+            .set_time_scale(time_scale);
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.state.borrow().clock.timestep().time_scale()
+    }
+
+    // Sets the size the canvas is displayed at, in CSS pixels. Only needed for an
+    // `OffscreenCanvas`, whose host has to pass on the size of wherever it is shown; an element
+    // is resized automatically. `device_pixel_ratio` defaults to the current one.
+    pub fn resize(&self, width: f64, height: f64, device_pixel_ratio: Option<f64>) {
+        self.state.borrow_mut().size = Some(CanvasSize {
+            width,
+            height,
+            device_pixel_ratio: device_pixel_ratio.unwrap_or_else(resize::device_pixel_ratio),
+            device_pixels: None,
+        });
+    }
+
+    // Renders at no more than `ratio` device pixels per CSS pixel, trading sharpness for speed on
+    // high density screens. Unlimited by default.
+    pub fn set_max_pixel_ratio(&self, ratio: f64) -> Result<(), JsValue> {
+        if ratio.is_nan() || ratio <= 0.0 {
+            return Err("Pixel ratio must be positive".into());
+        }
+        self.state.borrow_mut().max_pixel_ratio = ratio;
+        Ok(())
+    }
+
+    // Stops wall time from advancing the simulation, e.g. while waiting for the player's next
+    // turn. The emitters are still drawn, frozen where they are.
+    pub fn pause(&self) {
+        self.state.borrow_mut().clock.pause();
+    }
+
+    pub fn resume(&self) {
+        self.state.borrow_mut().clock.resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.borrow().clock.is_paused()
+    }
+
+    // Simulates `seconds` more over the next few frames, as quickly as it can, even while paused
+    pub fn fast_forward(&self, seconds: f32) {
+        self.state.borrow_mut().clock.fast_forward(seconds);
+    }
+
+    pub fn is_fast_forwarding(&self) -> bool {
+        self.state.borrow().clock.is_fast_forwarding()
+    }
+
+    // Immediately runs an emitter until it is in its steady state. Returns false if there's no
+    // emitter with that id.
+    pub fn settle_emitter(&self, id: u32) -> bool {
+        let state = &mut *self.state.borrow_mut();
+        let step = state.clock.timestep().step();
+        match state
+            .emitters
+            .iter_mut()
+            .find(|(emitter_id, _)| *emitter_id == id)
+        {
+            Some((_, instance)) => {
+                instance
+                    .update
+                    .settle(&state.gl, &mut instance.emitter, step);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Takes the oldest action queued by a key or gamepad press ("move_n", "wait", ... see
+    // `Action`), or undefined if there is none. Actions wait until taken, so a turn-based game
+    // can ask for one whenever it's the player's turn.
+    pub fn poll_action(&self) -> Option<String> {
+        self.state
+            .borrow_mut()
+            .input
+            .pop_action()
+            .map(|action| action.to_string())
+    }
+
+    // Drops every queued action, e.g. when a menu opens
+    pub fn clear_actions(&self) {
+        self.state.borrow_mut().input.clear_actions();
+    }
+
+    // Replaces the keymap with a preset: "vi", "numpad", "arrows" or "all" (the default)
+    pub fn use_keymap(&self, preset: &str) -> Result<(), JsValue> {
+        let keymap =
+            Keymap::preset(preset).ok_or_else(|| format!("Unknown keymap {:?}", preset))?;
+        *self.state.borrow_mut().input.keymap_mut() = keymap;
+        Ok(())
+    }
+
+    // Binds a key, by its `KeyboardEvent.code`, to an action, replacing its old binding
+    pub fn bind_key(&self, code: &str, action: &str, shift: Option<bool>) -> Result<(), JsValue> {
+        let action = action
+            .parse::<Action>()
+            .map_err(|error| error.to_string())?;
+        let binding = Binding::Key {
+            code: code.to_string(),
+            shift: shift.unwrap_or(false),
+        };
+        self.state
+            .borrow_mut()
+            .input
+            .keymap_mut()
+            .bind(binding, action);
+        Ok(())
+    }
+
+    // Returns false if the key wasn't bound
+    pub fn unbind_key(&self, code: &str, shift: Option<bool>) -> bool {
+        let binding = Binding::Key {
+            code: code.to_string(),
+            shift: shift.unwrap_or(false),
+        };
+        self.state
+            .borrow_mut()
+            .input
+            .keymap_mut()
+            .unbind(&binding)
+            .is_some()
+    }
+
+    // Binds a button of a gamepad with the standard mapping to an action
+    pub fn bind_gamepad_button(&self, button: u32, action: &str) -> Result<(), JsValue> {
+        let action = action
+            .parse::<Action>()
+            .map_err(|error| error.to_string())?;
+        self.state
+            .borrow_mut()
+            .input
+            .keymap_mut()
+            .bind(Binding::Gamepad(button), action);
+        Ok(())
+    }
+
+    pub fn unbind_gamepad_button(&self, button: u32) -> bool {
+        self.state
+            .borrow_mut()
+            .input
+            .keymap_mut()
+            .unbind(&Binding::Gamepad(button))
+            .is_some()
+    }
+
+    // Key, button and pointer state. Presses and releases show up for the frame after they
+    // happen, so they are seen exactly once by code running once per frame.
+    pub fn is_key_down(&self, code: &str) -> bool {
+        self.state.borrow().input.is_key_down(code)
+    }
+
+    pub fn was_key_pressed(&self, code: &str) -> bool {
+        self.state.borrow().input.was_key_pressed(code)
+    }
+
+    pub fn was_key_released(&self, code: &str) -> bool {
+        self.state.borrow().input.was_key_released(code)
+    }
+
+    // `button` as in `MouseEvent.button`; touches are button 0
+    pub fn is_mouse_button_down(&self, button: i16) -> bool {
+        self.state
+            .borrow()
+            .input
+            .is_button_down(Button::Pointer(button))
+    }
+
+    pub fn was_mouse_button_pressed(&self, button: i16) -> bool {
+        self.state
+            .borrow()
+            .input
+            .was_button_pressed(Button::Pointer(button))
+    }
+
+    pub fn is_gamepad_button_down(&self, button: u32) -> bool {
+        self.state
+            .borrow()
+            .input
+            .is_button_down(Button::Gamepad(button))
+    }
+
+    pub fn was_gamepad_button_pressed(&self, button: u32) -> bool {
+        self.state
+            .borrow()
+            .input
+            .was_button_pressed(Button::Gamepad(button))
+    }
+
+    // The map tile under the mouse or touch, as `[x, y]`
+    pub fn pointer_tile(&self) -> Option<Vec<i32>> {
+        let pointer = self.state.borrow().input.pointer()?;
+        self.pick_tile(pointer.x, pointer.y)
+    }
+
+    // Starts drawing every animation frame. Does nothing if already running.
+    pub fn start(&self) -> Result<(), JsValue> {
+        let mut state = self.state.borrow_mut();
+        state.check_usable()?;
+        if state.running {
+            return Ok(());
+        }
+        state.running = true;
+        state.previous_time = None;
+        if !state.context_lost {
+            state.animation_frame = request_animation_frame(self.frame.borrow().as_ref());
+        }
+        Ok(())
+    }
+
+    // Stops drawing; the emitters are kept and resume where they were on `start`
+    pub fn stop(&self) {
+        let mut state = self.state.borrow_mut();
+        state.running = false;
+        if let Some(handle) = state.animation_frame.take() {
+            cancel_animation_frame(handle);
+        }
+    }
+
+    // Why the programs couldn't be rebuilt after the last `set_shader_source`, with the compiler
+    // diagnostics mapped back to the library files, or undefined if they were
+    #[cfg(feature = "hot-reload")]
+    pub fn last_shader_error(&self) -> Option<String> {
+        self.state.borrow().last_shader_error.clone()
+    }
+
+    // Stops the engine, removes its listeners from the canvas and frees its GL objects. The
+    // engine can't be used afterwards; `free` (or dropping it) disposes it too.
+    pub fn dispose(&mut self) {
+        if self.state.borrow().disposed {
+            return;
+        }
+        self.stop();
+
+        for (event, listener) in self.listeners.drain(..) {
+            let _ = self
+                .canvas
+                .remove_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+        }
+        if let Some(observer) = self.resize_observer.take() {
+            observer.disconnect();
+        }
+        self.on_resize.take();
+        self.frame.borrow_mut().take();
+
+        let mut state = self.state.borrow_mut();
+        state.emitters.clear();
+        state.tilemap = None;
+        state.atlas = None;
+        state.tilemap_render = None;
+        state.console = None;
+        state.hud = Hud::default();
+        state.cache.clear();
+        state.disposed = true;
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.dispose();
+    }
+}
+
+impl State {
+    fn check_usable(&self) -> Result<(), JsValue> {
+        if self.disposed {
+            Err("Engine has been disposed".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn create_instance(
+        &mut self,
+        emitter_type: EmitterType,
+        options: EmitterOptions,
+        colors: Vec<Vec4>,
+    ) -> Result<EmitterInstance, JsValue> {
+        let update = self.cache.update_system(&self.gl, &emitter_type)?;
+        let render = self.cache.render(&self.gl, &emitter_type)?;
+        let emitter = update.create_emitter(&self.gl, options)?;
+        let gradient = create_gradient_texture(&self.gl, &colors)?;
+        Ok(EmitterInstance {
+            emitter_type,
+            options,
+            colors,
+            update,
+            render,
+            emitter,
+            gradient,
+        })
+    }
+
+    // Recreates every GL object after the context has been restored. Particles start over, as
+    // their state only lived on the GPU, but emitters that stopped spawning stay stopped.
+    // Everything is rebuilt before anything is replaced, so a failure leaves the engine as it
+    // was rather than half restored.
+    fn restore(&mut self) -> Result<(), JsValue> {
+        self.gl.restored();
+        let gl = &self.gl;
+        let frame_uniforms = FrameUniformBuffer::new(gl)?;
+
+        let mut cache = ProgramCache::new();
+        let mut emitters = Vec::with_capacity(self.emitters.len());
+        for (id, instance) in &self.emitters {
+            let update = cache.update_system(gl, &instance.emitter_type)?;
+            let render = cache.render(gl, &instance.emitter_type)?;
+            let emitter = update.restore_emitter(gl, &instance.emitter)?;
+            let gradient = create_gradient_texture(gl, &instance.colors)?;
+            let instance = EmitterInstance {
+                emitter_type: instance.emitter_type.clone(),
+                options: instance.options,
+                colors: instance.colors.clone(),
+                update,
+                render,
+                emitter,
+                gradient,
+            };
+            emitters.push((*id, instance));
+        }
+
+        let tilemap_render = match self.tilemap_render {
+            Some(_) => Some(TilemapRender::new(gl)?),
+            None => None,
+        };
+        let tilemap = match &self.tilemap {
+            Some(tilemap) => Some(tilemap.restored(gl)?),
+            None => None,
+        };
+        let atlas = match &self.atlas {
+            Some(instance) => Some(instance.restored(gl)?),
+            None => None,
+        };
+        let text_drawing = self.hud.restored_drawing(gl)?;
+
+        self.viewport = None;
+        self.clock.timestep_mut().reset();
+        self.frame_uniforms = frame_uniforms;
+        self.cache = cache;
+        self.emitters = emitters;
+        self.tilemap_render = tilemap_render;
+        self.tilemap = tilemap;
+        self.atlas = atlas;
+        self.hud.replace_drawing(text_drawing);
+        Ok(())
+    }
+
+    // The tile map program is only built once a map or atlas is set, so pages without one
+    // don't pay for it
+    fn ensure_tilemap_render(&mut self) -> Result<(), JsValue> {
+        if self.tilemap_render.is_none() {
+            self.tilemap_render = Some(TilemapRender::new(&self.gl)?);
+        }
+        Ok(())
+    }
+
+    // Rebuilds every program from the current shader library. If any of them fail, the old
+    // programs are kept.
+    #[cfg(feature = "hot-reload")]
+    fn reload(&mut self) -> Result<(), JsValue> {
+        let tilemap_render = match self.tilemap_render {
+            Some(_) => Some(TilemapRender::new(&self.gl)?),
+            None => None,
+        };
+        let text_render = self.hud.relink(&self.gl)?;
+        let mut cache = ProgramCache::new();
+        let mut systems = Vec::with_capacity(self.emitters.len());
+        for (_, instance) in &self.emitters {
+            systems.push((
+                cache.update_system(&self.gl, &instance.emitter_type)?,
+                cache.render(&self.gl, &instance.emitter_type)?,
+            ));
+        }
+
+        // Attribute locations are fixed by the layout, so the emitters work with the new programs
+        for ((_, instance), (update, render)) in self.emitters.iter_mut().zip(systems) {
+            instance.update = update;
+            instance.render = render;
+        }
+        self.cache = cache;
+        self.tilemap_render = tilemap_render;
+        self.hud.replace_render(text_render);
+        Ok(())
+    }
+
+    fn buffer_size(&self) -> Vec2 {
+        Vec2::new(
+            self.gl.drawing_buffer_width() as f32,
+            self.gl.drawing_buffer_height() as f32,
+        )
+        .max(Vec2::ONE)
+    }
+
+    // The displayed size of the canvas in CSS pixels, or the drawing buffer's size if unknown
+    fn css_size(&self) -> Vec2 {
+        match self.size {
+            Some(size) => Vec2::new(size.width as f32, size.height as f32),
+            None => {
+                let (width, height) = self.canvas.size();
+                Vec2::new(width as f32, height as f32)
+            }
+        }
+        .max(Vec2::ONE)
+    }
+
+    // Resizes the drawing buffer to match the displayed size and points the viewport at it. The
+    // projection follows, as it's rebuilt from the drawing buffer size every frame.
+    fn apply_size(&mut self) {
+        if let Some(size) = self.size {
+            self.canvas.set_size(size.buffer_size(self.max_pixel_ratio));
+        }
+
+        // The drawing buffer can be smaller than the canvas if it's too big for the GPU
+        let buffer_size = (
+            self.gl.drawing_buffer_width(),
+            self.gl.drawing_buffer_height(),
+        );
+        if self.viewport != Some(buffer_size) {
+            self.gl.viewport(0, 0, buffer_size.0, buffer_size.1);
+            self.viewport = Some(buffer_size);
+        }
+    }
+
+    fn frame(&mut self, time: f64) {
+        let start_time = *self.start_time.get_or_insert(time);
+        let time_delta = match self.previous_time.replace(time) {
+            Some(previous) => ((time - previous) / 1000.0) as f32,
+            None => 0.0,
+        };
+        let time = ((time - start_time) / 1000.0) as f32;
+
+        #[cfg(feature = "hot-reload")]
+        if crate::shader::library_generation() != self.library_generation {
+            self.library_generation = crate::shader::library_generation();
+            self.last_shader_error = self
+                .reload()
+                .err()
+                .map(|error| error.as_string().unwrap_or_else(|| format!("{:?}", error)));
+            if let Some(error) = &self.last_shader_error {
+                log_error(error);
+            }
+        }
+
+        input::poll_gamepads(&mut self.input);
+        self.input.begin_frame();
+
+        self.apply_size();
+        let gl = &self.gl;
+        let (width, height) = (gl.drawing_buffer_width(), gl.drawing_buffer_height());
+        gl.clear_color(0.0, 0.0, 0.0, 1.0);
+        gl.clear(
+            WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT,
+        );
+
+        // Update particles in fixed steps
+        let steps = self.clock.advance(time_delta);
+        for _ in 0..steps.count {
+            for (_, instance) in &mut self.emitters {
+                instance
+                    .update
+                    .update(gl, &mut instance.emitter, steps.step);
+            }
+        }
+
+        // Collect the dynamic lights for this frame
+        self.lights.clear();
+        for (_, instance) in &self.emitters {
+            self.lights
+                .extend(instance.emitter.point_light(&instance.colors));
+        }
+
+        // Move the camera. It runs on wall time, so it still moves while the simulation is
+        // paused.
+        match &mut self.camera_mode {
+            CameraMode::Fixed => {}
+            CameraMode::Orbit(orbit) => {
+                if self.input.is_button_down(Button::Pointer(0)) {
+                    let drag = self.input.pointer_delta();
+                    orbit.drag(drag.x, drag.y);
+                }
+                orbit.zoom(self.input.wheel_delta());
+                orbit.apply(&mut self.camera);
+            }
+            CameraMode::TopDown(top_down) => {
+                top_down.update(time_delta);
+                top_down.apply(&mut self.camera);
+            }
+        }
+        self.camera.offset = self.shake.update(time_delta);
+
+        // Upload the camera and frame data every program reads
+        let camera = &self.camera;
+        let viewport = self.buffer_size();
+        self.frame_uniforms.update(
+            gl,
+            &FrameData {
+                view: camera.view(),
+                projection: camera.projection_matrix(viewport),
+                camera_position: camera.position(),
+                viewport_size: glam::vec2(width as f32, height as f32),
+                time,
+                delta: time_delta,
+                frame_index: self.frame_index,
+                interpolation: steps.interpolation,
+            },
+        );
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        // Render the map under the particles
+        if let (Some(render), Some(tilemap), Some(instance)) =
+            (&self.tilemap_render, &mut self.tilemap, &self.atlas)
+        {
+            if let (Some(console), true) = (&self.console, self.console_dirty) {
+                match tilemap.set_cells(&console.to_cells(instance.atlas.font())) {
+                    Ok(()) => self.console_dirty = false,
+                    Err(error) => log_error(&format!("Couldn't draw the console: {:?}", error)),
+                }
+            }
+            match tilemap.flush() {
+                Ok(()) => render.render(gl, tilemap, &instance.atlas),
+                Err(error) => log_error(&format!("Couldn't upload the tile map: {:?}", error)),
+            }
+        }
+
+        // Render particles
+        let lighting = SceneLighting::new(self.ambient, self.lights.as_slice());
+        for (_, instance) in &self.emitters {
+            instance
+                .render
+                .render(gl, &instance.emitter, &instance.gradient, &lighting);
+        }
+
+        // Render text over everything
+        if let Some(instance) = &self.atlas {
+            let pixel_ratio = viewport.x / self.css_size().x;
+            self.hud.render(gl, &instance.atlas, pixel_ratio);
+        }
+    }
+}
+
+// `requestAnimationFrame` and `cancelAnimationFrame` are looked up on the global object, as they
+// exist on both `Window` and `DedicatedWorkerGlobalScope`
+fn request_animation_frame(callback: Option<&Closure<dyn FnMut(f64)>>) -> Option<i32> {
+    let callback = callback?;
+    let result = call_global("requestAnimationFrame", callback.as_ref());
+    match result {
+        Ok(handle) => handle.as_f64().map(|handle| handle as i32),
+        Err(error) => {
+            log_error(&format!("requestAnimationFrame failed: {:?}", error));
+            None
+        }
+    }
+}
+
+fn cancel_animation_frame(handle: i32) {
+    if let Err(error) = call_global("cancelAnimationFrame", &JsValue::from(handle)) {
+        log_error(&format!("cancelAnimationFrame failed: {:?}", error));
+    }
+}
+
+fn call_global(name: &str, argument: &JsValue) -> Result<JsValue, JsValue> {
+    let global = js_sys::global();
+    let function: Function = Reflect::get(&global, &JsValue::from_str(name))?.dyn_into()?;
+    function.call1(&global, argument)
+}
+
+fn point(values: &[f32], name: &str) -> Result<Vec3, JsValue> {
+    match values {
+        [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+        _ => Err(format!("{} must be [x, y, z]", name).into()),
+    }
+}
+
+// A tile map cell from colours given as [r, g, b] or [r, g, b, a]
+fn cell(glyph: u16, foreground: &[f32], background: Option<Vec<f32>>) -> Result<Cell, JsValue> {
+    let foreground = color(foreground, "foreground")?;
+    let background = match background {
+        Some(background) => color(&background, "background")?,
+        None => Vec4::ZERO,
+    };
+    Ok(Cell::new(glyph, foreground, background))
+}
+
+// Console colours, white on transparent by default
+fn console_colors(
+    foreground: Option<Vec<f32>>,
+    background: Option<Vec<f32>>,
+) -> Result<(Vec4, Vec4), JsValue> {
+    let foreground = match foreground {
+        Some(values) => color(&values, "foreground")?,
+        None => Vec4::ONE,
+    };
+    let background = match background {
+        Some(values) => color(&values, "background")?,
+        None => Vec4::ZERO,
+    };
+    Ok((foreground, background))
+}
+
+// The size of anything `Texture2D::from_image` takes
+fn image_size(image: &JsValue) -> Result<(u32, u32), JsValue> {
+    let size = |key: &str| {
+        Reflect::get(image, &key.into())
+            .ok()
+            .and_then(|value| value.as_f64())
+    };
+    // Elements report their intrinsic size separately
+    match (size("naturalWidth"), size("naturalHeight")) {
+        (Some(width), Some(height)) => Ok((width as u32, height as u32)),
+        _ => match (size("width"), size("height")) {
+            (Some(width), Some(height)) => Ok((width as u32, height as u32)),
+            _ => Err("Expected an image".into()),
+        },
+    }
+}
+
+fn color(values: &[f32], name: &str) -> Result<Vec4, JsValue> {
+    match values {
+        [r, g, b] => Ok(Vec4::new(*r, *g, *b, 1.0)),
+        [r, g, b, a] => Ok(Vec4::new(*r, *g, *b, *a)),
+        _ => Err(format!("{} must be [r, g, b] or [r, g, b, a]", name).into()),
+    }
+}
+
+fn observe_options(box_: &str) -> Result<JsValue, JsValue> {
+    let options = js_sys::Object::new();
+    Reflect::set(&options, &"box".into(), &box_.into())?;
+    Ok(options.into())
+}
@@ -0,0 +1,145 @@
+use crate::gl::Gl;
+use crate::text::{TextBatch, TextRender};
+use crate::tilemap::TileAtlas;
+use glam::{Vec2, Vec4};
+use wasm_bindgen::JsValue;
+
+// Text drawn over the scene in the atlas' font, e.g. messages and status lines
+#[derive(Default)]
+pub(super) struct Hud {
+    labels: Vec<(u32, Label)>,
+    next_id: u32,
+    // Built with the first label, so pages without text don't pay for it
+    drawing: Option<(TextRender, TextBatch)>,
+    // Whether the batch is out of date with the labels or the atlas
+    dirty: bool,
+}
+
+struct Label {
+    text: String,
+    // Top left corner, in CSS pixels from the top left of the canvas
+    position: Vec2,
+    color: Vec4,
+    // CSS pixels per pixel of the font
+    scale: f32,
+}
+
+impl Hud {
+    pub fn show(
+        &mut self,
+        gl: &Gl,
+        text: String,
+        position: Vec2,
+        color: Vec4,
+        scale: f32,
+    ) -> Result<u32, JsValue> {
+        if self.drawing.is_none() {
+            self.drawing = Some((TextRender::new(gl)?, TextBatch::new(gl)?));
+        }
+        self.next_id += 1;
+        let label = Label {
+            text,
+            position,
+            color,
+            scale,
+        };
+        self.labels.push((self.next_id, label));
+        self.dirty = true;
+        Ok(self.next_id)
+    }
+
+    pub fn set_text(&mut self, id: u32, text: String) -> bool {
+        match self.label(id) {
+            Some(label) => {
+                label.text = text;
+                self.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn set_position(&mut self, id: u32, position: Vec2) -> bool {
+        match self.label(id) {
+            Some(label) => {
+                label.position = position;
+                self.dirty = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, id: u32) -> bool {
+        let count = self.labels.len();
+        self.labels.retain(|(label_id, _)| *label_id != id);
+        self.dirty |= self.labels.len() != count;
+        self.labels.len() != count
+    }
+
+    pub fn clear(&mut self) {
+        self.labels.clear();
+        self.dirty = true;
+    }
+
+    // Relays the labels out, e.g. after the font changed
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    fn label(&mut self, id: u32) -> Option<&mut Label> {
+        self.labels
+            .iter_mut()
+            .find(|(label_id, _)| *label_id == id)
+            .map(|(_, label)| label)
+    }
+
+    // New GL objects for drawing text after the context has been restored, if text is being
+    // drawn, for `replace_drawing`
+    pub fn restored_drawing(&self, gl: &Gl) -> Result<Option<(TextRender, TextBatch)>, JsValue> {
+        match self.drawing {
+            Some(_) => Ok(Some((TextRender::new(gl)?, TextBatch::new(gl)?))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn replace_drawing(&mut self, drawing: Option<(TextRender, TextBatch)>) {
+        self.drawing = drawing;
+        self.dirty = true;
+    }
+
+    // A new text program from the current shader library, if text is being drawn, for
+    // `replace_render`
+    #[cfg(feature = "hot-reload")]
+    pub fn relink(&self, gl: &Gl) -> Result<Option<TextRender>, JsValue> {
+        self.drawing
+            .as_ref()
+            .map(|_| TextRender::new(gl))
+            .transpose()
+    }
+
+    #[cfg(feature = "hot-reload")]
+    pub fn replace_render(&mut self, new_render: Option<TextRender>) {
+        if let (Some((render, _)), Some(new_render)) = (&mut self.drawing, new_render) {
+            *render = new_render;
+        }
+    }
+
+    // `pixel_ratio` is the number of drawing buffer pixels per CSS pixel
+    pub fn render(&mut self, gl: &Gl, atlas: &TileAtlas, pixel_ratio: f32) {
+        let (render, batch) = match &mut self.drawing {
+            Some(drawing) => drawing,
+            None => return,
+        };
+        if self.dirty {
+            let font = atlas.font();
+            batch.set(self.labels.iter().flat_map(|(_, label)| {
+                font.layout(&label.text, label.position, label.scale)
+                    .into_iter()
+                    .map(move |quad| (quad, label.color))
+            }));
+            self.dirty = false;
+        }
+        render.render(gl, batch, atlas, pixel_ratio);
+    }
+}
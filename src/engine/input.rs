@@ -0,0 +1,185 @@
+use super::{CameraMode, Listener, State};
+use crate::input::{Button, Direction, InputState};
+use glam::Vec2;
+use js_sys::{Array, Function, Reflect};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, KeyboardEvent, MouseEvent, WheelEvent};
+
+// How far a stick has to be pushed along an axis to move that way
+const STICK_THRESHOLD: f32 = 0.5;
+
+// Feeds keyboard, pointer (mouse, touch and pen) and focus events on the canvas into the
+// engine's `InputState`
+pub(super) fn listeners(state: &Rc<RefCell<State>>) -> Vec<Listener> {
+    let listener = |handle: fn(&mut State, &web_sys::Event)| {
+        let state = state.clone();
+        Closure::wrap(
+            Box::new(move |event: web_sys::Event| handle(&mut state.borrow_mut(), &event))
+                as Box<dyn FnMut(_)>,
+        )
+    };
+
+    vec![
+        (
+            "keydown",
+            listener(|state, event| {
+                let event = match event.dyn_ref::<KeyboardEvent>() {
+                    Some(event) => event,
+                    None => return,
+                };
+                // Leaves browser shortcuts alone
+                if event.ctrl_key() || event.alt_key() || event.meta_key() {
+                    return;
+                }
+                if state
+                    .input
+                    .key_down(&event.code(), event.shift_key(), event.repeat())
+                {
+                    event.prevent_default();
+                }
+            }),
+        ),
+        (
+            "keyup",
+            listener(|state, event| {
+                if let Some(event) = event.dyn_ref::<KeyboardEvent>() {
+                    state.input.key_up(&event.code());
+                }
+            }),
+        ),
+        ("blur", listener(|state, _event| state.input.release_all())),
+        (
+            "pointerdown",
+            listener(|state, event| {
+                if let Some(event) = event.dyn_ref::<MouseEvent>() {
+                    pointer_moved(&mut state.input, event);
+                    state.input.button_down(Button::Pointer(event.button()));
+                    // Clicking the canvas gives it the keyboard
+                    if let Some(target) = event.current_target() {
+                        let _ = call_method(&target, "focus");
+                    }
+                }
+            }),
+        ),
+        (
+            "pointerup",
+            listener(|state, event| {
+                if let Some(event) = event.dyn_ref::<MouseEvent>() {
+                    pointer_moved(&mut state.input, event);
+                    state.input.button_up(Button::Pointer(event.button()));
+                }
+            }),
+        ),
+        (
+            "pointermove",
+            listener(|state, event| {
+                if let Some(event) = event.dyn_ref::<MouseEvent>() {
+                    pointer_moved(&mut state.input, event);
+                }
+            }),
+        ),
+        (
+            "pointerleave",
+            listener(|state, _event| state.input.pointer_left()),
+        ),
+        (
+            "pointercancel",
+            listener(|state, _event| state.input.pointer_left()),
+        ),
+        (
+            "wheel",
+            listener(|state, event| {
+                let event = match event.dyn_ref::<WheelEvent>() {
+                    Some(event) => event,
+                    None => return,
+                };
+                state.input.wheel(event.delta_y() as f32);
+                if let CameraMode::Orbit(_) = state.camera_mode {
+                    // Keeps the page from scrolling while zooming
+                    event.prevent_default();
+                }
+            }),
+        ),
+    ]
+}
+
+// Key events only go to focused elements, so the canvas is made focusable unless the page
+// already decided its tab order
+pub(super) fn make_focusable(canvas: &Element) -> Result<(), JsValue> {
+    if !canvas.has_attribute("tabindex") {
+        canvas.set_attribute("tabindex", "0")?;
+    }
+    Ok(())
+}
+
+// Pointer events have `movementX`, but not for touches everywhere, so movement is measured
+// between events instead
+fn pointer_moved(input: &mut InputState, event: &MouseEvent) {
+    let position = Vec2::new(event.offset_x() as f32, event.offset_y() as f32);
+    let delta = match input.pointer() {
+        Some(previous) => position - previous,
+        None => Vec2::ZERO,
+    };
+    input.pointer_moved(position, delta);
+}
+
+// Gamepads can't be listened to, so they're polled every frame. The buttons of every connected
+// gamepad with the standard mapping are merged, and the first left stick pushed past the
+// threshold moves in the direction it points.
+pub(super) fn poll_gamepads(input: &mut InputState) {
+    let gamepads = match get_gamepads() {
+        Some(gamepads) => gamepads,
+        None => return,
+    };
+
+    let mut down = Vec::new();
+    let mut stick = None;
+    for gamepad in gamepads.iter().filter(|gamepad| gamepad.is_object()) {
+        let standard = Reflect::get(&gamepad, &"mapping".into())
+            .ok()
+            .and_then(|mapping| mapping.as_string())
+            .is_some_and(|mapping| mapping == "standard");
+        if !standard {
+            continue;
+        }
+
+        if let Some(buttons) = array(&gamepad, "buttons") {
+            for (index, button) in buttons.iter().enumerate() {
+                let pressed = Reflect::get(&button, &"pressed".into())
+                    .ok()
+                    .and_then(|pressed| pressed.as_bool())
+                    .unwrap_or(false);
+                if pressed {
+                    down.push(index as u32);
+                }
+            }
+        }
+
+        if let (None, Some(axes)) = (stick, array(&gamepad, "axes")) {
+            let x = axes.get(0).as_f64().unwrap_or(0.0) as f32;
+            let y = axes.get(1).as_f64().unwrap_or(0.0) as f32;
+            // Down is positive
+            stick = Direction::from_stick(x, -y, STICK_THRESHOLD);
+        }
+    }
+    input.set_gamepad_buttons(down);
+    input.set_gamepad_stick(stick);
+}
+
+// `navigator.getGamepads()`, where there is such a thing (not in workers)
+fn get_gamepads() -> Option<Array> {
+    let navigator = Reflect::get(&js_sys::global(), &"navigator".into()).ok()?;
+    call_method(&navigator, "getGamepads").ok()?.dyn_into().ok()
+}
+
+fn array(value: &JsValue, key: &str) -> Option<Array> {
+    Reflect::get(value, &key.into()).ok()?.dyn_into().ok()
+}
+
+fn call_method(target: &JsValue, name: &str) -> Result<JsValue, JsValue> {
+    let function: Function = Reflect::get(target, &name.into())?.dyn_into()?;
+    function.call0(target)
+}
@@ -0,0 +1,254 @@
+use crate::particle::{
+    EmitterLight, EmitterOptions, EmitterType, ParticleLayout, PointSize, MAX_PARTICLES,
+};
+use glam::{Vec3, Vec4};
+use js_sys::{Array, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+
+// What `Engine::spawn_emitter` builds an emitter from, parsed from a JS object such as
+//
+//     {
+//         numParticles: 800,
+//         origin: [0, 0, 0],
+//         gravity: [-7, 0, 0],
+//         minAge: 0.3, maxAge: 0.9,
+//         minTheta: -Math.PI, maxTheta: Math.PI,
+//         minSpeed: 0.02, maxSpeed: 0.3,
+//         planar: false,          // keep particles on the plane z = origin.z
+//         pointSize: 7,
+//         pointSizeUnits: "pixels", // or "tiles"
+//         colors: [[1, 1, 1, 1], [1, 0.83, 0, 0.9], [0, 0, 0, 0]],
+//         light: true,            // or { intensityPerParticle: 0.005, radius: 2 }
+//         lit: false,
+//         layout: "standard",     // or "packed", for maxAge up to 16
+//         customForce: "vec3 custom_force(vec3 position, float age) { ... }",
+//         customColor: "vec4 custom_color(vec4 base, float t) { ... }",
+//     }
+//
+// Every field is optional. Numbers must be finite, `numParticles` a whole number up to
+// `MAX_PARTICLES`, ages positive and speeds not negative, and each `min…` no greater than its
+// `max…`.
+pub(super) struct EmitterDescription {
+    pub emitter_type: EmitterType,
+    pub options: EmitterOptions,
+    // The gradient the particles are coloured with over their lifetime
+    pub colors: Vec<Vec4>,
+}
+
+impl EmitterDescription {
+    pub fn parse(value: &JsValue) -> Result<Self, JsValue> {
+        if value.is_undefined() || value.is_null() {
+            return Ok(Self::default());
+        }
+
+        let layout = match string(value, "layout")?.as_deref() {
+            None | Some("standard") => ParticleLayout::standard(),
+            Some("packed") => ParticleLayout::packed(),
+            Some(other) => return Err(format!("Unknown particle layout {:?}", other).into()),
+        };
+        let mut emitter_type = EmitterType::new(layout);
+        if let Some(source) = string(value, "customForce")? {
+            emitter_type = emitter_type.with_custom_force(source);
+        }
+        if let Some(source) = string(value, "customColor")? {
+            emitter_type = emitter_type.with_custom_color(source);
+        }
+
+        let defaults = EmitterOptions::default();
+        let num_particles = match number(value, "numParticles")? {
+            Some(n) if n.fract() == 0.0 && (0.0..=MAX_PARTICLES as f32).contains(&n) => n as u32,
+            Some(_) => {
+                return Err(format!(
+                    "Emitter option \"numParticles\" must be a whole number from 0 to {}",
+                    MAX_PARTICLES
+                )
+                .into())
+            }
+            None => defaults.num_particles,
+        };
+        let (min_age, max_age) = range(
+            value,
+            "minAge",
+            "maxAge",
+            (defaults.min_age, defaults.max_age),
+        )?;
+        if min_age <= 0.0 {
+            return Err("Emitter option \"minAge\" must be positive".into());
+        }
+        let (min_speed, max_speed) = range(
+            value,
+            "minSpeed",
+            "maxSpeed",
+            (defaults.min_speed, defaults.max_speed),
+        )?;
+        if min_speed < 0.0 {
+            return Err("Emitter option \"minSpeed\" must not be negative".into());
+        }
+        let options = EmitterOptions {
+            num_particles,
+            gravity: vec3(value, "gravity")?.unwrap_or(defaults.gravity),
+            origin: vec3(value, "origin")?.unwrap_or(defaults.origin),
+            min_age,
+            max_age,
+            min_theta: number(value, "minTheta")?.unwrap_or(defaults.min_theta),
+            max_theta: number(value, "maxTheta")?.unwrap_or(defaults.max_theta),
+            min_speed,
+            max_speed,
+            planar: boolean(value, "planar")?.unwrap_or(defaults.planar),
+            point_size: point_size(value)?,
+            light: light(value)?,
+            lit: boolean(value, "lit")?.unwrap_or(defaults.lit),
+        };
+
+        let colors = match field(value, "colors")? {
+            Some(colors) => floats(&colors, "colors")?
+                .chunks(4)
+                .map(|c| match c {
+                    [r, g, b, a] => Ok(Vec4::new(*r, *g, *b, *a)),
+                    _ => Err(JsValue::from_str("Each of \"colors\" must be [r, g, b, a]")),
+                })
+                .collect::<Result<_, _>>()?,
+            None => default_colors(),
+        };
+        if colors.is_empty() || colors.len() > 256 {
+            return Err("\"colors\" must have between 1 and 256 entries".into());
+        }
+
+        Ok(Self {
+            emitter_type,
+            options,
+            colors,
+        })
+    }
+}
+
+impl Default for EmitterDescription {
+    fn default() -> Self {
+        Self {
+            emitter_type: EmitterType::default(),
+            options: EmitterOptions::default(),
+            colors: default_colors(),
+        }
+    }
+}
+
+fn default_colors() -> Vec<Vec4> {
+    vec![Vec4::ONE, Vec4::new(1.0, 1.0, 1.0, 0.0)]
+}
+
+fn point_size(value: &JsValue) -> Result<PointSize, JsValue> {
+    let size = number(value, "pointSize")?;
+    match string(value, "pointSizeUnits")?.as_deref() {
+        None | Some("pixels") => Ok(size.map_or_else(PointSize::default, PointSize::Pixels)),
+        Some("tiles") => Ok(PointSize::World(size.unwrap_or(0.5))),
+        Some(other) => Err(format!("Unknown point size units {:?}", other).into()),
+    }
+}
+
+fn light(value: &JsValue) -> Result<Option<EmitterLight>, JsValue> {
+    let light = match field(value, "light")? {
+        Some(light) => light,
+        None => return Ok(None),
+    };
+    if let Some(enabled) = light.as_bool() {
+        return Ok(enabled.then(EmitterLight::default));
+    }
+
+    let defaults = EmitterLight::default();
+    Ok(Some(EmitterLight {
+        intensity_per_particle: number(&light, "intensityPerParticle")?
+            .unwrap_or(defaults.intensity_per_particle),
+        radius: number(&light, "radius")?.unwrap_or(defaults.radius),
+    }))
+}
+
+// `None` for missing or undefined fields
+fn field(value: &JsValue, key: &str) -> Result<Option<JsValue>, JsValue> {
+    let field = Reflect::get(value, &JsValue::from_str(key))?;
+    Ok(if field.is_undefined() {
+        None
+    } else {
+        Some(field)
+    })
+}
+
+fn number(value: &JsValue, key: &str) -> Result<Option<f32>, JsValue> {
+    field(value, key)?
+        .map(|field| {
+            field
+                .as_f64()
+                .map(|n| n as f32)
+                .filter(|n| n.is_finite())
+                .ok_or_else(|| format!("Emitter option {:?} must be a finite number", key).into())
+        })
+        .transpose()
+}
+
+// A pair of options bounding a random value, where the first must be no greater than the second
+fn range(
+    value: &JsValue,
+    min_key: &str,
+    max_key: &str,
+    defaults: (f32, f32),
+) -> Result<(f32, f32), JsValue> {
+    let min = number(value, min_key)?.unwrap_or(defaults.0);
+    let max = number(value, max_key)?.unwrap_or(defaults.1);
+    if min > max {
+        return Err(format!(
+            "Emitter option {:?} ({}) must not be greater than {:?} ({})",
+            min_key, min, max_key, max
+        )
+        .into());
+    }
+    Ok((min, max))
+}
+
+fn boolean(value: &JsValue, key: &str) -> Result<Option<bool>, JsValue> {
+    field(value, key)?
+        .map(|field| {
+            field
+                .as_bool()
+                .ok_or_else(|| format!("Emitter option {:?} must be a boolean", key).into())
+        })
+        .transpose()
+}
+
+fn string(value: &JsValue, key: &str) -> Result<Option<String>, JsValue> {
+    field(value, key)?
+        .map(|field| {
+            field
+                .as_string()
+                .ok_or_else(|| format!("Emitter option {:?} must be a string", key).into())
+        })
+        .transpose()
+}
+
+fn vec3(value: &JsValue, key: &str) -> Result<Option<Vec3>, JsValue> {
+    field(value, key)?
+        .map(|field| match floats(&field, key)?.as_slice() {
+            [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+            _ => Err(format!("Emitter option {:?} must be [x, y, z]", key).into()),
+        })
+        .transpose()
+}
+
+// The numbers in an array, flattening nested arrays (for `colors`)
+fn floats(value: &JsValue, key: &str) -> Result<Vec<f32>, JsValue> {
+    let array = value
+        .dyn_ref::<Array>()
+        .ok_or_else(|| JsValue::from_str(&format!("{:?} must be an array", key)))?;
+    let mut values = Vec::new();
+    for item in array.iter() {
+        if item.is_array() {
+            values.extend(floats(&item, key)?);
+        } else {
+            values.push(
+                item.as_f64()
+                    .map(|n| n as f32)
+                    .filter(|n| n.is_finite())
+                    .ok_or_else(|| format!("{:?} must only contain finite numbers", key))?,
+            );
+        }
+    }
+    Ok(values)
+}
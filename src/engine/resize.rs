@@ -0,0 +1,100 @@
+use js_sys::{Array, Function, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::Element;
+
+// web-sys only has `ResizeObserver` behind `--cfg=web_sys_unstable_apis`, so it is bound here
+#[wasm_bindgen]
+extern "C" {
+    pub(super) type ResizeObserver;
+
+    #[wasm_bindgen(constructor, catch)]
+    pub(super) fn new(callback: &Function) -> Result<ResizeObserver, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub(super) fn observe(
+        this: &ResizeObserver,
+        target: &Element,
+        options: &JsValue,
+    ) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method)]
+    pub(super) fn disconnect(this: &ResizeObserver);
+}
+
+// The size a canvas is displayed at
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(super) struct CanvasSize {
+    // In CSS pixels
+    pub width: f64,
+    pub height: f64,
+    pub device_pixel_ratio: f64,
+    // In device pixels, when the browser reports it exactly (`devicePixelContentBoxSize`)
+    pub device_pixels: Option<(u32, u32)>,
+}
+
+impl CanvasSize {
+    // Reads the size from the last of a `ResizeObserver` callback's entries
+    pub fn from_entries(entries: &Array) -> Result<Option<Self>, JsValue> {
+        let entry = entries.get(entries.length().wrapping_sub(1));
+        if entry.is_undefined() {
+            return Ok(None);
+        }
+
+        let rect = Reflect::get(&entry, &"contentRect".into())?;
+        let width = number(&rect, "width")?;
+        let height = number(&rect, "height")?;
+
+        // Not supported everywhere (Safari), in which case the CSS size is scaled instead
+        let device_pixels = Reflect::get(&entry, &"devicePixelContentBoxSize".into())?
+            .dyn_into::<Array>()
+            .ok()
+            .map(|sizes| sizes.get(0))
+            .filter(|size| !size.is_undefined())
+            .map(|size| -> Result<_, JsValue> {
+                Ok((
+                    number(&size, "inlineSize")? as u32,
+                    number(&size, "blockSize")? as u32,
+                ))
+            })
+            .transpose()?;
+
+        Ok(Some(Self {
+            width,
+            height,
+            device_pixel_ratio: device_pixel_ratio(),
+            device_pixels,
+        }))
+    }
+
+    // The drawing buffer size to use, rendering at no more than `max_pixel_ratio` device pixels
+    // per CSS pixel
+    pub fn buffer_size(&self, max_pixel_ratio: f64) -> (u32, u32) {
+        let (width, height) = match self.device_pixels {
+            Some(device_pixels) if self.device_pixel_ratio <= max_pixel_ratio => device_pixels,
+            _ => {
+                let ratio = self.device_pixel_ratio.min(max_pixel_ratio);
+                (
+                    (self.width * ratio).round() as u32,
+                    (self.height * ratio).round() as u32,
+                )
+            }
+        };
+        (width.max(1), height.max(1))
+    }
+}
+
+// `window.devicePixelRatio`, or 1 where there's no such thing (in a worker)
+pub(super) fn device_pixel_ratio() -> f64 {
+    Reflect::get(&js_sys::global(), &"devicePixelRatio".into())
+        .ok()
+        .and_then(|ratio| ratio.as_f64())
+        .filter(|ratio| *ratio > 0.0)
+        .unwrap_or(1.0)
+}
+
+fn number(value: &JsValue, key: &str) -> Result<f64, JsValue> {
+    Reflect::get(value, &key.into())?
+        .as_f64()
+        .ok_or_else(|| format!("Expected {:?} to be a number", key).into())
+}
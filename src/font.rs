@@ -0,0 +1,422 @@
+use glam::{Vec2, Vec4};
+use std::collections::HashMap;
+use std::fmt;
+use wasm_bindgen::JsValue;
+
+mod bmfont;
+mod cp437;
+
+pub use cp437::CP437;
+
+// Where a glyph is in its font's image and how it sits on a line of text, in pixels of the image
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Glyph {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    // From the pen position to the top left corner of the glyph
+    pub x_offset: i32,
+    pub y_offset: i32,
+    // How far the pen moves after the glyph
+    pub x_advance: i32,
+}
+
+// Which characters the cells of a glyph sheet hold, numbering them left to right and top to
+// bottom
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Charset {
+    // Cell n is code n of code page 437 (see `CP437`), as in most roguelike fonts
+    Cp437,
+    // Cell n is the character U+n
+    Unicode,
+}
+
+// Tile map cells refer to glyphs with a u16
+pub const MAX_GLYPHS: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FontError {
+    Parse { line: usize, message: String },
+    // The font's glyphs are spread over several images
+    MultiplePages,
+    // More glyphs than a tile map cell can refer to
+    TooManyGlyphs(usize),
+    InvalidGrid(String),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Parse { line, message } => write!(f, "Font line {}: {}", line, message),
+            FontError::MultiplePages => write!(f, "Fonts with more than one page aren't supported"),
+            FontError::TooManyGlyphs(count) => {
+                write!(f, "Fonts can have up to 65536 glyphs, not {}", count)
+            }
+            FontError::InvalidGrid(message) => write!(f, "Invalid glyph sheet: {}", message),
+        }
+    }
+}
+
+impl From<FontError> for JsValue {
+    fn from(error: FontError) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+// The metrics of a bitmap font: where each glyph is in the font's image and how to lay out text
+// with it. Glyphs are numbered in the order they were given, which is also the glyph index tile
+// map cells use.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BitmapFont {
+    // Size of the image, in pixels
+    image_width: u32,
+    image_height: u32,
+    // Distance between lines, in pixels
+    line_height: u32,
+    // From the top of a line to the baseline
+    base: u32,
+    glyphs: Vec<Glyph>,
+    characters: HashMap<char, u16>,
+    // Extra advance between pairs of characters
+    kerning: HashMap<(char, char), i32>,
+}
+
+// A glyph placed on the screen by `BitmapFont::layout`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GlyphQuad {
+    // Top left corner and size, in the units `layout` was given
+    pub position: Vec2,
+    pub size: Vec2,
+    // Rectangle of the image to draw there, in pixels: x, y, width, height
+    pub source: Vec4,
+}
+
+impl BitmapFont {
+    // A font (or tile set) drawn on a grid of `columns` × `rows` cells of `cell_width` ×
+    // `cell_height` pixels, starting at the top left of an `image_width` × `image_height` image.
+    // Every cell is a glyph, whether or not `charset` gives it a character, so tiles can be
+    // drawn by index.
+    pub fn grid(
+        image_width: u32,
+        image_height: u32,
+        columns: u32,
+        rows: u32,
+        cell_width: u32,
+        cell_height: u32,
+        charset: Charset,
+    ) -> Result<Self, FontError> {
+        if columns == 0 || rows == 0 || cell_width == 0 || cell_height == 0 {
+            return Err(FontError::InvalidGrid(
+                "columns, rows and cell size must be positive".to_string(),
+            ));
+        }
+        let fits = |count: u32, cell_size: u32, image_size: u32| {
+            count
+                .checked_mul(cell_size)
+                .is_some_and(|size| size <= image_size)
+        };
+        if !fits(columns, cell_width, image_width) || !fits(rows, cell_height, image_height) {
+            return Err(FontError::InvalidGrid(format!(
+                "{} × {} cells of {} × {} pixels don't fit in a {} × {} image",
+                columns, rows, cell_width, cell_height, image_width, image_height
+            )));
+        }
+        // Checked before the glyphs are generated, so a sheet of tiny cells is rejected without
+        // allocating them all
+        let count = u64::from(columns) * u64::from(rows);
+        if count > MAX_GLYPHS as u64 {
+            return Err(FontError::TooManyGlyphs(
+                usize::try_from(count).unwrap_or(usize::MAX),
+            ));
+        }
+
+        let glyphs = (0..rows).flat_map(|row| {
+            (0..columns).map(move |column| Glyph {
+                x: column * cell_width,
+                y: row * cell_height,
+                width: cell_width,
+                height: cell_height,
+                x_offset: 0,
+                y_offset: 0,
+                x_advance: cell_width as i32,
+            })
+        });
+        let glyphs: Vec<(Option<char>, Glyph)> = glyphs
+            .enumerate()
+            .map(|(i, glyph)| {
+                let character = match charset {
+                    Charset::Cp437 => CP437.get(i).copied(),
+                    Charset::Unicode => u32::try_from(i).ok().and_then(char::from_u32),
+                };
+                (character, glyph)
+            })
+            .collect();
+        Self::build(image_width, image_height, cell_height, cell_height, glyphs)
+    }
+
+    // Parses a BMFont text file (.fnt) describing a single image
+    pub fn parse_fnt(text: &str) -> Result<Self, FontError> {
+        bmfont::parse(text)
+    }
+
+    fn from_glyphs(
+        image_width: u32,
+        image_height: u32,
+        line_height: u32,
+        base: u32,
+        glyphs: Vec<(char, Glyph)>,
+    ) -> Result<Self, FontError> {
+        let glyphs = glyphs
+            .into_iter()
+            .map(|(character, glyph)| (Some(character), glyph))
+            .collect();
+        Self::build(image_width, image_height, line_height, base, glyphs)
+    }
+
+    fn build(
+        image_width: u32,
+        image_height: u32,
+        line_height: u32,
+        base: u32,
+        glyphs: Vec<(Option<char>, Glyph)>,
+    ) -> Result<Self, FontError> {
+        if glyphs.len() > MAX_GLYPHS {
+            return Err(FontError::TooManyGlyphs(glyphs.len()));
+        }
+        let mut font = Self {
+            image_width,
+            image_height,
+            line_height,
+            base,
+            glyphs: Vec::with_capacity(glyphs.len()),
+            characters: HashMap::new(),
+            kerning: HashMap::new(),
+        };
+        for (i, (character, glyph)) in glyphs.into_iter().enumerate() {
+            // A character drawn by several cells (like the blank ones of CP437) keeps the first
+            if let Some(character) = character {
+                font.characters.entry(character).or_insert(i as u16);
+            }
+            font.glyphs.push(glyph);
+        }
+        Ok(font)
+    }
+
+    pub fn image_width(&self) -> u32 {
+        self.image_width
+    }
+
+    pub fn image_height(&self) -> u32 {
+        self.image_height
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.line_height
+    }
+
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    pub fn glyphs(&self) -> &[Glyph] {
+        &self.glyphs
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    // The glyph index drawing `character`
+    pub fn index(&self, character: char) -> Option<u16> {
+        self.characters.get(&character).copied()
+    }
+
+    pub fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.index(character)
+            .map(|index| &self.glyphs[usize::from(index)])
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> i32 {
+        self.kerning.get(&(first, second)).copied().unwrap_or(0)
+    }
+
+    // Characters the font lacks are drawn as '?', or skipped if it lacks that too
+    fn glyph_or_fallback(&self, character: char) -> Option<&Glyph> {
+        self.glyph(character).or_else(|| self.glyph('?'))
+    }
+
+    // Places the glyphs of `text`, with the top left corner of its first line at `origin` and
+    // every pixel of the font drawn `scale` units big. Lines are broken at '\n'.
+    pub fn layout(&self, text: &str, origin: Vec2, scale: f32) -> Vec<GlyphQuad> {
+        let mut quads = Vec::with_capacity(text.len());
+        for (line_index, line) in text.split('\n').enumerate() {
+            let mut pen = 0;
+            let mut previous = None;
+            let top = line_index as f32 * self.line_height as f32;
+            for character in line.chars() {
+                let glyph = match self.glyph_or_fallback(character) {
+                    Some(glyph) => glyph,
+                    None => continue,
+                };
+                if let Some(previous) = previous {
+                    pen += self.kerning(previous, character);
+                }
+                if glyph.width > 0 && glyph.height > 0 {
+                    let corner =
+                        Vec2::new((pen + glyph.x_offset) as f32, top + glyph.y_offset as f32);
+                    quads.push(GlyphQuad {
+                        position: origin + corner * scale,
+                        size: Vec2::new(glyph.width as f32, glyph.height as f32) * scale,
+                        source: Vec4::new(
+                            glyph.x as f32,
+                            glyph.y as f32,
+                            glyph.width as f32,
+                            glyph.height as f32,
+                        ),
+                    });
+                }
+                pen += glyph.x_advance;
+                previous = Some(character);
+            }
+        }
+        quads
+    }
+
+    // The width of the widest line of `text` and the height of all of them, at `scale`
+    pub fn measure(&self, text: &str, scale: f32) -> Vec2 {
+        let mut width = 0;
+        let mut lines = 0;
+        for line in text.split('\n') {
+            let mut pen = 0;
+            let mut previous = None;
+            for character in line.chars() {
+                if let Some(glyph) = self.glyph_or_fallback(character) {
+                    if let Some(previous) = previous {
+                        pen += self.kerning(previous, character);
+                    }
+                    pen += glyph.x_advance;
+                    previous = Some(character);
+                }
+            }
+            width = width.max(pen);
+            lines += 1;
+        }
+        Vec2::new(width as f32, (lines * self.line_height) as f32) * scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 'A' and 'V' kerned together, '?' for missing characters and a space with no pixels
+    const FONT: &str = "common lineHeight=10 base=8 scaleW=64 scaleH=32 pages=1
+char id=65 x=0 y=0 width=6 height=8 xoffset=0 yoffset=1 xadvance=7
+char id=86 x=8 y=0 width=6 height=8 xoffset=1 yoffset=1 xadvance=7
+char id=63 x=16 y=0 width=5 height=8 xoffset=0 yoffset=0 xadvance=6
+char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=4
+kerning first=65 second=86 amount=-2
+";
+
+    fn quad(position: (f32, f32), size: (f32, f32), source: [f32; 4]) -> GlyphQuad {
+        GlyphQuad {
+            position: position.into(),
+            size: size.into(),
+            source: source.into(),
+        }
+    }
+
+    #[test]
+    fn grid_numbers_cells_row_by_row() {
+        let font = BitmapFont::grid(32, 24, 4, 2, 8, 8, Charset::Cp437).unwrap();
+        assert_eq!(font.glyph_count(), 8);
+        assert_eq!((font.image_width(), font.image_height()), (32, 24));
+        let glyph = font.glyphs()[5];
+        assert_eq!((glyph.x, glyph.y, glyph.width, glyph.height), (8, 8, 8, 8));
+        assert_eq!(glyph.x_advance, 8);
+        assert_eq!(font.index(CP437[5]), Some(5));
+
+        let font = BitmapFont::grid(16, 16, 2, 2, 8, 8, Charset::Unicode).unwrap();
+        assert_eq!(font.index('\u{3}'), Some(3));
+        assert_eq!(font.index('?'), None);
+    }
+
+    #[test]
+    fn grid_rejects_bad_sizes() {
+        let invalid = |result| matches!(result, Err(FontError::InvalidGrid(_)));
+        assert!(invalid(BitmapFont::grid(
+            32,
+            32,
+            0,
+            4,
+            8,
+            8,
+            Charset::Cp437
+        )));
+        assert!(invalid(BitmapFont::grid(
+            32,
+            32,
+            5,
+            4,
+            8,
+            8,
+            Charset::Cp437
+        )));
+        assert!(invalid(BitmapFont::grid(
+            32,
+            32,
+            4,
+            5,
+            8,
+            8,
+            Charset::Cp437
+        )));
+        // the cells' total width overflows
+        assert!(invalid(BitmapFont::grid(
+            u32::MAX,
+            32,
+            u32::MAX,
+            4,
+            2,
+            8,
+            Charset::Unicode
+        )));
+        assert_eq!(
+            BitmapFont::grid(300, 300, 300, 300, 1, 1, Charset::Unicode),
+            Err(FontError::TooManyGlyphs(90000))
+        );
+    }
+
+    #[test]
+    fn layout_breaks_lines_kerns_and_falls_back() {
+        let font = BitmapFont::parse_fnt(FONT).unwrap();
+        let quads = font.layout("AV\n? x", Vec2::new(10.0, 20.0), 2.0);
+        assert_eq!(
+            quads,
+            [
+                quad((10.0, 22.0), (12.0, 16.0), [0.0, 0.0, 6.0, 8.0]),
+                // 'V' is pulled 2 pixels towards 'A'
+                quad((22.0, 22.0), (12.0, 16.0), [8.0, 0.0, 6.0, 8.0]),
+                quad((10.0, 40.0), (10.0, 16.0), [16.0, 0.0, 5.0, 8.0]),
+                // the space only advances, and 'x' is drawn as '?'
+                quad((30.0, 40.0), (10.0, 16.0), [16.0, 0.0, 5.0, 8.0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_skips_characters_without_a_fallback() {
+        let font = BitmapFont::grid(16, 8, 2, 1, 8, 8, Charset::Unicode).unwrap();
+        let quads = font.layout("x\u{1}", Vec2::ZERO, 1.0);
+        assert_eq!(quads, [quad((0.0, 0.0), (8.0, 8.0), [8.0, 0.0, 8.0, 8.0])]);
+    }
+
+    #[test]
+    fn measure_covers_the_widest_line() {
+        let font = BitmapFont::parse_fnt(FONT).unwrap();
+        assert_eq!(font.measure("AV", 1.0), Vec2::new(12.0, 10.0));
+        assert_eq!(font.measure("A\n? x\n", 0.5), Vec2::new(8.0, 15.0));
+        assert_eq!(font.measure("", 1.0), Vec2::new(0.0, 10.0));
+    }
+}
@@ -0,0 +1,198 @@
+use super::{BitmapFont, FontError, Glyph};
+use std::collections::HashMap;
+
+// Reads the text flavour of AngelCode's BMFont format (.fnt):
+//
+//     common lineHeight=32 base=26 scaleW=256 scaleH=256 pages=1
+//     page id=0 file="font.png"
+//     char id=65 x=2 y=4 width=18 height=20 xoffset=0 yoffset=6 xadvance=19 page=0 chnl=15
+//     kerning first=65 second=86 amount=-2
+//
+// Only single page fonts are supported, as the atlas is a single texture.
+pub(super) fn parse(text: &str) -> Result<BitmapFont, FontError> {
+    let mut common = None;
+    let mut glyphs = Vec::new();
+    let mut kerning = HashMap::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let mut tokens = tokenize(line);
+        let tag = match tokens.next() {
+            Some(tag) => tag,
+            None => continue,
+        };
+        let fields: HashMap<&str, &str> = tokens
+            .filter_map(|token| token.split_once('='))
+            .map(|(key, value)| (key, value.trim_matches('"')))
+            .collect();
+        let number = |key: &str| -> Result<i32, FontError> {
+            fields
+                .get(key)
+                .ok_or_else(|| FontError::Parse {
+                    line: line_number,
+                    message: format!("\"{}\" is missing {:?}", tag, key),
+                })?
+                .parse()
+                .map_err(|_| FontError::Parse {
+                    line: line_number,
+                    message: format!("{:?} is not a number", key),
+                })
+        };
+
+        match tag {
+            "common" => {
+                if fields.get("pages").is_some_and(|pages| *pages != "1") {
+                    return Err(FontError::MultiplePages);
+                }
+                common = Some((
+                    number("lineHeight")?,
+                    number("base")?,
+                    number("scaleW")?,
+                    number("scaleH")?,
+                ));
+            }
+            "char" => {
+                let id = number("id")?;
+                let character =
+                    u32::try_from(id)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| FontError::Parse {
+                            line: line_number,
+                            message: format!("{} is not a character", id),
+                        })?;
+                let glyph = Glyph {
+                    x: number("x")?.max(0) as u32,
+                    y: number("y")?.max(0) as u32,
+                    width: number("width")?.max(0) as u32,
+                    height: number("height")?.max(0) as u32,
+                    x_offset: number("xoffset")?,
+                    y_offset: number("yoffset")?,
+                    x_advance: number("xadvance")?,
+                };
+                glyphs.push((character, glyph));
+            }
+            "kerning" => {
+                let pair = (number("first")?, number("second")?);
+                if let (Some(first), Some(second)) = (
+                    u32::try_from(pair.0).ok().and_then(char::from_u32),
+                    u32::try_from(pair.1).ok().and_then(char::from_u32),
+                ) {
+                    kerning.insert((first, second), number("amount")?);
+                }
+            }
+            // "info", "page", "chars" and "kernings" have nothing we need
+            _ => {}
+        }
+    }
+
+    let (line_height, base, width, height) = common.ok_or(FontError::Parse {
+        line: 1,
+        message: "missing \"common\" line".to_string(),
+    })?;
+    let mut font = BitmapFont::from_glyphs(
+        width.max(0) as u32,
+        height.max(0) as u32,
+        line_height.max(0) as u32,
+        base.max(0) as u32,
+        glyphs,
+    )?;
+    font.kerning = kerning;
+    Ok(font)
+}
+
+// Splits a line on whitespace outside of double quotes
+fn tokenize(line: &str) -> impl Iterator<Item = &str> {
+    let mut rest = line.trim();
+    std::iter::from_fn(move || {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let mut quoted = false;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c.is_whitespace() && !quoted
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let (token, remainder) = rest.split_at(end);
+        rest = remainder;
+        Some(token)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FONT: &str = r#"info face="Test Font" size=8 bold=0
+common lineHeight=10 base=8 scaleW=64 scaleH=32 pages=1 packed=0
+page id=0 file="test font.png"
+chars count=2
+char id=65   x=0  y=0 width=6 height=8 xoffset=0 yoffset=1 xadvance=7 page=0 chnl=15
+char id=86   x=8  y=0 width=6 height=8 xoffset=1 yoffset=1 xadvance=7 page=0 chnl=15
+kernings count=1
+kerning first=65 second=86 amount=-2
+"#;
+
+    #[test]
+    fn tokenize_keeps_quoted_spaces() {
+        let tokens: Vec<&str> = tokenize(r#"  page id=0 file="test font.png"   "#).collect();
+        assert_eq!(tokens, ["page", "id=0", r#"file="test font.png""#]);
+        assert_eq!(tokenize("   ").count(), 0);
+    }
+
+    #[test]
+    fn parses_common_chars_and_kerning() {
+        let font = parse(FONT).unwrap();
+        assert_eq!((font.image_width(), font.image_height()), (64, 32));
+        assert_eq!((font.line_height(), font.base()), (10, 8));
+        assert_eq!(font.glyph_count(), 2);
+        assert_eq!(font.index('V'), Some(1));
+        assert_eq!(
+            font.glyph('V'),
+            Some(&Glyph {
+                x: 8,
+                y: 0,
+                width: 6,
+                height: 8,
+                x_offset: 1,
+                y_offset: 1,
+                x_advance: 7,
+            })
+        );
+        assert_eq!(font.kerning('A', 'V'), -2);
+        assert_eq!(font.kerning('V', 'A'), 0);
+    }
+
+    #[test]
+    fn rejects_multiple_pages() {
+        let text = FONT.replace("pages=1", "pages=2");
+        assert_eq!(parse(&text), Err(FontError::MultiplePages));
+    }
+
+    #[test]
+    fn reports_the_line_of_bad_fields() {
+        let text = FONT.replace("xadvance=7", "xadvance=seven");
+        assert_eq!(
+            parse(&text),
+            Err(FontError::Parse {
+                line: 5,
+                message: "\"xadvance\" is not a number".to_string(),
+            })
+        );
+        let text = FONT.replace("char id=86 ", "char ");
+        assert!(matches!(
+            parse(&text),
+            Err(FontError::Parse { line: 6, .. })
+        ));
+        assert!(matches!(
+            parse("char id=65 x=0 y=0 width=1 height=1 xoffset=0 yoffset=0 xadvance=1"),
+            Err(FontError::Parse { line: 1, .. })
+        ));
+    }
+}
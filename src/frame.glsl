@@ -0,0 +1,19 @@
+/* Camera and frame data shared by every program, uploaded once per frame by
+   `FrameUniformBuffer`. The layout must match `FrameData::to_std140`.
+   Members are highp so the block matches between stages whatever default
+   precision the including shader sets. */
+layout(std140) uniform FrameData {
+  highp mat4 u_View;
+  highp mat4 u_Projection;
+  highp mat4 u_ViewProjection;
+  highp vec3 u_CameraPosition;
+  /* Size of the drawing buffer, in pixels */
+  highp vec2 u_ViewportSize;
+  /* Seconds since the first frame */
+  highp float u_Time;
+  /* Seconds since the previous frame */
+  highp float u_FrameDelta;
+  highp uint u_FrameIndex;
+  /* How far this frame is between the last two simulation steps, from 0 to 1 */
+  highp float u_Interpolation;
+};
@@ -0,0 +1,328 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::rc::Rc;
+use web_sys::{
+    WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlTexture, WebGlVertexArrayObject,
+};
+
+mod resource;
+
+pub use resource::{
+    live_resources, Buffer, BufferElement, Program, ResourceCounts, Texture2D, VertexArray,
+};
+
+// A `WebGl2RenderingContext` that remembers the state it has set: the current program, vertex
+// array, buffer and texture bindings, enabled capabilities and blend function. The methods below
+// shadow the context's own and skip calls that wouldn't change anything; everything else is
+// reached through `Deref`.
+//
+// Changing tracked state through the raw context (or a library that holds it) leaves the cache
+// out of date; call `invalidate` afterwards.
+//
+// Clones share the context and the tracked state, so resources can keep a handle to it.
+#[derive(Debug, Clone)]
+pub struct Gl {
+    inner: Rc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    context: WebGl2RenderingContext,
+    state: RefCell<State>,
+    // Bumped every time the context is restored, invalidating the objects created before
+    generation: Cell<u32>,
+}
+
+// The tracked state. `None` (or a missing map entry) means unknown, so the next call goes through.
+// Texture bindings start out unknown, as there's one per unit.
+#[derive(Debug, Clone, Default)]
+struct State {
+    program: Option<Option<WebGlProgram>>,
+    vertex_array: Option<Option<WebGlVertexArrayObject>>,
+    // Generic bind points (ARRAY_BUFFER, UNIFORM_BUFFER, ...). ELEMENT_ARRAY_BUFFER is part of
+    // the vertex array's state and isn't tracked.
+    buffers: HashMap<u32, Option<WebGlBuffer>>,
+    active_texture: Option<u32>,
+    // Keyed by (texture unit, target)
+    textures: HashMap<(u32, u32), Option<WebGlTexture>>,
+    capabilities: HashMap<u32, bool>,
+    blend_func: Option<(u32, u32)>,
+}
+
+impl State {
+    // The state of a context nothing has been done with yet
+    fn initial() -> Self {
+        type Context = WebGl2RenderingContext;
+
+        let buffers = [
+            Context::ARRAY_BUFFER,
+            Context::UNIFORM_BUFFER,
+            Context::TRANSFORM_FEEDBACK_BUFFER,
+            Context::COPY_READ_BUFFER,
+            Context::COPY_WRITE_BUFFER,
+            Context::PIXEL_PACK_BUFFER,
+            Context::PIXEL_UNPACK_BUFFER,
+        ];
+        let capabilities = [
+            (Context::BLEND, false),
+            (Context::CULL_FACE, false),
+            (Context::DEPTH_TEST, false),
+            (Context::DITHER, true),
+            (Context::POLYGON_OFFSET_FILL, false),
+            (Context::RASTERIZER_DISCARD, false),
+            (Context::SAMPLE_ALPHA_TO_COVERAGE, false),
+            (Context::SAMPLE_COVERAGE, false),
+            (Context::SCISSOR_TEST, false),
+            (Context::STENCIL_TEST, false),
+        ];
+
+        Self {
+            program: Some(None),
+            vertex_array: Some(None),
+            buffers: buffers.into_iter().map(|target| (target, None)).collect(),
+            active_texture: Some(Context::TEXTURE0),
+            textures: HashMap::new(),
+            capabilities: capabilities.into_iter().collect(),
+            blend_func: Some((Context::ONE, Context::ZERO)),
+        }
+    }
+}
+
+impl Gl {
+    // `context` is assumed to be fresh. If it has already been used, call `invalidate` after
+    // wrapping it.
+    pub fn new(context: WebGl2RenderingContext) -> Self {
+        Self {
+            inner: Rc::new(Inner {
+                context,
+                state: RefCell::new(State::initial()),
+                generation: Cell::new(0),
+            }),
+        }
+    }
+
+    pub fn context(&self) -> &WebGl2RenderingContext {
+        &self.inner.context
+    }
+
+    // Forgets the tracked state, e.g. after handing the context to code that doesn't use `Gl`
+    pub fn invalidate(&self) {
+        *self.inner.state.borrow_mut() = State::default();
+    }
+
+    // Resets the tracked state after `webglcontextrestored`: the restored context starts out
+    // fresh, like a new one
+    pub fn restored(&self) {
+        *self.inner.state.borrow_mut() = State::initial();
+        self.inner.generation.set(self.inner.generation.get() + 1);
+    }
+
+    // Objects from an earlier generation belong to a lost context and can't be deleted
+    pub(crate) fn generation(&self) -> u32 {
+        self.inner.generation.get()
+    }
+
+    // The largest width or height a texture can have. WebGL 2 guarantees at least 2048.
+    pub fn max_texture_size(&self) -> u32 {
+        self.inner
+            .context
+            .get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)
+            .ok()
+            .and_then(|size| size.as_f64())
+            .map_or(2048, |size| size as u32)
+    }
+
+    // Snapshots the tracked state; it is restored when the guard is dropped. State that was
+    // unknown when the snapshot was taken is left as it is.
+    pub fn save_state(&self) -> StateGuard<'_> {
+        StateGuard {
+            gl: self,
+            saved: self.inner.state.borrow().clone(),
+        }
+    }
+
+    pub fn use_program(&self, program: Option<&WebGlProgram>) {
+        let program = program.cloned();
+        let mut state = self.inner.state.borrow_mut();
+        if state.program.as_ref() != Some(&program) {
+            self.inner.context.use_program(program.as_ref());
+            state.program = Some(program);
+        }
+    }
+
+    pub fn bind_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+        let vertex_array = vertex_array.cloned();
+        let mut state = self.inner.state.borrow_mut();
+        if state.vertex_array.as_ref() != Some(&vertex_array) {
+            self.inner.context.bind_vertex_array(vertex_array.as_ref());
+            state.vertex_array = Some(vertex_array);
+        }
+    }
+
+    pub fn bind_buffer(&self, target: u32, buffer: Option<&WebGlBuffer>) {
+        if target == WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER {
+            self.inner.context.bind_buffer(target, buffer);
+            return;
+        }
+        let buffer = buffer.cloned();
+        let mut state = self.inner.state.borrow_mut();
+        if state.buffers.get(&target) != Some(&buffer) {
+            self.inner.context.bind_buffer(target, buffer.as_ref());
+            state.buffers.insert(target, buffer);
+        }
+    }
+
+    // Indexed bindings aren't tracked, so this always goes through. It also binds the buffer to
+    // the generic bind point, which is recorded.
+    pub fn bind_buffer_base(&self, target: u32, index: u32, buffer: Option<&WebGlBuffer>) {
+        self.inner.context.bind_buffer_base(target, index, buffer);
+        self.inner
+            .state
+            .borrow_mut()
+            .buffers
+            .insert(target, buffer.cloned());
+    }
+
+    pub fn active_texture(&self, unit: u32) {
+        let mut state = self.inner.state.borrow_mut();
+        if state.active_texture != Some(unit) {
+            self.inner.context.active_texture(unit);
+            state.active_texture = Some(unit);
+        }
+    }
+
+    pub fn bind_texture(&self, target: u32, texture: Option<&WebGlTexture>) {
+        let texture = texture.cloned();
+        let mut state = self.inner.state.borrow_mut();
+        let unit = match state.active_texture {
+            Some(unit) => unit,
+            None => {
+                // The active unit is unknown, so the binding can't be recorded against it
+                self.inner.context.bind_texture(target, texture.as_ref());
+                return;
+            }
+        };
+        if state.textures.get(&(unit, target)) != Some(&texture) {
+            self.inner.context.bind_texture(target, texture.as_ref());
+            state.textures.insert((unit, target), texture);
+        }
+    }
+
+    pub fn enable(&self, capability: u32) {
+        self.set_capability(capability, true);
+    }
+
+    pub fn disable(&self, capability: u32) {
+        self.set_capability(capability, false);
+    }
+
+    fn set_capability(&self, capability: u32, enabled: bool) {
+        let mut state = self.inner.state.borrow_mut();
+        if state.capabilities.get(&capability) != Some(&enabled) {
+            if enabled {
+                self.inner.context.enable(capability);
+            } else {
+                self.inner.context.disable(capability);
+            }
+            state.capabilities.insert(capability, enabled);
+        }
+    }
+
+    pub fn blend_func(&self, source: u32, destination: u32) {
+        let mut state = self.inner.state.borrow_mut();
+        if state.blend_func != Some((source, destination)) {
+            self.inner.context.blend_func(source, destination);
+            state.blend_func = Some((source, destination));
+        }
+    }
+
+    // Deleting a bound object unbinds it, which the cache has to know about
+    pub fn delete_program(&self, program: Option<&WebGlProgram>) {
+        if let Some(program) = program {
+            let mut state = self.inner.state.borrow_mut();
+            if state.program.as_ref() == Some(&Some(program.clone())) {
+                state.program = Some(None);
+            }
+        }
+        self.inner.context.delete_program(program);
+    }
+
+    pub fn delete_vertex_array(&self, vertex_array: Option<&WebGlVertexArrayObject>) {
+        if let Some(vertex_array) = vertex_array {
+            let mut state = self.inner.state.borrow_mut();
+            if state.vertex_array.as_ref() == Some(&Some(vertex_array.clone())) {
+                state.vertex_array = Some(None);
+            }
+        }
+        self.inner.context.delete_vertex_array(vertex_array);
+    }
+
+    pub fn delete_buffer(&self, buffer: Option<&WebGlBuffer>) {
+        if let Some(buffer) = buffer {
+            for binding in self.inner.state.borrow_mut().buffers.values_mut() {
+                if binding.as_ref() == Some(buffer) {
+                    *binding = None;
+                }
+            }
+        }
+        self.inner.context.delete_buffer(buffer);
+    }
+
+    pub fn delete_texture(&self, texture: Option<&WebGlTexture>) {
+        if let Some(texture) = texture {
+            for binding in self.inner.state.borrow_mut().textures.values_mut() {
+                if binding.as_ref() == Some(texture) {
+                    *binding = None;
+                }
+            }
+        }
+        self.inner.context.delete_texture(texture);
+    }
+
+    fn restore(&self, saved: &State) {
+        if let Some(program) = &saved.program {
+            self.use_program(program.as_ref());
+        }
+        if let Some(vertex_array) = &saved.vertex_array {
+            self.bind_vertex_array(vertex_array.as_ref());
+        }
+        for (&target, buffer) in &saved.buffers {
+            self.bind_buffer(target, buffer.as_ref());
+        }
+        for (&(unit, target), texture) in &saved.textures {
+            self.active_texture(unit);
+            self.bind_texture(target, texture.as_ref());
+        }
+        if let Some(unit) = saved.active_texture {
+            self.active_texture(unit);
+        }
+        for (&capability, &enabled) in &saved.capabilities {
+            self.set_capability(capability, enabled);
+        }
+        if let Some((source, destination)) = saved.blend_func {
+            self.blend_func(source, destination);
+        }
+    }
+}
+
+impl Deref for Gl {
+    type Target = WebGl2RenderingContext;
+
+    fn deref(&self) -> &WebGl2RenderingContext {
+        &self.inner.context
+    }
+}
+
+// Restores the state saved by `Gl::save_state` when dropped
+#[must_use = "the state is restored as soon as the guard is dropped"]
+pub struct StateGuard<'a> {
+    gl: &'a Gl,
+    saved: State,
+}
+
+impl Drop for StateGuard<'_> {
+    fn drop(&mut self) {
+        self.gl.restore(&self.saved);
+    }
+}
@@ -0,0 +1,452 @@
+use super::Gl;
+use crate::shader::{ShaderError, ShaderSource};
+use crate::{compile_shader, link_program};
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    HtmlCanvasElement, HtmlImageElement, ImageBitmap, ImageData, WebGl2RenderingContext,
+    WebGlBuffer, WebGlProgram, WebGlTexture, WebGlVertexArrayObject,
+};
+
+// The number of each kind of GL object currently alive, for spotting leaks
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ResourceCounts {
+    pub buffers: usize,
+    pub textures: usize,
+    pub vertex_arrays: usize,
+    pub programs: usize,
+}
+
+impl fmt::Display for ResourceCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} buffers, {} textures, {} vertex arrays, {} programs",
+            self.buffers, self.textures, self.vertex_arrays, self.programs
+        )
+    }
+}
+
+thread_local! {
+    static LIVE: Cell<ResourceCounts> = const {
+        Cell::new(ResourceCounts {
+            buffers: 0,
+            textures: 0,
+            vertex_arrays: 0,
+            programs: 0,
+        })
+    };
+}
+
+pub fn live_resources() -> ResourceCounts {
+    LIVE.with(Cell::get)
+}
+
+fn count(update: impl FnOnce(&mut ResourceCounts)) {
+    LIVE.with(|live| {
+        let mut counts = live.get();
+        update(&mut counts);
+        live.set(counts);
+    });
+}
+
+// A plain value that can be stored in a `Buffer`
+pub trait BufferElement: Copy {
+    fn to_bytes(data: &[Self]) -> Vec<u8>;
+}
+
+macro_rules! buffer_element {
+    ($($ty:ty),*) => {
+        $(impl BufferElement for $ty {
+            fn to_bytes(data: &[Self]) -> Vec<u8> {
+                data.iter().flat_map(|value| value.to_le_bytes()).collect()
+            }
+        })*
+    };
+}
+
+buffer_element!(u8, u16, u32, i32, f32);
+
+// Each wrapper deletes its object when dropped, unless the context has been lost and restored
+// since it was created.
+
+// A buffer of `T`s, deleted when dropped
+#[derive(Debug)]
+pub struct Buffer<T> {
+    gl: Gl,
+    generation: u32,
+    buffer: WebGlBuffer,
+    target: u32,
+    len: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T: BufferElement> Buffer<T> {
+    // An empty buffer that will be bound to `target`
+    pub fn new(gl: &Gl, target: u32) -> Result<Self, JsValue> {
+        let buffer = gl.create_buffer().ok_or("Could not create buffer")?;
+        count(|counts| counts.buffers += 1);
+        Ok(Self {
+            gl: gl.clone(),
+            generation: gl.generation(),
+            buffer,
+            target,
+            len: 0,
+            marker: PhantomData,
+        })
+    }
+
+    pub fn with_data(gl: &Gl, target: u32, data: &[T], usage: u32) -> Result<Self, JsValue> {
+        let mut buffer = Self::new(gl, target)?;
+        buffer.upload(data, usage);
+        Ok(buffer)
+    }
+
+    // An uninitialized buffer with room for `len` elements
+    pub fn with_len(gl: &Gl, target: u32, len: usize, usage: u32) -> Result<Self, JsValue> {
+        let mut buffer = Self::new(gl, target)?;
+        buffer.bind();
+        gl.buffer_data_with_i32(target, (len * std::mem::size_of::<T>()) as i32, usage);
+        buffer.len = len;
+        Ok(buffer)
+    }
+
+    // Replaces the contents of the buffer, resizing it to fit `data`. Leaves it bound.
+    pub fn upload(&mut self, data: &[T], usage: u32) {
+        self.bind();
+        self.gl
+            .buffer_data_with_u8_array(self.target, &T::to_bytes(data), usage);
+        self.len = data.len();
+    }
+
+    // Overwrites part of the buffer, starting at element `offset`. Leaves it bound.
+    pub fn update(&self, offset: usize, data: &[T]) {
+        assert!(
+            offset + data.len() <= self.len,
+            "buffer update out of range"
+        );
+        self.bind();
+        self.gl.buffer_sub_data_with_i32_and_u8_array(
+            self.target,
+            (offset * std::mem::size_of::<T>()) as i32,
+            &T::to_bytes(data),
+        );
+    }
+
+    pub fn bind(&self) {
+        self.gl.bind_buffer(self.target, Some(&self.buffer));
+    }
+}
+
+impl<T> Buffer<T> {
+    pub fn raw(&self) -> &WebGlBuffer {
+        &self.buffer
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    // Number of elements
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        self.len * std::mem::size_of::<T>()
+    }
+}
+
+impl<T> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        if self.generation == self.gl.generation() {
+            self.gl.delete_buffer(Some(&self.buffer));
+        }
+        count(|counts| counts.buffers -= 1);
+    }
+}
+
+// A 2D texture, deleted when dropped
+#[derive(Debug)]
+pub struct Texture2D {
+    gl: Gl,
+    generation: u32,
+    texture: WebGlTexture,
+    width: i32,
+    height: i32,
+    internal_format: u32,
+}
+
+impl Texture2D {
+    // Creates a texture and uploads `data` (or leaves it uninitialized). The texture is left
+    // bound to the active unit.
+    pub fn new(
+        gl: &Gl,
+        width: i32,
+        height: i32,
+        internal_format: u32,
+        format: u32,
+        type_: u32,
+        data: Option<&[u8]>,
+    ) -> Result<Self, JsValue> {
+        let texture = gl.create_texture().ok_or("Could not create texture")?;
+        count(|counts| counts.textures += 1);
+        let texture = Self {
+            gl: gl.clone(),
+            generation: gl.generation(),
+            texture,
+            width,
+            height,
+            internal_format,
+        };
+
+        texture.bind();
+        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            internal_format as i32,
+            width,
+            height,
+            0,
+            format,
+            type_,
+            data,
+        )?;
+        Ok(texture)
+    }
+
+    // Creates an RGBA8 texture from an `ImageBitmap`, `HTMLImageElement` (which must have
+    // loaded), `HTMLCanvasElement` or `ImageData`, e.g. a PNG decoded by the browser. The texture
+    // is left bound to the active unit.
+    pub fn from_image(gl: &Gl, image: &JsValue) -> Result<Self, JsValue> {
+        type Context = WebGl2RenderingContext;
+        let (width, height) = if let Some(image) = image.dyn_ref::<ImageBitmap>() {
+            (image.width(), image.height())
+        } else if let Some(image) = image.dyn_ref::<HtmlImageElement>() {
+            (image.natural_width(), image.natural_height())
+        } else if let Some(image) = image.dyn_ref::<HtmlCanvasElement>() {
+            (image.width(), image.height())
+        } else if let Some(image) = image.dyn_ref::<ImageData>() {
+            (image.width(), image.height())
+        } else {
+            return Err(
+                "Expected an ImageBitmap, HTMLImageElement, HTMLCanvasElement or ImageData".into(),
+            );
+        };
+        if width == 0 || height == 0 {
+            return Err("Image is empty or hasn't loaded".into());
+        }
+
+        let texture = Self::new(
+            gl,
+            width as i32,
+            height as i32,
+            Context::RGBA8,
+            Context::RGBA,
+            Context::UNSIGNED_BYTE,
+            None,
+        )?;
+        let (target, format, type_) = (Context::TEXTURE_2D, Context::RGBA, Context::UNSIGNED_BYTE);
+        if let Some(image) = image.dyn_ref::<ImageBitmap>() {
+            gl.tex_sub_image_2d_with_u32_and_u32_and_image_bitmap(
+                target, 0, 0, 0, format, type_, image,
+            )?;
+        } else if let Some(image) = image.dyn_ref::<HtmlImageElement>() {
+            gl.tex_sub_image_2d_with_u32_and_u32_and_html_image_element(
+                target, 0, 0, 0, format, type_, image,
+            )?;
+        } else if let Some(image) = image.dyn_ref::<HtmlCanvasElement>() {
+            gl.tex_sub_image_2d_with_u32_and_u32_and_html_canvas_element(
+                target, 0, 0, 0, format, type_, image,
+            )?;
+        } else if let Some(image) = image.dyn_ref::<ImageData>() {
+            gl.tex_sub_image_2d_with_u32_and_u32_and_image_data(
+                target, 0, 0, 0, format, type_, image,
+            )?;
+        }
+        Ok(texture)
+    }
+
+    // Overwrites a `width` × `height` region starting at (`x`, `y`). Leaves the texture bound.
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        format: u32,
+        type_: u32,
+        data: &[u8],
+    ) -> Result<(), JsValue> {
+        self.bind();
+        self.gl
+            .tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+                WebGl2RenderingContext::TEXTURE_2D,
+                0,
+                x,
+                y,
+                width,
+                height,
+                format,
+                type_,
+                Some(data),
+            )
+    }
+
+    // Binds the texture to the active unit
+    pub fn bind(&self) {
+        self.gl
+            .bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture));
+    }
+
+    pub fn set_filter(&self, min: u32, mag: u32) {
+        self.bind();
+        self.parameter(WebGl2RenderingContext::TEXTURE_MIN_FILTER, min);
+        self.parameter(WebGl2RenderingContext::TEXTURE_MAG_FILTER, mag);
+    }
+
+    pub fn set_wrap(&self, s: u32, t: u32) {
+        self.bind();
+        self.parameter(WebGl2RenderingContext::TEXTURE_WRAP_S, s);
+        self.parameter(WebGl2RenderingContext::TEXTURE_WRAP_T, t);
+    }
+
+    fn parameter(&self, name: u32, value: u32) {
+        self.gl
+            .tex_parameteri(WebGl2RenderingContext::TEXTURE_2D, name, value as i32);
+    }
+
+    pub fn raw(&self) -> &WebGlTexture {
+        &self.texture
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn internal_format(&self) -> u32 {
+        self.internal_format
+    }
+}
+
+impl Drop for Texture2D {
+    fn drop(&mut self) {
+        if self.generation == self.gl.generation() {
+            self.gl.delete_texture(Some(&self.texture));
+        }
+        count(|counts| counts.textures -= 1);
+    }
+}
+
+// A vertex array object, deleted when dropped
+#[derive(Debug)]
+pub struct VertexArray {
+    gl: Gl,
+    generation: u32,
+    vertex_array: WebGlVertexArrayObject,
+}
+
+impl VertexArray {
+    pub fn new(gl: &Gl) -> Result<Self, JsValue> {
+        let vertex_array = gl
+            .create_vertex_array()
+            .ok_or("Could not create vertex array")?;
+        count(|counts| counts.vertex_arrays += 1);
+        Ok(Self {
+            gl: gl.clone(),
+            generation: gl.generation(),
+            vertex_array,
+        })
+    }
+
+    pub fn bind(&self) {
+        self.gl.bind_vertex_array(Some(&self.vertex_array));
+    }
+
+    pub fn raw(&self) -> &WebGlVertexArrayObject {
+        &self.vertex_array
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        if self.generation == self.gl.generation() {
+            self.gl.delete_vertex_array(Some(&self.vertex_array));
+        }
+        count(|counts| counts.vertex_arrays -= 1);
+    }
+}
+
+// A linked program, deleted when dropped
+#[derive(Debug)]
+pub struct Program {
+    gl: Gl,
+    generation: u32,
+    program: WebGlProgram,
+}
+
+impl Program {
+    // Compiles both stages and links them. The shader objects are deleted once linked.
+    pub fn link(
+        gl: &Gl,
+        vertex: &ShaderSource,
+        fragment: &ShaderSource,
+        attribute_locations: &[(u32, String)],
+        transform_feedback_varyings: Option<&[&str]>,
+    ) -> Result<Self, ShaderError> {
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, vertex)?;
+        let frag_shader =
+            match compile_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, fragment) {
+                Ok(shader) => shader,
+                Err(error) => {
+                    gl.delete_shader(Some(&vert_shader));
+                    return Err(error);
+                }
+            };
+        let program = link_program(
+            gl,
+            &vert_shader,
+            &frag_shader,
+            attribute_locations,
+            transform_feedback_varyings,
+        );
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
+
+        let program = program?;
+        count(|counts| counts.programs += 1);
+        Ok(Self {
+            gl: gl.clone(),
+            generation: gl.generation(),
+            program,
+        })
+    }
+
+    pub fn bind(&self) {
+        self.gl.use_program(Some(&self.program));
+    }
+
+    pub fn raw(&self) -> &WebGlProgram {
+        &self.program
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        if self.generation == self.gl.generation() {
+            self.gl.delete_program(Some(&self.program));
+        }
+        count(|counts| counts.programs -= 1);
+    }
+}
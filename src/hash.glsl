@@ -0,0 +1,24 @@
+/* Cheap integer hashes for when a noise texture lookup isn't convenient.
+   Based on "Hash Functions for GPU Rendering" (Jarzynski & Olano, 2020). */
+
+uvec3 pcg3d(uvec3 v) {
+  v = v * 1664525u + 1013904223u;
+  v.x += v.y * v.z;
+  v.y += v.z * v.x;
+  v.z += v.x * v.y;
+  v ^= v >> 16u;
+  v.x += v.y * v.z;
+  v.y += v.z * v.x;
+  v.z += v.x * v.y;
+  return v;
+}
+
+/* Three random values in [0, 1] for an integer seed. */
+vec3 hash31(int seed) {
+  return vec3(pcg3d(uvec3(seed, seed * 7 + 1, seed * 13 + 2))) / float(0xffffffffu);
+}
+
+/* Three random values in [0, 1] for a position. */
+vec3 hash33(vec3 p) {
+  return vec3(pcg3d(floatBitsToUint(p))) / float(0xffffffffu);
+}
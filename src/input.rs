@@ -0,0 +1,762 @@
+use glam::Vec2;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+// Keyboard, pointer and gamepad state for the game loop, fed by DOM events (see `Engine`) but
+// free of any web APIs itself.
+//
+// Keys are identified by their `KeyboardEvent.code` ("KeyH", "Numpad8", "ArrowUp", ...), so
+// bindings follow the physical layout. Presses are also translated into `Action`s through a
+// `Keymap` and queued until the game takes them, which suits a turn-based loop that only asks
+// for input when it's the player's turn.
+//
+// Held state is live. Pressed and released edges collect as events come in and are published by
+// `begin_frame`, so they stay visible for a whole frame whenever the game looks at them.
+
+// One of the eight directions on the map, with +Y up
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::NorthEast,
+        Direction::East,
+        Direction::SouthEast,
+        Direction::South,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+    ];
+
+    // The step in tiles
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, 1),
+            Direction::NorthEast => (1, 1),
+            Direction::East => (1, 0),
+            Direction::SouthEast => (1, -1),
+            Direction::South => (0, -1),
+            Direction::SouthWest => (-1, -1),
+            Direction::West => (-1, 0),
+            Direction::NorthWest => (-1, 1),
+        }
+    }
+
+    pub fn from_offset(dx: i32, dy: i32) -> Option<Direction> {
+        Direction::ALL
+            .into_iter()
+            .find(|direction| direction.offset() == (dx, dy))
+    }
+
+    // The direction a stick at (`x`, `y`) points in, with +Y up. Each axis counts once it is
+    // past `threshold`, so pushing the stick diagonally gives a diagonal.
+    pub fn from_stick(x: f32, y: f32, threshold: f32) -> Option<Direction> {
+        let axis = |value: f32| {
+            if value > threshold {
+                1
+            } else if value < -threshold {
+                -1
+            } else {
+                0
+            }
+        };
+        Self::from_offset(axis(x), axis(y))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Direction::North => "n",
+            Direction::NorthEast => "ne",
+            Direction::East => "e",
+            Direction::SouthEast => "se",
+            Direction::South => "s",
+            Direction::SouthWest => "sw",
+            Direction::West => "w",
+            Direction::NorthWest => "nw",
+        }
+    }
+}
+
+// What the player asked to do
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    Move(Direction),
+    Wait,
+    PickUp,
+    Inventory,
+    Ascend,
+    Descend,
+    Confirm,
+    Cancel,
+}
+
+// Actions are named "move_n", "move_ne", ..., "wait", "pick_up", "inventory", "ascend",
+// "descend", "confirm" and "cancel", for passing to and from JavaScript
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Move(direction) => write!(f, "move_{}", direction.name()),
+            Action::Wait => f.write_str("wait"),
+            Action::PickUp => f.write_str("pick_up"),
+            Action::Inventory => f.write_str("inventory"),
+            Action::Ascend => f.write_str("ascend"),
+            Action::Descend => f.write_str("descend"),
+            Action::Confirm => f.write_str("confirm"),
+            Action::Cancel => f.write_str("cancel"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownAction(pub String);
+
+impl fmt::Display for UnknownAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown action {:?}", self.0)
+    }
+}
+
+impl FromStr for Action {
+    type Err = UnknownAction;
+
+    fn from_str(name: &str) -> Result<Self, UnknownAction> {
+        let action = match name {
+            "wait" => Action::Wait,
+            "pick_up" => Action::PickUp,
+            "inventory" => Action::Inventory,
+            "ascend" => Action::Ascend,
+            "descend" => Action::Descend,
+            "confirm" => Action::Confirm,
+            "cancel" => Action::Cancel,
+            _ => {
+                return name
+                    .strip_prefix("move_")
+                    .and_then(|direction| {
+                        Direction::ALL.into_iter().find(|d| d.name() == direction)
+                    })
+                    .map(Action::Move)
+                    .ok_or_else(|| UnknownAction(name.to_string()))
+            }
+        };
+        Ok(action)
+    }
+}
+
+// A mouse button (`MouseEvent.button`; touches and pens act as button 0) or a button of a
+// gamepad with the standard mapping
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Button {
+    Pointer(i16),
+    Gamepad(u32),
+}
+
+// Standard gamepad mapping buttons used by the presets
+pub const GAMEPAD_A: u32 = 0;
+pub const GAMEPAD_B: u32 = 1;
+pub const GAMEPAD_X: u32 = 2;
+pub const GAMEPAD_Y: u32 = 3;
+pub const GAMEPAD_UP: u32 = 12;
+pub const GAMEPAD_DOWN: u32 = 13;
+pub const GAMEPAD_LEFT: u32 = 14;
+pub const GAMEPAD_RIGHT: u32 = 15;
+
+// Something that can be bound to an action
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Binding {
+    // A `KeyboardEvent.code`, with or without shift held
+    Key { code: String, shift: bool },
+    Gamepad(u32),
+}
+
+impl Binding {
+    pub fn key(code: &str) -> Self {
+        Binding::Key {
+            code: code.to_string(),
+            shift: false,
+        }
+    }
+
+    pub fn shifted(code: &str) -> Self {
+        Binding::Key {
+            code: code.to_string(),
+            shift: true,
+        }
+    }
+}
+
+// Maps keys and gamepad buttons to actions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keymap {
+    bindings: HashMap<Binding, Action>,
+}
+
+impl Keymap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // h/j/k/l and y/u/b/n, as in Rogue and NetHack
+    pub fn vi_keys() -> Self {
+        let mut keymap = Self::common();
+        for (code, direction) in [
+            ("KeyK", Direction::North),
+            ("KeyU", Direction::NorthEast),
+            ("KeyL", Direction::East),
+            ("KeyN", Direction::SouthEast),
+            ("KeyJ", Direction::South),
+            ("KeyB", Direction::SouthWest),
+            ("KeyH", Direction::West),
+            ("KeyY", Direction::NorthWest),
+        ] {
+            keymap.bind(Binding::key(code), Action::Move(direction));
+        }
+        keymap.bind(Binding::key("Period"), Action::Wait);
+        keymap
+    }
+
+    // The number pad, with 5 to wait
+    pub fn numpad() -> Self {
+        let mut keymap = Self::common();
+        for (code, direction) in [
+            ("Numpad8", Direction::North),
+            ("Numpad9", Direction::NorthEast),
+            ("Numpad6", Direction::East),
+            ("Numpad3", Direction::SouthEast),
+            ("Numpad2", Direction::South),
+            ("Numpad1", Direction::SouthWest),
+            ("Numpad4", Direction::West),
+            ("Numpad7", Direction::NorthWest),
+        ] {
+            keymap.bind(Binding::key(code), Action::Move(direction));
+        }
+        keymap.bind(Binding::key("Numpad5"), Action::Wait);
+        keymap
+    }
+
+    // The arrow keys, with Home, Page Up, Page Down and End for the diagonals like on a number
+    // pad with Num Lock off
+    pub fn arrows() -> Self {
+        let mut keymap = Self::common();
+        for (code, direction) in [
+            ("ArrowUp", Direction::North),
+            ("PageUp", Direction::NorthEast),
+            ("ArrowRight", Direction::East),
+            ("PageDown", Direction::SouthEast),
+            ("ArrowDown", Direction::South),
+            ("End", Direction::SouthWest),
+            ("ArrowLeft", Direction::West),
+            ("Home", Direction::NorthWest),
+        ] {
+            keymap.bind(Binding::key(code), Action::Move(direction));
+        }
+        keymap.bind(Binding::key("Space"), Action::Wait);
+        keymap
+    }
+
+    // Every preset at once, which is what the engine starts with
+    pub fn all() -> Self {
+        let mut keymap = Self::vi_keys();
+        keymap.extend(Self::numpad());
+        keymap.extend(Self::arrows());
+        keymap
+    }
+
+    // Looks up a preset by name: "vi", "numpad", "arrows" or "all"
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "vi" => Some(Self::vi_keys()),
+            "numpad" => Some(Self::numpad()),
+            "arrows" => Some(Self::arrows()),
+            "all" => Some(Self::all()),
+            _ => None,
+        }
+    }
+
+    // Bindings every preset shares: the usual command keys and a gamepad's d-pad and face
+    // buttons
+    fn common() -> Self {
+        let mut keymap = Self::new();
+        keymap.bind(Binding::key("Enter"), Action::Confirm);
+        keymap.bind(Binding::key("NumpadEnter"), Action::Confirm);
+        keymap.bind(Binding::key("Escape"), Action::Cancel);
+        keymap.bind(Binding::key("KeyG"), Action::PickUp);
+        keymap.bind(Binding::key("Comma"), Action::PickUp);
+        keymap.bind(Binding::key("KeyI"), Action::Inventory);
+        // < and >
+        keymap.bind(Binding::shifted("Comma"), Action::Ascend);
+        keymap.bind(Binding::shifted("Period"), Action::Descend);
+
+        keymap.bind(Binding::Gamepad(GAMEPAD_UP), Action::Move(Direction::North));
+        keymap.bind(
+            Binding::Gamepad(GAMEPAD_RIGHT),
+            Action::Move(Direction::East),
+        );
+        keymap.bind(
+            Binding::Gamepad(GAMEPAD_DOWN),
+            Action::Move(Direction::South),
+        );
+        keymap.bind(
+            Binding::Gamepad(GAMEPAD_LEFT),
+            Action::Move(Direction::West),
+        );
+        keymap.bind(Binding::Gamepad(GAMEPAD_A), Action::Confirm);
+        keymap.bind(Binding::Gamepad(GAMEPAD_B), Action::Cancel);
+        keymap.bind(Binding::Gamepad(GAMEPAD_X), Action::PickUp);
+        keymap.bind(Binding::Gamepad(GAMEPAD_Y), Action::Wait);
+        keymap
+    }
+
+    // Replaces whatever `binding` was bound to
+    pub fn bind(&mut self, binding: Binding, action: Action) {
+        self.bindings.insert(binding, action);
+    }
+
+    pub fn unbind(&mut self, binding: &Binding) -> Option<Action> {
+        self.bindings.remove(binding)
+    }
+
+    // Adds `other`'s bindings, replacing ours where they overlap
+    pub fn extend(&mut self, other: Keymap) {
+        self.bindings.extend(other.bindings);
+    }
+
+    pub fn action(&self, binding: &Binding) -> Option<Action> {
+        self.bindings.get(binding).copied()
+    }
+
+    // A key with shift held falls back to its unshifted binding, so capital letters still move
+    pub fn key_action(&self, code: &str, shift: bool) -> Option<Action> {
+        let binding = Binding::Key {
+            code: code.to_string(),
+            shift,
+        };
+        self.action(&binding)
+            .or_else(|| shift.then(|| self.action(&Binding::key(code))).flatten())
+    }
+
+    // Everything bound to `action`
+    pub fn bindings_for(&self, action: Action) -> impl Iterator<Item = &Binding> {
+        self.bindings
+            .iter()
+            .filter(move |(_, bound)| **bound == action)
+            .map(|(binding, _)| binding)
+    }
+}
+
+// Pressed and released edges gathered between two frames
+#[derive(Debug, Clone, Default)]
+struct Edges {
+    keys_pressed: HashSet<String>,
+    keys_released: HashSet<String>,
+    buttons_pressed: HashSet<Button>,
+    buttons_released: HashSet<Button>,
+    // Pixels the pointer moved
+    pointer_delta: Vec2,
+    // Wheel pixels scrolled, positive away from the user
+    wheel: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputState {
+    keymap: Keymap,
+    keys_down: HashSet<String>,
+    buttons_down: HashSet<Button>,
+    // Where the gamepad's stick points, from polling the gamepad
+    stick: Option<Direction>,
+    // In CSS pixels from the canvas' top left corner, while it's over the canvas
+    pointer: Option<Vec2>,
+    // Edges since the last `begin_frame`, and the ones it published
+    incoming: Edges,
+    current: Edges,
+    actions: VecDeque<Action>,
+    // Actions beyond this many are dropped, so mashing keys while the game is busy doesn't
+    // play out for ages afterwards
+    max_queued: usize,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self::new(Keymap::all())
+    }
+}
+
+impl InputState {
+    pub fn new(keymap: Keymap) -> Self {
+        Self {
+            keymap,
+            keys_down: HashSet::new(),
+            buttons_down: HashSet::new(),
+            stick: None,
+            pointer: None,
+            incoming: Edges::default(),
+            current: Edges::default(),
+            actions: VecDeque::new(),
+            max_queued: 8,
+        }
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    pub fn keymap_mut(&mut self) -> &mut Keymap {
+        &mut self.keymap
+    }
+
+    pub fn set_max_queued(&mut self, max_queued: usize) {
+        self.max_queued = max_queued;
+        self.actions.truncate(max_queued);
+    }
+
+    // Publishes the edges gathered since the last call; call once at the start of every frame
+    pub fn begin_frame(&mut self) {
+        self.current = std::mem::take(&mut self.incoming);
+    }
+
+    // Records a key going down and queues its action. Auto-repeats queue the action again
+    // without counting as a new press. Returns whether the key is bound, i.e. whether the
+    // browser's default action (scrolling, ...) should be prevented.
+    pub fn key_down(&mut self, code: &str, shift: bool, repeat: bool) -> bool {
+        if !repeat && self.keys_down.insert(code.to_string()) {
+            self.incoming.keys_pressed.insert(code.to_string());
+        }
+        match self.keymap.key_action(code, shift) {
+            Some(action) => {
+                self.queue(action);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn key_up(&mut self, code: &str) {
+        if self.keys_down.remove(code) {
+            self.incoming.keys_released.insert(code.to_string());
+        }
+    }
+
+    pub fn button_down(&mut self, button: Button) {
+        if self.buttons_down.insert(button) {
+            self.incoming.buttons_pressed.insert(button);
+            if let Button::Gamepad(index) = button {
+                if let Some(action) = self.keymap.action(&Binding::Gamepad(index)) {
+                    self.queue(action);
+                }
+            }
+        }
+    }
+
+    pub fn button_up(&mut self, button: Button) {
+        if self.buttons_down.remove(&button) {
+            self.incoming.buttons_released.insert(button);
+        }
+    }
+
+    // Sets which gamepad buttons are down, from polling the gamepad. Buttons pressed since the
+    // last poll queue their actions in button order.
+    pub fn set_gamepad_buttons(&mut self, down: impl IntoIterator<Item = u32>) {
+        let down: BTreeSet<u32> = down.into_iter().collect();
+        let released: Vec<Button> = self
+            .buttons_down
+            .iter()
+            .filter(|button| match button {
+                Button::Gamepad(index) => !down.contains(index),
+                Button::Pointer(_) => false,
+            })
+            .copied()
+            .collect();
+        for button in released {
+            self.button_up(button);
+        }
+        for index in down {
+            self.button_down(Button::Gamepad(index));
+        }
+    }
+
+    // Sets where the gamepad's stick points, from polling the gamepad. Pushing it in a new
+    // direction queues a single move that way, diagonals included.
+    pub fn set_gamepad_stick(&mut self, direction: Option<Direction>) {
+        if direction != self.stick {
+            self.stick = direction;
+            if let Some(direction) = direction {
+                self.queue(Action::Move(direction));
+            }
+        }
+    }
+
+    pub fn pointer_moved(&mut self, position: Vec2, delta: Vec2) {
+        self.pointer = Some(position);
+        self.incoming.pointer_delta += delta;
+    }
+
+    // The pointer left the canvas, taking any held pointer buttons with it as their releases
+    // won't be seen
+    pub fn pointer_left(&mut self) {
+        self.pointer = None;
+        let held: Vec<Button> = self
+            .buttons_down
+            .iter()
+            .filter(|button| matches!(button, Button::Pointer(_)))
+            .copied()
+            .collect();
+        for button in held {
+            self.button_up(button);
+        }
+    }
+
+    pub fn wheel(&mut self, delta: f32) {
+        self.incoming.wheel += delta;
+    }
+
+    // Releases everything, e.g. when the canvas loses focus and would miss the key ups
+    pub fn release_all(&mut self) {
+        for code in std::mem::take(&mut self.keys_down) {
+            self.incoming.keys_released.insert(code);
+        }
+        for button in std::mem::take(&mut self.buttons_down) {
+            self.incoming.buttons_released.insert(button);
+        }
+        self.stick = None;
+    }
+
+    fn queue(&mut self, action: Action) {
+        if self.actions.len() < self.max_queued {
+            self.actions.push_back(action);
+        }
+    }
+
+    // Takes the oldest queued action
+    pub fn pop_action(&mut self) -> Option<Action> {
+        self.actions.pop_front()
+    }
+
+    pub fn clear_actions(&mut self) {
+        self.actions.clear();
+    }
+
+    pub fn is_key_down(&self, code: &str) -> bool {
+        self.keys_down.contains(code)
+    }
+
+    pub fn was_key_pressed(&self, code: &str) -> bool {
+        self.current.keys_pressed.contains(code)
+    }
+
+    pub fn was_key_released(&self, code: &str) -> bool {
+        self.current.keys_released.contains(code)
+    }
+
+    pub fn is_button_down(&self, button: Button) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    pub fn was_button_pressed(&self, button: Button) -> bool {
+        self.current.buttons_pressed.contains(&button)
+    }
+
+    pub fn was_button_released(&self, button: Button) -> bool {
+        self.current.buttons_released.contains(&button)
+    }
+
+    pub fn pointer(&self) -> Option<Vec2> {
+        self.pointer
+    }
+
+    pub fn pointer_delta(&self) -> Vec2 {
+        self.current.pointer_delta
+    }
+
+    pub fn wheel_delta(&self) -> f32 {
+        self.current.wheel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions(input: &mut InputState) -> Vec<Action> {
+        std::iter::from_fn(|| input.pop_action()).collect()
+    }
+
+    fn press(input: &mut InputState, code: &str) -> bool {
+        let bound = input.key_down(code, false, false);
+        input.key_up(code);
+        bound
+    }
+
+    #[test]
+    fn presets_map_keys_to_moves() {
+        for (keymap, codes, wait) in [
+            (
+                Keymap::vi_keys(),
+                [
+                    "KeyK", "KeyU", "KeyL", "KeyN", "KeyJ", "KeyB", "KeyH", "KeyY",
+                ],
+                "Period",
+            ),
+            (
+                Keymap::numpad(),
+                [
+                    "Numpad8", "Numpad9", "Numpad6", "Numpad3", "Numpad2", "Numpad1", "Numpad4",
+                    "Numpad7",
+                ],
+                "Numpad5",
+            ),
+            (
+                Keymap::arrows(),
+                [
+                    "ArrowUp",
+                    "PageUp",
+                    "ArrowRight",
+                    "PageDown",
+                    "ArrowDown",
+                    "End",
+                    "ArrowLeft",
+                    "Home",
+                ],
+                "Space",
+            ),
+        ] {
+            let mut input = InputState::new(keymap);
+            input.set_max_queued(16);
+            for code in codes {
+                assert!(press(&mut input, code));
+            }
+            assert!(press(&mut input, wait));
+            assert!(!press(&mut input, "KeyZ"));
+
+            let expected: Vec<Action> = Direction::ALL
+                .into_iter()
+                .map(Action::Move)
+                .chain([Action::Wait])
+                .collect();
+            assert_eq!(actions(&mut input), expected);
+        }
+    }
+
+    #[test]
+    fn shift_falls_back_to_the_unshifted_binding() {
+        let keymap = Keymap::all();
+        assert_eq!(
+            keymap.key_action("KeyH", true),
+            Some(Action::Move(Direction::West))
+        );
+        assert_eq!(keymap.key_action("Period", true), Some(Action::Descend));
+        assert_eq!(keymap.key_action("Period", false), Some(Action::Wait));
+    }
+
+    #[test]
+    fn rebinding_overrides_and_unbinding_removes() {
+        let mut input = InputState::new(Keymap::vi_keys());
+        input
+            .keymap_mut()
+            .bind(Binding::key("KeyH"), Action::Inventory);
+        assert_eq!(
+            input.keymap_mut().unbind(&Binding::key("KeyL")),
+            Some(Action::Move(Direction::East))
+        );
+
+        assert!(press(&mut input, "KeyH"));
+        assert!(!press(&mut input, "KeyL"));
+        assert_eq!(actions(&mut input), [Action::Inventory]);
+        // only the gamepad's d-pad is left moving west
+        assert!(input
+            .keymap()
+            .bindings_for(Action::Move(Direction::West))
+            .all(|binding| *binding == Binding::Gamepad(GAMEPAD_LEFT)));
+    }
+
+    #[test]
+    fn edges_last_one_frame() {
+        let mut input = InputState::default();
+        input.key_down("KeyA", false, false);
+        input.button_down(Button::Pointer(0));
+        assert!(input.is_key_down("KeyA"));
+        // not published until the next frame starts
+        assert!(!input.was_key_pressed("KeyA"));
+
+        input.begin_frame();
+        assert!(input.was_key_pressed("KeyA"));
+        assert!(input.was_button_pressed(Button::Pointer(0)));
+
+        input.key_up("KeyA");
+        input.pointer_left();
+        input.begin_frame();
+        assert!(!input.was_key_pressed("KeyA"));
+        assert!(!input.was_button_pressed(Button::Pointer(0)));
+        assert!(input.was_key_released("KeyA"));
+        assert!(input.was_button_released(Button::Pointer(0)));
+        assert!(!input.is_key_down("KeyA"));
+
+        input.begin_frame();
+        assert!(!input.was_key_released("KeyA"));
+        assert!(!input.was_button_released(Button::Pointer(0)));
+    }
+
+    #[test]
+    fn repeats_queue_actions_without_new_presses() {
+        let mut input = InputState::default();
+        input.key_down("KeyJ", false, false);
+        input.begin_frame();
+        input.key_down("KeyJ", false, true);
+        input.begin_frame();
+        assert!(!input.was_key_pressed("KeyJ"));
+        assert_eq!(actions(&mut input), [Action::Move(Direction::South); 2]);
+    }
+
+    #[test]
+    fn gamepad_presses_queue_in_button_order() {
+        let mut input = InputState::default();
+        input.set_gamepad_buttons([GAMEPAD_Y, GAMEPAD_LEFT, GAMEPAD_A, GAMEPAD_X]);
+        assert_eq!(
+            actions(&mut input),
+            [
+                Action::Confirm,
+                Action::PickUp,
+                Action::Wait,
+                Action::Move(Direction::West)
+            ]
+        );
+
+        // held buttons don't queue again
+        input.set_gamepad_buttons([GAMEPAD_A, GAMEPAD_B]);
+        assert_eq!(actions(&mut input), [Action::Cancel]);
+        input.begin_frame();
+        assert!(input.was_button_released(Button::Gamepad(GAMEPAD_Y)));
+    }
+
+    #[test]
+    fn stick_resolves_to_one_direction() {
+        assert_eq!(Direction::from_stick(0.2, -0.3, 0.5), None);
+        assert_eq!(Direction::from_stick(0.9, 0.1, 0.5), Some(Direction::East));
+        assert_eq!(
+            Direction::from_stick(-0.7, 0.7, 0.5),
+            Some(Direction::NorthWest)
+        );
+
+        let mut input = InputState::default();
+        input.set_gamepad_stick(Direction::from_stick(0.7, -0.7, 0.5));
+        input.set_gamepad_stick(Direction::from_stick(0.8, -0.6, 0.5));
+        input.set_gamepad_stick(None);
+        input.set_gamepad_stick(Direction::from_stick(0.0, 1.0, 0.5));
+        assert_eq!(
+            actions(&mut input),
+            [
+                Action::Move(Direction::SouthEast),
+                Action::Move(Direction::North)
+            ]
+        );
+    }
+}
@@ -1,109 +1,41 @@
-use glam::vec3;
 use js_sys::Array;
-use std::cell::RefCell;
-use std::default::Default;
-use std::rc::Rc;
+use shader::{Diagnostic, ShaderError, ShaderSource, ShaderStage};
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
-use web_sys::{
-    WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlShader, WebGlTexture,
-    WebGlUniformLocation,
-};
-
-mod particle;
-
-#[wasm_bindgen(start)]
-pub fn display_model() -> Result<(), JsValue> {
-    let window = web_sys::window().unwrap();
-    let performance = window.performance().unwrap();
-    let document = window.document().unwrap();
-    let canvas = document.get_element_by_id("canvas").unwrap();
-    let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
-
-    let context = canvas
-        .get_context("webgl2")?
-        .unwrap()
-        .dyn_into::<WebGl2RenderingContext>()?;
-
-    // Setup particle systems
-    let particle_system = particle::UpdateSystem::new(&context)?;
-    let particle_renderer = particle::Render::new(&context)?;
-
-    // Create a particle emitter and a renderer
-    let mut fireball = particle_system.create_emitter(
-        &context,
-        particle::EmitterOptions {
-            gravity: vec3(-7.0, 0.0, 0.0),
-            min_speed: 0.02,
-            max_speed: 0.3,
-            ..Default::default()
-        },
-    )?;
-    let fireball_gradient = create_gradient_texture(
-        &context,
-        &[
-            glam::f32::Vec4::new(1.0, 1.0, 1.0, 1.0),
-            glam::f32::Vec4::new(1.0, 0.83, 0.0, 0.9),
-            glam::f32::Vec4::new(0.75, 0.25, 0.05, 0.8),
-            glam::f32::Vec4::new(0.18, 0.0, 0.02, 0.5),
-            glam::f32::Vec4::new(0.0, 0.0, 0.0, 0.0),
-        ],
-    )?;
-
-    let projection =
-        glam::f32::Mat4::perspective_infinite_rh(f32::to_radians(45.0), 640.0 / 480.0, 0.01);
-
-    let f = Rc::new(RefCell::new(None));
-    let g = f.clone();
-
-    let start_time = (performance.now() / 1000.0) as f32;
-    let mut prev_time = start_time;
-    *g.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        let current_time = (performance.now() / 1000.0) as f32;
-        let time_delta = current_time - prev_time;
-        let time = current_time - start_time;
-
-        context.clear_color(0.0, 0.0, 0.0, 1.0);
-        context.clear(
-            WebGl2RenderingContext::COLOR_BUFFER_BIT | WebGl2RenderingContext::DEPTH_BUFFER_BIT,
-        );
-
-        // Update Particles. Skip if the time delta is too large
-        if time_delta < 0.15 {
-            particle_system.update(&context, &mut fireball, time_delta);
-        }
-
-        // Calculate camera position
-        let theta = time * (2.0 * std::f32::consts::PI) / 5.0;
-        let radius = 1.5;
-        let camera_pos = glam::vec3(0.0, 0.5, radius);
-        let view = glam::f32::Mat4::look_at_rh(
-            camera_pos,
-            glam::vec3(0.0, 0.0, 0.0),
-            glam::vec3(0.0, 1.0, 0.0),
-        );
-
-        // Render particles
-        particle_renderer.render(&context, projection, view, &fireball, &fireball_gradient);
-
-        prev_time = current_time;
-        request_animation_frame(f.borrow().as_ref().unwrap());
-    }) as Box<dyn FnMut()>));
-
-    request_animation_frame(g.borrow().as_ref().unwrap());
-    Ok(())
+use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlShader, WebGlUniformLocation};
+
+pub mod camera;
+pub mod console;
+pub mod engine;
+pub mod font;
+pub mod gl;
+pub mod input;
+pub mod light;
+pub mod particle;
+pub mod shader;
+pub mod text;
+pub mod tilemap;
+pub mod time;
+
+pub use engine::Engine;
+
+// Replaces a shader library file, e.g. from a dev server watching src/*.glsl. Programs using it
+// are rebuilt on the next frame; compile errors are logged and the old programs kept, and
+// `Engine::last_shader_error` reports them.
+#[cfg(feature = "hot-reload")]
+#[wasm_bindgen]
+pub fn set_shader_source(name: &str, source: &str) {
+    shader::set_library_source(name, source);
 }
 
 fn create_gradient_texture(
-    gl: &WebGl2RenderingContext,
+    gl: &gl::Gl,
     gradient: &[glam::f32::Vec4],
-) -> Result<WebGlTexture, JsValue> {
+) -> Result<gl::Texture2D, JsValue> {
     assert!(gradient.len() <= 256);
 
     let bytes: Vec<_> = gradient
         .iter()
-        .map(|c| c.to_array())
-        .flatten()
+        .flat_map(|c| c.to_array())
         .map(|f| (f * 255.0) as u8)
         .collect();
 
@@ -113,54 +45,39 @@ fn create_gradient_texture(
         bytes.len()
     ));
 
-    let texture = gl
-        .create_texture()
-        .ok_or_else(|| "Failed to create texture")?;
-    gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
-
-    gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
-        WebGl2RenderingContext::TEXTURE_2D,
-        0,
-        WebGl2RenderingContext::RGBA as i32,
+    let texture = gl::Texture2D::new(
+        gl,
         gradient.len() as i32,
         1,
-        0,
+        WebGl2RenderingContext::RGBA,
         WebGl2RenderingContext::RGBA,
         WebGl2RenderingContext::UNSIGNED_BYTE,
         Some(&bytes),
     )?;
-    gl.tex_parameteri(
-        WebGl2RenderingContext::TEXTURE_2D,
-        WebGl2RenderingContext::TEXTURE_WRAP_S,
-        WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
-    );
-    gl.tex_parameteri(
-        WebGl2RenderingContext::TEXTURE_2D,
-        WebGl2RenderingContext::TEXTURE_WRAP_T,
-        WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
+    texture.set_wrap(
+        WebGl2RenderingContext::CLAMP_TO_EDGE,
+        WebGl2RenderingContext::CLAMP_TO_EDGE,
     );
-    gl.tex_parameteri(
-        WebGl2RenderingContext::TEXTURE_2D,
-        WebGl2RenderingContext::TEXTURE_MIN_FILTER,
-        WebGl2RenderingContext::LINEAR as i32,
-    );
-    gl.tex_parameteri(
-        WebGl2RenderingContext::TEXTURE_2D,
-        WebGl2RenderingContext::TEXTURE_MAG_FILTER,
-        WebGl2RenderingContext::LINEAR as i32,
+    texture.set_filter(
+        WebGl2RenderingContext::LINEAR,
+        WebGl2RenderingContext::LINEAR,
     );
 
     Ok(texture)
 }
 
-fn window() -> web_sys::Window {
-    web_sys::window().expect("no global `window` exists")
-}
-
-fn request_animation_frame(f: &Closure<dyn FnMut()>) {
-    window()
-        .request_animation_frame(f.as_ref().unchecked_ref())
-        .expect("should register `requestAnimationFrame` OK");
+// Samples a gradient the same way the texture from `create_gradient_texture` is sampled on the
+// GPU: linear filtering between texel centers, clamped at the edges.
+pub fn sample_gradient(gradient: &[glam::f32::Vec4], t: f32) -> glam::f32::Vec4 {
+    match gradient.len() {
+        0 => glam::f32::Vec4::ZERO,
+        1 => gradient[0],
+        len => {
+            let x = (t * len as f32 - 0.5).clamp(0.0, (len - 1) as f32);
+            let i = (x.floor() as usize).min(len - 2);
+            gradient[i].lerp(gradient[i + 1], x - i as f32)
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -170,17 +87,20 @@ extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
 
+    #[wasm_bindgen(js_namespace = console, js_name = error)]
+    fn log_error(s: &str);
+
 }
 
 pub fn compile_shader(
     context: &WebGl2RenderingContext,
     shader_type: u32,
-    source: &str,
-) -> Result<WebGlShader, String> {
+    source: &ShaderSource,
+) -> Result<WebGlShader, ShaderError> {
     let shader = context
         .create_shader(shader_type)
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
-    context.shader_source(&shader, source);
+        .ok_or(ShaderError::ShaderCreate)?;
+    context.shader_source(&shader, &source.source);
     context.compile_shader(&shader);
 
     if context
@@ -190,9 +110,15 @@ pub fn compile_shader(
     {
         Ok(shader)
     } else {
-        Err(context
+        let log = context
             .get_shader_info_log(&shader)
-            .unwrap_or_else(|| String::from("Unknown error creating shader")))
+            .unwrap_or_else(|| String::from("Unknown error creating shader"));
+        Err(ShaderError::Compile {
+            stage: ShaderStage::from_gl(shader_type),
+            source_name: source.name.clone(),
+            diagnostics: Diagnostic::parse_log(&log, source),
+            log,
+        })
     }
 }
 
@@ -200,15 +126,18 @@ pub fn link_program(
     context: &WebGl2RenderingContext,
     vert_shader: &WebGlShader,
     frag_shader: &WebGlShader,
+    attribute_locations: &[(u32, String)],
     transform_feedback_varyings: Option<&[&str]>,
-) -> Result<WebGlProgram, String> {
-    let program = context
-        .create_program()
-        .ok_or_else(|| String::from("Unable to create shader object"))?;
+) -> Result<WebGlProgram, ShaderError> {
+    let program = context.create_program().ok_or(ShaderError::ShaderCreate)?;
 
     context.attach_shader(&program, vert_shader);
     context.attach_shader(&program, frag_shader);
 
+    for (location, name) in attribute_locations {
+        context.bind_attrib_location(&program, *location, name);
+    }
+
     if let Some(varyings) = transform_feedback_varyings {
         let varyings_js: Array = varyings.iter().map(|s| JsValue::from_str(s)).collect();
         context.transform_feedback_varyings(
@@ -227,9 +156,11 @@ pub fn link_program(
     {
         Ok(program)
     } else {
-        Err(context
+        let log = context
             .get_program_info_log(&program)
-            .unwrap_or_else(|| String::from("Unknown error creating program object")))
+            .unwrap_or_else(|| String::from("Unknown error creating program object"));
+        context.delete_program(Some(&program));
+        Err(ShaderError::Link { log })
     }
 }
 
@@ -237,15 +168,25 @@ pub fn get_uniform(
     context: &WebGl2RenderingContext,
     program: &WebGlProgram,
     name: &str,
-) -> Result<WebGlUniformLocation, String> {
+) -> Result<WebGlUniformLocation, ShaderError> {
     context
-        .get_uniform_location(&program, name)
-        .ok_or(format!("Could not get uniform location for {:?}", name))
+        .get_uniform_location(program, name)
+        .ok_or_else(|| ShaderError::MissingUniform {
+            name: name.to_string(),
+        })
 }
 
-pub fn create_buffer(context: &WebGl2RenderingContext) -> Result<WebGlBuffer, String> {
-    let buffer = context
-        .create_buffer()
-        .ok_or_else(|| format!("Could not create buffer"))?;
-    Ok(buffer)
+pub fn get_attrib(
+    context: &WebGl2RenderingContext,
+    program: &WebGlProgram,
+    name: &str,
+) -> Result<u32, ShaderError> {
+    let location = context.get_attrib_location(program, name);
+    if location < 0 {
+        Err(ShaderError::MissingAttribute {
+            name: name.to_string(),
+        })
+    } else {
+        Ok(location as u32)
+    }
 }
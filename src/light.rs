@@ -0,0 +1,86 @@
+use crate::gl::Texture2D;
+use glam::{Mat4, Vec3};
+
+// The most point lights a single draw will take into account. Shaders that consume the light list
+// size their uniform arrays with this.
+pub const MAX_LIGHTS: usize = 16;
+
+#[derive(Debug, Copy, Clone)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    // Distance at which the light's contribution falls off to zero
+    pub radius: f32,
+}
+
+// The dynamic lights in the scene for the current frame. It is cleared and refilled every frame
+// (e.g. from `Emitter::point_light`) and then handed to whatever renders the lit geometry.
+#[derive(Debug, Default)]
+pub struct LightList {
+    lights: Vec<PointLight>,
+}
+
+impl LightList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+
+    pub fn push(&mut self, light: PointLight) {
+        self.lights.push(light);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[PointLight] {
+        &self.lights
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PointLight> {
+        self.lights.iter()
+    }
+}
+
+impl Extend<PointLight> for LightList {
+    fn extend<T: IntoIterator<Item = PointLight>>(&mut self, iter: T) {
+        self.lights.extend(iter);
+    }
+}
+
+// Everything a lit renderer needs to know about the scene's lighting for the current frame.
+#[derive(Debug, Copy, Clone)]
+pub struct SceneLighting<'a> {
+    pub ambient: Vec3,
+    // Only the first `MAX_LIGHTS` lights are used
+    pub lights: &'a [PointLight],
+    pub visibility: Option<VisibilityMap<'a>>,
+}
+
+impl<'a> SceneLighting<'a> {
+    pub fn new(ambient: Vec3, lights: &'a [PointLight]) -> Self {
+        Self {
+            ambient,
+            lights,
+            visibility: None,
+        }
+    }
+}
+
+// The dungeon's shadow/visibility map: a texture whose red channel is 0.0 where a cell is fully
+// dark and 1.0 where it is fully lit, and the transform from world space into its texture
+// coordinates.
+#[derive(Debug, Copy, Clone)]
+pub struct VisibilityMap<'a> {
+    pub texture: &'a Texture2D,
+    pub world_to_texture: Mat4,
+}
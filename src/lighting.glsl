@@ -0,0 +1,35 @@
+/* Ambient + point light shading from the scene's light list, darkened by the
+   dungeon's visibility map. Needs MAX_LIGHTS to be defined. */
+
+uniform vec3 u_Ambient;
+
+/* The scene's point lights. Colours are premultiplied by intensity. */
+uniform int u_LightCount;
+uniform vec3 u_LightPositions[MAX_LIGHTS];
+uniform vec3 u_LightColors[MAX_LIGHTS];
+uniform float u_LightRadii[MAX_LIGHTS];
+
+/* The dungeon's visibility map. Geometry in cells it marks as dark is
+   darkened accordingly. */
+uniform bool u_UseVisibility;
+uniform sampler2D u_Visibility;
+uniform mat4 u_WorldToVisibility;
+
+vec3 lighting(vec3 position) {
+  vec3 light = u_Ambient;
+  for (int i = 0; i < MAX_LIGHTS; i++) {
+    if (i >= u_LightCount) {
+      break;
+    }
+    /* A zero radius would divide by zero and poison the whole colour */
+    float radius = max(u_LightRadii[i], 1e-4);
+    float falloff = clamp(1.0 - distance(position, u_LightPositions[i]) / radius, 0.0, 1.0);
+    light += u_LightColors[i] * falloff * falloff;
+  }
+
+  if (u_UseVisibility) {
+    vec2 coord = (u_WorldToVisibility * vec4(position, 1.0)).xy;
+    light *= texture(u_Visibility, coord).r;
+  }
+  return light;
+}
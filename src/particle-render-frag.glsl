@@ -5,10 +5,24 @@ uniform sampler2D u_Gradient;
 
 //uniform sampler2D u_Sprite;
 
+/* When false the particle is drawn with its plain gradient colour; when true
+   it is lit by the ambient term and the scene's point lights, like smoke. */
+uniform bool u_Lit;
+
+#include "lighting.glsl"
+#include "color.glsl"
+
 in float v_Age;
 in float v_Life;
+in vec3 v_WorldPosition;
 //in vec2 v_TexCoord;
 
+/* Inputs for the emitter's custom attributes are generated here. */
+#pragma hook(custom_attributes)
+
+/* The emitter type's `vec4 custom_color(vec4 base, float t)` goes here. */
+#pragma hook(custom_color)
+
 out vec4 o_FragColor;
 
 void main() {
@@ -16,6 +30,11 @@ void main() {
   //vec3 initial_color = vec3(1.0, 0.8, 0.3);
   //vec4 color = vec4(initial_color, 1.0-(v_Age/v_Life));
   //o_FragColor = color * texture(u_Sprite, v_TexCoord);
-  vec2 texture_coord = vec2(v_Age/v_Life, 0.5);
-  o_FragColor = texture(u_Gradient, texture_coord);
+  float t = v_Age/v_Life;
+  vec2 texture_coord = vec2(t, 0.5);
+  o_FragColor = custom_color(texture(u_Gradient, texture_coord), t);
+
+  if (u_Lit) {
+    o_FragColor.rgb *= lighting(v_WorldPosition);
+  }
 }
@@ -1,26 +1,83 @@
 #version 300 es
 precision mediump float;
 
-uniform mat4 u_View;
-uniform mat4 u_Projection;
+#include "frame.glsl"
+
+/* Size of a newborn particle, in pixels, or in world units with
+   u_PointSizeInWorld */
+uniform float u_PointSize;
+uniform bool u_PointSizeInWorld;
+
+#ifdef PACKED_LAYOUT
+/* Packed positions are relative to the emitter's origin */
+uniform vec3 u_Origin;
+#endif
 
 in vec3 i_Position;
 in float i_Age;
 in float i_Life;
 
+/* The same particle in the newer state, for interpolating between steps */
+in vec3 i_NextPosition;
+in float i_NextAge;
+
 //in vec2 i_Coord;
 //in vec2 i_TexCoord;
 
 out float v_Age;
 out float v_Life;
+out vec3 v_WorldPosition;
 //out vec2 v_TexCoord;
 
+/* Inputs and outputs for the emitter's custom attributes, and the
+   `forward_custom` function passing them on to the fragment shader, are
+   generated here. */
+#pragma hook(custom_attributes)
+
 void main() {
+#ifdef PACKED_LAYOUT
+  /* Age and life are normalized shorts in the packed layout */
+  float age = i_Age * PACKED_MAX_AGE;
+  float next_age = i_NextAge * PACKED_MAX_AGE;
+  float life = i_Life * PACKED_MAX_AGE;
+#else
+  float age = i_Age;
+  float next_age = i_NextAge;
+  float life = i_Life;
+#endif
+
+  /* A particle that got younger was respawned; don't sweep it across from
+     where it died */
+  float t = next_age >= age ? u_Interpolation : 0.0;
+  vec3 position = mix(i_Position, i_NextPosition, t);
+#ifdef PACKED_LAYOUT
+  position += u_Origin;
+#endif
+  age = mix(age, next_age, t);
+
   //float scale = 0.50;
   //vec2 vert_coord = i_Position + (scale * (1.0 - i_Age / i_Life) + 0.05) * 0.1 * i_Coord;
-  v_Age = i_Age;
-  v_Life = i_Life;
+  v_Age = age;
+  v_Life = life;
+  v_WorldPosition = position;
+  forward_custom();
   
-  gl_PointSize = 1.0 + 6.0 * (1.0 - i_Age/i_Life);  
-  gl_Position = u_Projection * u_View * vec4(i_Position, 1.0);
+  gl_Position = u_ViewProjection * vec4(position, 1.0);
+
+  /* Shrinks to a seventh of the full size over the particle's life */
+  gl_PointSize = u_PointSize * (1.0 + 6.0 * (1.0 - age/life)) / 7.0;
+  if (u_PointSizeInWorld) {
+    /* Pixels per world unit at this depth; w is 1 with an orthographic
+       projection */
+    gl_PointSize *= u_Projection[1][1] * u_ViewportSize.y * 0.5 / gl_Position.w;
+  }
+#ifdef HAS_SIZE
+  gl_PointSize *= i_Size;
+#endif
+
+  /* Dead particles of an emitter that stopped spawning aren't drawn. Points
+     are at least a pixel big, so move them out of view instead. */
+  if (age >= life) {
+    gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+  }
 }
@@ -28,6 +28,16 @@ uniform float u_MaxTheta;
 uniform float u_MinSpeed;
 uniform float u_MaxSpeed;
 
+/* Keeps particles on the plane z = u_Origin.z, for 2D games. Theta is then
+   simply the angle of the velocity in that plane. */
+uniform bool u_Planar;
+
+/* False once the emitter has stopped spawning: dead particles stay dead. */
+uniform bool u_Spawning;
+
+/* Hash functions, for custom attributes and forces. */
+#include "hash.glsl"
+
 /* Inputs. These reflect the state of a single particle before the update. */
 
 /* Where the particle is. */
@@ -45,13 +55,66 @@ in vec3 i_Velocity;
 
 /* Outputs. These mirror the inputs. These values will be captured
    into our transform feedback buffer! */
+#ifdef PACKED_LAYOUT
+/* Transform feedback can only capture 32-bit values, so the packed layout
+   writes position (relative to u_Origin) and velocity as half floats and
+   age/life as normalized shorts, two to a uint. */
+flat out uint v_PositionXY;
+flat out uint v_PositionZVelocityX;
+flat out uint v_VelocityYZ;
+flat out uint v_AgeLife;
+#else
 out vec3 v_Position;
 out float v_Age;
 out float v_Life;
 out vec3 v_Velocity;
+#endif
+
+/* Random values that differ for each `n`, for initializing custom
+   attributes. */
+vec3 random3(int n) {
+  ivec2 noise_coord = ivec2((gl_VertexID + 89 * n) % 512, (gl_VertexID / 512 + 233 * n) % 512);
+  return texelFetch(u_RgbNoise, noise_coord, 0).rgb;
+}
+
+/* Inputs, outputs and the `spawn_custom`/`update_custom` functions for the
+   emitter's custom attributes (rotation, size, ...) are generated here. */
+#pragma hook(custom_attributes)
+
+/* The emitter type's `vec3 custom_force(vec3 pos, float age)` goes here. Its
+   result is applied on top of gravity. */
+#pragma hook(custom_force)
+
+void write_particle(vec3 position, float age, float life, vec3 velocity) {
+#ifdef PACKED_LAYOUT
+  vec3 offset = position - u_Origin;
+  v_PositionXY = packHalf2x16(offset.xy);
+  v_PositionZVelocityX = packHalf2x16(vec2(offset.z, velocity.x));
+  v_VelocityYZ = packHalf2x16(velocity.yz);
+  v_AgeLife = packUnorm2x16(vec2(age, life) / PACKED_MAX_AGE);
+#else
+  v_Position = position;
+  v_Age = age;
+  v_Life = life;
+  v_Velocity = velocity;
+#endif
+}
 
 void main() {
-  if (i_Age >= i_Life) {
+#ifdef PACKED_LAYOUT
+  vec3 position = u_Origin + i_Position;
+  float age = i_Age * PACKED_MAX_AGE;
+  float life = i_Life * PACKED_MAX_AGE;
+#else
+  vec3 position = i_Position;
+  float age = i_Age;
+  float life = i_Life;
+#endif
+
+  if (age >= life && !u_Spawning) {
+    write_particle(position, age, life, i_Velocity);
+    update_custom();
+  } else if (age >= life) {
     /* Particle has exceeded its lifetime! Time to spawn a new one
        in place of the old one, in accordance with our rules.*/
     
@@ -81,26 +144,29 @@ void main() {
          cos(phi + theta),
          sin(-phi - theta)
      ));
-
-    /* Return the particle to origin. */
-    v_Position = u_Origin;
-
-    /* It's new, so age must be set accordingly.*/
-    v_Age = 0.0;
-    v_Life = i_Life;
+    if (u_Planar) {
+      direction = vec3(cos(theta), sin(theta), 0.0);
+    }
 
     /* Generate final velocity vector. We use the second random value here
        to randomize speed. */
     float speed = (u_MinSpeed + rand.b * (u_MaxSpeed - u_MinSpeed));
 
-    v_Velocity = direction * speed;
+    /* Return the particle to origin. It's new, so age must be set
+       accordingly.*/
+    write_particle(u_Origin, 0.0, life, direction * speed);
+    spawn_custom();
   } else {
     /* Update parameters according to our simple rules.*/
-    v_Position = i_Position + i_Velocity * u_TimeDelta;
-    v_Age = i_Age + u_TimeDelta;
-    v_Life = i_Life;
-    
     //vec2 force = 4.0 * (2.0 * texture(u_ForceField, i_Position).rg - vec2(1.0));
-    v_Velocity = i_Velocity + u_Gravity * u_TimeDelta; // + force * u_TimeDelta;
+    vec3 force = u_Gravity + custom_force(position, age);
+    vec3 velocity = i_Velocity + force * u_TimeDelta;
+    position += i_Velocity * u_TimeDelta;
+    if (u_Planar) {
+      position.z = u_Origin.z;
+      velocity.z = 0.0;
+    }
+    write_particle(position, age + u_TimeDelta, life, velocity);
+    update_custom();
   }
 }
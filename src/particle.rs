@@ -1,45 +1,73 @@
-use crate::{compile_shader, create_buffer, get_uniform, link_program};
-use glam::Vec3;
+use crate::gl::{Buffer, Gl, Program, Texture2D, VertexArray};
+use crate::light::{PointLight, SceneLighting, MAX_LIGHTS};
+use crate::shader::{
+    bind_frame_data, Preprocessor, ProgramInfo, Sampler, ShaderError, UniformBinding,
+};
+use crate::{get_attrib, sample_gradient};
+use glam::{Mat4, Vec3, Vec4};
 use std::default::Default;
-use std::mem::size_of;
 use wasm_bindgen::JsValue;
-use web_sys::{
-    WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlTexture, WebGlUniformLocation,
-    WebGlVertexArrayObject,
+use web_sys::{WebGl2RenderingContext, WebGlProgram};
+
+mod cache;
+mod cpu;
+mod custom;
+mod emitter_type;
+mod layout;
+
+pub use cache::ProgramCache;
+pub use cpu::CpuSimulator;
+pub use custom::CustomAttribute;
+pub use emitter_type::{EmitterType, ShaderSnippets};
+pub use layout::{
+    AttributeFormat, LayoutError, LayoutKind, Particle, ParticleAttribute, ParticleLayout,
+    PACKED_MAX_AGE,
 };
 
 // Contains data needed to update a set of particles; it is a "function" that modifies a
 // `Emitter` instance.
 pub struct UpdateSystem {
-    program: WebGlProgram,
-    rg_noise: WebGlTexture,
-
-    // vertex attribute locations
-    i_pos: u32,
-    i_age: u32,
-    i_life: u32,
-    i_velocity: u32,
-
-    // uniform locations
-    u_timedelta: WebGlUniformLocation,
-    u_rgnoise: WebGlUniformLocation,
-    u_gravity: WebGlUniformLocation,
-    u_origin: WebGlUniformLocation,
-    u_mintheta: WebGlUniformLocation,
-    u_maxtheta: WebGlUniformLocation,
-    u_minspeed: WebGlUniformLocation,
-    u_maxspeed: WebGlUniformLocation,
+    program: Program,
+    rg_noise: Texture2D,
+    // The type the program was generated from
+    emitter_type: EmitterType,
+
+    uniforms: UniformBinding<UpdateUniforms>,
+}
+
+crate::uniforms! {
+    struct UpdateUniforms {
+        time_delta: f32 => "u_TimeDelta",
+        rgb_noise: Sampler => "u_RgbNoise",
+        gravity: Vec3 => "u_Gravity",
+        origin: Vec3 => "u_Origin",
+        min_theta: f32 => "u_MinTheta",
+        max_theta: f32 => "u_MaxTheta",
+        min_speed: f32 => "u_MinSpeed",
+        max_speed: f32 => "u_MaxSpeed",
+        planar: bool => "u_Planar",
+        spawning: bool => "u_Spawning",
+    }
 }
 
 #[derive(Debug)]
 pub struct Emitter {
     options: EmitterOptions,
+    layout: ParticleLayout,
 
     generation: usize,
-    buffers: [WebGlBuffer; 2],
-    vaos: [WebGlVertexArrayObject; 2],
+    population: Population,
+    buffers: [Buffer<u8>; 2],
+    // Vertex arrays reading each buffer: every attribute for the update pass, and only the ones
+    // the render program uses for drawing. Render vertex arrays also read the next position and
+    // age from the other buffer, so drawing can interpolate between the last two steps.
+    vaos: [VertexArray; 2],
+    render_vaos: [VertexArray; 2],
 }
 
+// The most particles an emitter made from JavaScript can have
+pub const MAX_PARTICLES: u32 = 1 << 20;
+
 #[derive(Debug, Copy, Clone)]
 pub struct EmitterOptions {
     // update options
@@ -54,6 +82,117 @@ pub struct EmitterOptions {
     pub max_theta: f32,
     pub min_speed: f32,
     pub max_speed: f32,
+    // Keeps the particles on the plane z = origin.z, for 2D games. Theta is then the angle of a
+    // newborn particle's velocity in that plane.
+    pub planar: bool,
+    // Size of a newborn particle; they shrink to a seventh of it as they age
+    pub point_size: PointSize,
+
+    // lighting options
+    pub light: Option<EmitterLight>,
+    // Whether the particles are shaded by the scene's lights instead of drawn at full brightness
+    pub lit: bool,
+}
+
+// The units of a particle's point size
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PointSize {
+    // Drawing buffer pixels, whatever the distance
+    Pixels(f32),
+    // World units (a tile in a tile map), so particles shrink with distance and scale with zoom
+    World(f32),
+}
+
+impl Default for PointSize {
+    fn default() -> Self {
+        PointSize::Pixels(7.0)
+    }
+}
+
+// Makes an emitter act as a dynamic point light at its origin, e.g. so a fireball lights up the
+// corridor it is flying down.
+#[derive(Debug, Copy, Clone)]
+pub struct EmitterLight {
+    // Light intensity contributed by each live particle
+    pub intensity_per_particle: f32,
+    pub radius: f32,
+}
+
+impl Default for EmitterLight {
+    fn default() -> Self {
+        Self {
+            intensity_per_particle: 0.005,
+            radius: 2.0,
+        }
+    }
+}
+
+// Estimates how many of an emitter's particles are alive, for its light, without reading the
+// particles back from the GPU
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Population {
+    // Whether an update has spawned the particles yet
+    spawned: bool,
+    // Seconds simulated since the emitter stopped spawning particles
+    stopped_for: Option<f32>,
+}
+
+impl Population {
+    fn new() -> Self {
+        Self {
+            spawned: false,
+            stopped_for: None,
+        }
+    }
+
+    fn spawning(&self) -> bool {
+        self.stopped_for.is_none()
+    }
+
+    fn stop(&mut self) {
+        if self.stopped_for.is_none() {
+            self.stopped_for = Some(0.0);
+        }
+    }
+
+    fn advance(&mut self, delta: f32) {
+        match &mut self.stopped_for {
+            None => self.spawned = true,
+            Some(time) => *time += delta,
+        }
+    }
+
+    // Dead particles are respawned on the next update, so while spawning every particle is
+    // alive. Once it stops, the particles are assumed to be spread evenly over their lives.
+    fn live(&self, options: &EmitterOptions) -> u32 {
+        if !self.spawned {
+            return 0;
+        }
+        let fraction = match self.stopped_for {
+            None => 1.0,
+            Some(time) => surviving_fraction(time, options.min_age, options.max_age),
+        };
+        (options.num_particles as f32 * fraction).round() as u32
+    }
+}
+
+// The fraction of particles still alive `elapsed` seconds after spawning stopped, if their lives
+// are spread evenly between `min_life` and `max_life` and their ages evenly over their lives: the
+// average of max(0, 1 - elapsed / life) over the lives.
+fn surviving_fraction(elapsed: f32, min_life: f32, max_life: f32) -> f32 {
+    let (min, max) = (min_life.min(max_life), min_life.max(max_life));
+    if elapsed.is_nan() || elapsed >= max {
+        return 0.0;
+    }
+    if elapsed <= 0.0 {
+        return 1.0;
+    }
+    if max - min < 1e-6 {
+        return 1.0 - elapsed / max;
+    }
+    let shortest = elapsed.max(min);
+    let fraction = ((max - shortest) - elapsed * (max / shortest).ln()) / (max - min);
+    fraction.clamp(0.0, 1.0)
 }
 
 impl Default for EmitterOptions {
@@ -68,218 +207,225 @@ impl Default for EmitterOptions {
             max_theta: std::f32::consts::PI,
             min_speed: 0.5,
             max_speed: 1.0,
+            planar: false,
+            point_size: PointSize::default(),
+            light: None,
+            lit: false,
         }
     }
 }
 
 pub struct Render {
-    program: WebGlProgram,
+    program: Program,
+    // The type the program was generated from
+    emitter_type: EmitterType,
+
+    uniforms: UniformBinding<RenderUniforms>,
+    lighting: UniformBinding<LightingUniforms>,
+    // Only the packed layout's program has these
+    packed: Option<UniformBinding<PackedUniforms>>,
+}
 
-    // vertex attribute locations
-    i_pos: u32,
-    i_age: u32,
-    i_life: u32,
+crate::uniforms! {
+    struct RenderUniforms {
+        gradient: Sampler => "u_Gradient",
+        lit: bool => "u_Lit",
+        point_size: f32 => "u_PointSize",
+        point_size_in_world: bool => "u_PointSizeInWorld",
+    }
+}
 
-    // uniform locations
-    u_projection: WebGlUniformLocation,
-    u_view: WebGlUniformLocation,
-    u_gradient: WebGlUniformLocation,
+// The origin packed particle positions are relative to
+crate::uniforms! {
+    struct PackedUniforms {
+        origin: Vec3 => "u_Origin",
+    }
+}
+
+type RenderBindings = (
+    UniformBinding<RenderUniforms>,
+    UniformBinding<LightingUniforms>,
+    Option<UniformBinding<PackedUniforms>>,
+);
+
+// The uniforms declared by lighting.glsl
+crate::uniforms! {
+    struct LightingUniforms {
+        ambient: Vec3 => "u_Ambient",
+        light_count: i32 => "u_LightCount",
+        light_positions: [Vec3; MAX_LIGHTS] => "u_LightPositions",
+        light_colors: [Vec3; MAX_LIGHTS] => "u_LightColors",
+        light_radii: [f32; MAX_LIGHTS] => "u_LightRadii",
+        use_visibility: bool => "u_UseVisibility",
+        visibility: Sampler => "u_Visibility",
+        world_to_visibility: Mat4 => "u_WorldToVisibility",
+    }
 }
 
 impl UpdateSystem {
-    pub fn new(gl: &WebGl2RenderingContext) -> Result<UpdateSystem, JsValue> {
-        let particle_update_shader = compile_shader(
-            gl,
-            WebGl2RenderingContext::VERTEX_SHADER,
-            include_str!("particle-update.glsl"),
-        )?;
-        let passthru_frag_shader = compile_shader(
-            gl,
-            WebGl2RenderingContext::FRAGMENT_SHADER,
-            include_str!("passthru-frag.glsl"),
-        )?;
-        let program = link_program(
-            gl,
-            &particle_update_shader,
-            &passthru_frag_shader,
-            Some(&["v_Position", "v_Age", "v_Life", "v_Velocity"]),
-        )?;
+    pub fn new(gl: &Gl) -> Result<UpdateSystem, JsValue> {
+        Self::with_layout(gl, ParticleLayout::standard())
+    }
 
-        let rg_noise = gl
-            .create_texture()
-            .ok_or("Could not create texture handle")?;
-        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&rg_noise));
-        gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
-            WebGl2RenderingContext::TEXTURE_2D,
-            0,
-            WebGl2RenderingContext::RGB8 as i32,
+    pub fn with_layout(gl: &Gl, layout: ParticleLayout) -> Result<UpdateSystem, JsValue> {
+        Self::with_type(gl, &EmitterType::new(layout))
+    }
+
+    // Prefer `ProgramCache::update_system`, which only compiles each emitter type once
+    pub fn with_type(gl: &Gl, emitter_type: &EmitterType) -> Result<UpdateSystem, JsValue> {
+        let (program, uniforms) = Self::link(gl, emitter_type)?;
+
+        let rg_noise = Texture2D::new(
+            gl,
             512,
             512,
-            0,
+            WebGl2RenderingContext::RGB8,
             WebGl2RenderingContext::RGB,
             WebGl2RenderingContext::UNSIGNED_BYTE,
             Some(&generate_random_rgb_data(512, 512)),
         )?;
-        gl.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_WRAP_S,
-            WebGl2RenderingContext::MIRRORED_REPEAT as i32,
-        );
-        gl.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_WRAP_T,
-            WebGl2RenderingContext::MIRRORED_REPEAT as i32,
-        );
-        gl.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_MIN_FILTER,
-            WebGl2RenderingContext::NEAREST as i32,
+        rg_noise.set_wrap(
+            WebGl2RenderingContext::MIRRORED_REPEAT,
+            WebGl2RenderingContext::MIRRORED_REPEAT,
         );
-        gl.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
-            WebGl2RenderingContext::TEXTURE_MAG_FILTER,
-            WebGl2RenderingContext::NEAREST as i32,
+        rg_noise.set_filter(
+            WebGl2RenderingContext::NEAREST,
+            WebGl2RenderingContext::NEAREST,
         );
 
         Ok(UpdateSystem {
-            rg_noise,
-
-            i_pos: gl.get_attrib_location(&program, "i_Position") as u32,
-            i_age: gl.get_attrib_location(&program, "i_Age") as u32,
-            i_life: gl.get_attrib_location(&program, "i_Life") as u32,
-            i_velocity: gl.get_attrib_location(&program, "i_Velocity") as u32,
-
-            u_timedelta: get_uniform(gl, &program, "u_TimeDelta")?,
-            u_rgnoise: get_uniform(gl, &program, "u_RgbNoise")?,
-            u_gravity: get_uniform(gl, &program, "u_Gravity")?,
-            u_origin: get_uniform(gl, &program, "u_Origin")?,
-            u_mintheta: get_uniform(gl, &program, "u_MinTheta")?,
-            u_maxtheta: get_uniform(gl, &program, "u_MaxTheta")?,
-            u_minspeed: get_uniform(gl, &program, "u_MinSpeed")?,
-            u_maxspeed: get_uniform(gl, &program, "u_MaxSpeed")?,
-
             program,
+            rg_noise,
+            emitter_type: emitter_type.clone(),
+            uniforms,
         })
     }
 
-    pub fn create_emitter(
-        self: &Self,
-        gl: &WebGl2RenderingContext,
-        options: EmitterOptions,
-    ) -> Result<Emitter, JsValue> {
-        let buffers = [create_buffer(gl)?, create_buffer(gl)?];
-
-        let particle_init_data = generate_initial_particle_data(
-            options.num_particles as i32,
-            options.min_age,
-            options.max_age,
-        );
-        for buffer in &buffers {
-            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
-            unsafe {
-                let vert_array = js_sys::Float32Array::view(&particle_init_data);
-
-                gl.buffer_data_with_array_buffer_view(
-                    WebGl2RenderingContext::ARRAY_BUFFER,
-                    &vert_array,
-                    WebGl2RenderingContext::STATIC_DRAW,
-                );
-            }
-        }
+    // Compiles and links the update program for `emitter_type` from the current shader library
+    fn link(
+        gl: &Gl,
+        emitter_type: &EmitterType,
+    ) -> Result<(Program, UniformBinding<UpdateUniforms>), ShaderError> {
+        let layout = &emitter_type.layout;
+        let varyings: Vec<&str> = layout.varyings().iter().map(String::as_str).collect();
+        let program = Program::link(
+            gl,
+            &emitter_type.update_shader_source()?,
+            &Preprocessor::new().process("passthru-frag.glsl")?,
+            &layout.attribute_bindings(),
+            Some(&varyings),
+        )?;
 
-        let vaos = [
-            gl.create_vertex_array()
-                .ok_or("Could not create vertex array")?,
-            gl.create_vertex_array()
-                .ok_or("Could not create vertex array")?,
-        ];
-
-        gl.use_program(Some(&self.program));
-        for (buffer, vao) in buffers.iter().zip(&vaos) {
-            gl.bind_vertex_array(Some(vao));
-
-            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(buffer));
-
-            let num_components = 3 + 1 + 1 + 3;
-            let stride = (num_components * size_of::<f32>()) as i32;
-
-            gl.enable_vertex_attrib_array(self.i_pos);
-            gl.vertex_attrib_pointer_with_i32(
-                self.i_pos,
-                3,
-                WebGl2RenderingContext::FLOAT,
-                false,
-                stride,
-                0,
-            );
+        check_attributes(gl, program.raw(), layout, false)?;
+        let info = ProgramInfo::reflect(gl, program.raw());
+        let uniforms = UniformBinding::new(gl, program.raw(), &info)?;
+        Ok((program, uniforms))
+    }
 
-            gl.enable_vertex_attrib_array(self.i_age);
-            gl.vertex_attrib_pointer_with_i32(
-                self.i_age,
-                1,
-                WebGl2RenderingContext::FLOAT,
-                false,
-                stride,
-                (3 * size_of::<f32>()) as i32,
-            );
+    // A replacement for `emitter` with its buffers recreated, after the context has been
+    // restored. Its particles start over, as their state only lived on the GPU, but it stays
+    // stopped if it had stopped spawning them.
+    pub fn restore_emitter(&self, gl: &Gl, emitter: &Emitter) -> Result<Emitter, JsValue> {
+        let mut restored = self.create_emitter(gl, emitter.options)?;
+        if !emitter.population.spawning() {
+            restored.population.stop();
+        }
+        Ok(restored)
+    }
 
-            gl.enable_vertex_attrib_array(self.i_life);
-            gl.vertex_attrib_pointer_with_i32(
-                self.i_life,
-                1,
-                WebGl2RenderingContext::FLOAT,
-                false,
-                stride,
-                (4 * size_of::<f32>()) as i32,
-            );
+    // The program's active attributes and uniforms, for diagnostics
+    pub fn program_info(&self, gl: &Gl) -> ProgramInfo {
+        ProgramInfo::reflect(gl, self.program.raw())
+    }
 
-            gl.enable_vertex_attrib_array(self.i_velocity);
-            gl.vertex_attrib_pointer_with_i32(
-                self.i_velocity,
-                3,
-                WebGl2RenderingContext::FLOAT,
-                false,
-                stride,
-                (5 * size_of::<f32>()) as i32,
-            );
+    pub fn create_emitter(&self, gl: &Gl, options: EmitterOptions) -> Result<Emitter, JsValue> {
+        if self.emitter_type.layout.kind() == LayoutKind::Packed && options.max_age > PACKED_MAX_AGE
+        {
+            return Err(format!(
+                "Packed emitters can't have lives longer than {} seconds, not {}",
+                PACKED_MAX_AGE, options.max_age
+            )
+            .into());
+        }
+        let particle_init_data =
+            generate_initial_particle_data(&self.emitter_type.layout, &options, || {
+                js_sys::Math::random() as f32
+            })
+            .ok_or("Too many particles to fit in a buffer")?;
+        let buffer = || {
+            Buffer::with_data(
+                gl,
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                &particle_init_data,
+                WebGl2RenderingContext::STATIC_DRAW,
+            )
+        };
+        let buffers = [buffer()?, buffer()?];
+
+        let vaos = [VertexArray::new(gl)?, VertexArray::new(gl)?];
+        let render_vaos = [VertexArray::new(gl)?, VertexArray::new(gl)?];
+
+        // Attribute locations are fixed by the layout, so the vertex arrays work with any
+        // update or render program for it
+        let _state = gl.save_state();
+        let layout = &self.emitter_type.layout;
+        for (i, buffer) in buffers.iter().enumerate() {
+            buffer.bind();
+
+            vaos[i].bind();
+            layout.bind_attributes(gl, false);
+
+            render_vaos[i].bind();
+            layout.bind_attributes(gl, true);
+            buffers[(i + 1) % 2].bind();
+            layout.bind_next_attributes(gl);
         }
-        // reset state
-        gl.bind_vertex_array(None);
-        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, None);
 
         Ok(Emitter {
             options,
+            layout: self.emitter_type.layout.clone(),
             generation: 0,
+            population: Population::new(),
             buffers,
             vaos,
+            render_vaos,
         })
     }
 
-    pub fn update(self: &Self, gl: &WebGl2RenderingContext, emitter: &mut Emitter, delta: f32) {
+    pub fn update(&self, gl: &Gl, emitter: &mut Emitter, delta: f32) {
         let read = emitter.generation % 2;
         let write = (emitter.generation + 1) % 2;
 
-        gl.use_program(Some(&self.program));
+        let _state = gl.save_state();
+        self.program.bind();
 
-        gl.uniform1f(Some(&self.u_timedelta), delta);
-        gl.uniform3fv_with_f32_array(Some(&self.u_origin), &emitter.options.origin.to_array());
-        gl.uniform3fv_with_f32_array(Some(&self.u_gravity), &emitter.options.gravity.to_array());
-        gl.uniform1f(Some(&self.u_mintheta), emitter.options.min_theta);
-        gl.uniform1f(Some(&self.u_maxtheta), emitter.options.max_theta);
-        gl.uniform1f(Some(&self.u_minspeed), emitter.options.min_speed);
-        gl.uniform1f(Some(&self.u_maxspeed), emitter.options.max_speed);
+        let options = &emitter.options;
+        self.uniforms.upload(
+            gl,
+            &UpdateUniforms {
+                time_delta: delta,
+                rgb_noise: Sampler(0),
+                gravity: options.gravity,
+                origin: options.origin,
+                min_theta: options.min_theta,
+                max_theta: options.max_theta,
+                min_speed: options.min_speed,
+                max_speed: options.max_speed,
+                planar: options.planar,
+                spawning: emitter.population.spawning(),
+            },
+        );
 
         gl.active_texture(WebGl2RenderingContext::TEXTURE0);
-        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.rg_noise));
-        gl.uniform1i(Some(&self.u_rgnoise), 0);
+        self.rg_noise.bind();
 
-        gl.bind_vertex_array(Some(&emitter.vaos[read]));
+        emitter.vaos[read].bind();
 
         gl.enable(WebGl2RenderingContext::RASTERIZER_DISCARD);
         gl.bind_buffer_base(
             WebGl2RenderingContext::TRANSFORM_FEEDBACK_BUFFER,
             0,
-            Some(&emitter.buffers[write]),
+            Some(emitter.buffers[write].raw()),
         );
 
         gl.begin_transform_feedback(WebGl2RenderingContext::POINTS);
@@ -290,50 +436,119 @@ impl UpdateSystem {
         );
         gl.end_transform_feedback();
 
-        gl.disable(WebGl2RenderingContext::RASTERIZER_DISCARD);
+        // The written buffer is read as vertex data next, which isn't allowed while it is still
+        // bound for transform feedback
         gl.bind_buffer_base(WebGl2RenderingContext::TRANSFORM_FEEDBACK_BUFFER, 0, None);
-        gl.bind_vertex_array(None);
 
         emitter.generation += 1;
+        emitter.population.advance(delta);
+    }
+
+    // Runs the emitter in `step`-second steps until every particle has lived a full life, so it
+    // looks as if it had been running all along, e.g. for an effect that should skip straight to
+    // its steady state.
+    pub fn settle(&self, gl: &Gl, emitter: &mut Emitter, step: f32) {
+        let steps = (emitter.options.max_age / step).ceil() as u32 + 1;
+        for _ in 0..steps {
+            self.update(gl, emitter, step);
+        }
     }
 }
 
-impl Render {
-    pub fn new(gl: &WebGl2RenderingContext) -> Result<Self, JsValue> {
-        let vert_shader = compile_shader(
-            gl,
-            WebGl2RenderingContext::VERTEX_SHADER,
-            include_str!("particle-render-vert.glsl"),
-        )?;
-        let frag_shader = compile_shader(
-            gl,
-            WebGl2RenderingContext::FRAGMENT_SHADER,
-            include_str!("particle-render-frag.glsl"),
-        )?;
-        let program = link_program(gl, &vert_shader, &frag_shader, None)?;
+impl Emitter {
+    // Stops respawning particles as they die, e.g. when a spell ends. The live ones finish their
+    // lives, and the emitter's light fades with them.
+    pub fn stop_spawning(&mut self) {
+        self.population.stop();
+    }
 
-        Ok(Self {
-            i_pos: gl.get_attrib_location(&program, "i_Position") as u32,
-            i_age: gl.get_attrib_location(&program, "i_Age") as u32,
-            i_life: gl.get_attrib_location(&program, "i_Life") as u32,
+    pub fn is_spawning(&self) -> bool {
+        self.population.spawning()
+    }
+
+    // An estimate of the particles alive after the last update
+    pub fn live_particles(&self) -> u32 {
+        self.population.live(&self.options)
+    }
 
-            u_projection: get_uniform(gl, &program, "u_Projection")?,
-            u_view: get_uniform(gl, &program, "u_View")?,
-            u_gradient: get_uniform(gl, &program, "u_Gradient")?,
+    // The point light this emitter contributes to the scene, if it was configured to emit light.
+    // The colour is taken from the middle of the gradient the particles are rendered with.
+    pub fn point_light(&self, gradient: &[Vec4]) -> Option<PointLight> {
+        point_light(&self.options, self.live_particles(), gradient)
+    }
+}
 
+fn point_light(options: &EmitterOptions, live: u32, gradient: &[Vec4]) -> Option<PointLight> {
+    let light = options.light?;
+    if live == 0 {
+        return None;
+    }
+
+    Some(PointLight {
+        position: options.origin,
+        color: sample_gradient(gradient, 0.5).truncate(),
+        intensity: light.intensity_per_particle * live as f32,
+        radius: light.radius,
+    })
+}
+
+impl Render {
+    pub fn new(gl: &Gl) -> Result<Self, JsValue> {
+        Self::with_layout(gl, ParticleLayout::standard())
+    }
+
+    pub fn with_layout(gl: &Gl, layout: ParticleLayout) -> Result<Self, JsValue> {
+        Self::with_type(gl, &EmitterType::new(layout))
+    }
+
+    // Prefer `ProgramCache::render`, which only compiles each emitter type once
+    pub fn with_type(gl: &Gl, emitter_type: &EmitterType) -> Result<Self, JsValue> {
+        let (program, (uniforms, lighting, packed)) = Self::link(gl, emitter_type)?;
+        Ok(Self {
             program,
+            emitter_type: emitter_type.clone(),
+            uniforms,
+            lighting,
+            packed,
         })
     }
 
+    // Compiles and links the render program for `emitter_type` from the current shader library
+    fn link(gl: &Gl, emitter_type: &EmitterType) -> Result<(Program, RenderBindings), ShaderError> {
+        let program = Program::link(
+            gl,
+            &emitter_type.render_vertex_shader_source()?,
+            &emitter_type.render_fragment_shader_source()?,
+            &emitter_type.layout.attribute_bindings(),
+            None,
+        )?;
+
+        bind_frame_data(gl, program.raw())?;
+        let info = ProgramInfo::reflect(gl, program.raw());
+        check_attributes(gl, program.raw(), &emitter_type.layout, true)?;
+        let uniforms = UniformBinding::new(gl, program.raw(), &info)?;
+        let lighting = UniformBinding::new(gl, program.raw(), &info)?;
+        let packed = match emitter_type.layout.kind() {
+            LayoutKind::Standard => None,
+            LayoutKind::Packed => Some(UniformBinding::new(gl, program.raw(), &info)?),
+        };
+        Ok((program, (uniforms, lighting, packed)))
+    }
+
+    // The program's active attributes and uniforms, for diagnostics
+    pub fn program_info(&self, gl: &Gl) -> ProgramInfo {
+        ProgramInfo::reflect(gl, self.program.raw())
+    }
+
     pub fn render(
-        self: &Self,
-        gl: &WebGl2RenderingContext,
-        projection: glam::Mat4,
-        view: glam::Mat4,
+        &self,
+        gl: &Gl,
         emitter: &Emitter,
-        gradient: &WebGlTexture,
+        gradient: &Texture2D,
+        lighting: &SceneLighting,
     ) {
-        gl.use_program(Some(&self.program));
+        let _state = gl.save_state();
+        self.program.bind();
 
         // Setup blending
         gl.enable(WebGl2RenderingContext::BLEND);
@@ -343,54 +558,39 @@ impl Render {
         );
 
         // Bind uniforms
-        gl.uniform_matrix4fv_with_f32_array(
-            Some(&self.u_projection),
-            false,
-            &projection.to_cols_array(),
+        let (point_size, point_size_in_world) = match emitter.options.point_size {
+            PointSize::Pixels(size) => (size, false),
+            PointSize::World(size) => (size, true),
+        };
+        self.uniforms.upload(
+            gl,
+            &RenderUniforms {
+                gradient: Sampler(0),
+                lit: emitter.options.lit,
+                point_size,
+                point_size_in_world,
+            },
         );
-        gl.uniform_matrix4fv_with_f32_array(Some(&self.u_view), false, &view.to_cols_array());
+        if let Some(packed) = &self.packed {
+            packed.upload(
+                gl,
+                &PackedUniforms {
+                    origin: emitter.options.origin,
+                },
+            );
+        }
 
         gl.active_texture(WebGl2RenderingContext::TEXTURE0);
-        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(gradient));
-        gl.uniform1i(Some(&self.u_gradient), 0);
+        gradient.bind();
 
-        // Bind particle buffer
-        gl.bind_buffer(
-            WebGl2RenderingContext::ARRAY_BUFFER,
-            Some(&emitter.buffers[(emitter.generation + 1) % 2]),
-        );
-        let num_components = 3 + 1 + 1 + 3;
-        let stride = (num_components * size_of::<f32>()) as i32;
-
-        gl.enable_vertex_attrib_array(self.i_pos);
-        gl.vertex_attrib_pointer_with_i32(
-            self.i_pos,
-            3,
-            WebGl2RenderingContext::FLOAT,
-            false,
-            stride,
-            0,
-        );
-
-        gl.enable_vertex_attrib_array(self.i_age);
-        gl.vertex_attrib_pointer_with_i32(
-            self.i_age,
-            1,
-            WebGl2RenderingContext::FLOAT,
-            false,
-            stride,
-            (3 * size_of::<f32>()) as i32,
-        );
+        if emitter.options.lit {
+            self.bind_lighting(gl, lighting);
+        }
 
-        gl.enable_vertex_attrib_array(self.i_life);
-        gl.vertex_attrib_pointer_with_i32(
-            self.i_life,
-            1,
-            WebGl2RenderingContext::FLOAT,
-            false,
-            stride,
-            (4 * size_of::<f32>()) as i32,
-        );
+        // Bind particle buffer. This reads the older of the two states, and the newer one as
+        // `i_Next*`.
+        debug_assert_eq!(self.emitter_type.layout, emitter.layout);
+        emitter.render_vaos[(emitter.generation + 1) % 2].bind();
 
         // Draw particles
         gl.draw_arrays(
@@ -398,10 +598,34 @@ impl Render {
             0,
             emitter.options.num_particles as i32,
         );
+    }
+
+    fn bind_lighting(&self, gl: &Gl, lighting: &SceneLighting) {
+        let lights = &lighting.lights[..lighting.lights.len().min(MAX_LIGHTS)];
+
+        let mut uniforms = LightingUniforms {
+            ambient: lighting.ambient,
+            light_count: lights.len() as i32,
+            light_positions: [Vec3::ZERO; MAX_LIGHTS],
+            light_colors: [Vec3::ZERO; MAX_LIGHTS],
+            light_radii: [0.0; MAX_LIGHTS],
+            use_visibility: lighting.visibility.is_some(),
+            visibility: Sampler(1),
+            world_to_visibility: Mat4::IDENTITY,
+        };
+        for (i, light) in lights.iter().enumerate() {
+            uniforms.light_positions[i] = light.position;
+            uniforms.light_colors[i] = light.color * light.intensity;
+            uniforms.light_radii[i] = light.radius;
+        }
 
-        // Reset bindings
-        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, None);
-        gl.disable(WebGl2RenderingContext::BLEND);
+        if let Some(visibility) = lighting.visibility {
+            gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+            visibility.texture.bind();
+            gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+            uniforms.world_to_visibility = visibility.world_to_texture;
+        }
+        self.lighting.upload(gl, &uniforms);
     }
 }
 
@@ -416,24 +640,116 @@ fn generate_random_rgb_data(width: usize, height: usize) -> Vec<u8> {
     data
 }
 
-fn generate_initial_particle_data(num_parts: i32, min_age: f32, max_age: f32) -> Vec<f32> {
-    let mut data = Vec::new();
-    for _ in 0..num_parts {
-        // position
-        data.push(0.0);
-        data.push(0.0);
-        data.push(0.0);
+// Checks the program has the attributes it needs at the locations `layout` binds them to.
+// Attributes only the update shader reads are skipped for render programs, and custom attributes
+// may be left unused.
+fn check_attributes(
+    gl: &Gl,
+    program: &WebGlProgram,
+    layout: &ParticleLayout,
+    render: bool,
+) -> Result<(), ShaderError> {
+    let custom_start = layout.attributes().len() - layout.custom_attributes().len();
+    for (i, attribute) in layout.attributes().iter().enumerate() {
+        if render && !attribute.render {
+            continue;
+        }
+        let name = format!("i_{}", attribute.name);
+        match get_attrib(gl, program, &name) {
+            Ok(location) if location != i as u32 => {
+                return Err(ShaderError::AttributeLocation {
+                    name,
+                    expected: i as u32,
+                    found: location,
+                })
+            }
+            Ok(_) => {}
+            Err(ShaderError::MissingAttribute { .. }) if i >= custom_start => {}
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(())
+}
 
-        let life = min_age + js_sys::Math::random() as f32 * (max_age - min_age);
+// The contents of a new emitter's particle buffers, or `None` if their size overflows
+fn generate_initial_particle_data(
+    layout: &ParticleLayout,
+    options: &EmitterOptions,
+    mut random: impl FnMut() -> f32,
+) -> Option<Vec<u8>> {
+    let (min_age, max_age) = (options.min_age, options.max_age);
+    let len = (options.num_particles as usize).checked_mul(layout.stride())?;
+    let mut data = vec![0; len];
+    for bytes in data.chunks_exact_mut(layout.stride()) {
+        let life = min_age + random() * (max_age - min_age);
         // set age to max. life + 1 to ensure the particle gets initialized
         // on first invocation of particle update shader
-        data.push(life + 1.0); // age
-        data.push(life); // life
+        let particle = Particle {
+            position: options.origin,
+            age: life + 1.0,
+            life,
+            ..Default::default()
+        };
+        layout.write_particle(bytes, &particle, options.origin);
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // velocity
-        data.push(0.0);
-        data.push(0.0);
-        data.push(0.0);
+    fn light_after(population: &Population, options: &EmitterOptions) -> f32 {
+        point_light(options, population.live(options), &[Vec4::ONE])
+            .map_or(0.0, |light| light.intensity)
+    }
+
+    #[test]
+    fn light_fades_after_spawning_stops() {
+        let options = EmitterOptions {
+            light: Some(EmitterLight::default()),
+            ..Default::default()
+        };
+        let mut population = Population::new();
+        assert_eq!(light_after(&population, &options), 0.0);
+
+        population.advance(0.1);
+        let full = light_after(&population, &options);
+        assert_eq!(population.live(&options), options.num_particles);
+        assert!(full > 0.0);
+
+        population.stop();
+        let mut previous = full;
+        for _ in 0..8 {
+            population.advance(options.max_age / 10.0);
+            let intensity = light_after(&population, &options);
+            assert!(intensity < previous);
+            previous = intensity;
+        }
+        assert!(previous > 0.0);
+
+        population.advance(options.max_age / 5.0);
+        assert_eq!(population.live(&options), 0);
+        assert_eq!(light_after(&population, &options), 0.0);
+    }
+
+    #[test]
+    fn stopped_before_spawning_has_no_particles() {
+        let options = EmitterOptions::default();
+        let mut population = Population::new();
+        population.stop();
+        population.advance(0.1);
+        assert_eq!(population.live(&options), 0);
+    }
+
+    #[test]
+    fn surviving_fraction_covers_the_longest_life() {
+        assert_eq!(surviving_fraction(0.0, 0.3, 0.9), 1.0);
+        assert_eq!(surviving_fraction(0.9, 0.3, 0.9), 0.0);
+        assert_eq!(surviving_fraction(f32::NAN, 0.3, 0.9), 0.0);
+        assert!((surviving_fraction(0.25, 0.5, 0.5) - 0.5).abs() < 1e-6);
+        let early = surviving_fraction(0.2, 0.3, 0.9);
+        let late = surviving_fraction(0.6, 0.3, 0.9);
+        assert!(1.0 > early && early > late && late > 0.0);
     }
-    data
 }
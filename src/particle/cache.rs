@@ -0,0 +1,50 @@
+use super::emitter_type::EmitterType;
+use super::{Render, UpdateSystem};
+use crate::gl::Gl;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsValue;
+
+// Compiled particle programs, keyed by a hash of their generated shader sources, so every emitter
+// type is only compiled once and types that end up with the same shaders share programs.
+#[derive(Default)]
+pub struct ProgramCache {
+    update: HashMap<u64, Rc<UpdateSystem>>,
+    render: HashMap<u64, Rc<Render>>,
+}
+
+impl ProgramCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update_system(
+        &mut self,
+        gl: &Gl,
+        emitter_type: &EmitterType,
+    ) -> Result<Rc<UpdateSystem>, JsValue> {
+        let hash = emitter_type.update_hash()?;
+        if let Some(system) = self.update.get(&hash) {
+            return Ok(system.clone());
+        }
+        let system = Rc::new(UpdateSystem::with_type(gl, emitter_type)?);
+        self.update.insert(hash, system.clone());
+        Ok(system)
+    }
+
+    pub fn render(&mut self, gl: &Gl, emitter_type: &EmitterType) -> Result<Rc<Render>, JsValue> {
+        let hash = emitter_type.render_hash()?;
+        if let Some(render) = self.render.get(&hash) {
+            return Ok(render.clone());
+        }
+        let render = Rc::new(Render::with_type(gl, emitter_type)?);
+        self.render.insert(hash, render.clone());
+        Ok(render)
+    }
+
+    // Drops every program, e.g. once the context has been lost; they are rebuilt on next use
+    pub fn clear(&mut self) {
+        self.update.clear();
+        self.render.clear();
+    }
+}
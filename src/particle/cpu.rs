@@ -0,0 +1,137 @@
+use super::layout::{Particle, ParticleLayout};
+use super::{generate_initial_particle_data, EmitterOptions};
+use glam::{vec3, Vec3};
+
+// Width and height of the noise texture sampled by the update shader
+const NOISE_SIZE: usize = 512;
+
+// Runs the same simulation as `particle-update.glsl` on the CPU, reading and writing particle
+// buffers in the byte layout the GPU uses. Useful for comparing layouts without a GL context.
+// Custom attributes and shader snippets are not simulated.
+pub struct CpuSimulator {
+    layout: ParticleLayout,
+    options: EmitterOptions,
+    noise: Vec<Vec3>,
+
+    generation: usize,
+    buffers: [Vec<u8>; 2],
+}
+
+impl CpuSimulator {
+    pub fn new(layout: ParticleLayout, options: EmitterOptions, seed: u32) -> Self {
+        let mut rng = XorShift(seed.max(1));
+
+        let noise = (0..NOISE_SIZE * NOISE_SIZE)
+            .map(|_| {
+                // quantized the same way as the 8-bit noise texture
+                let mut channel = || (rng.next_f32() * 255.0) as u8 as f32 / 255.0;
+                vec3(channel(), channel(), channel())
+            })
+            .collect();
+
+        let data = generate_initial_particle_data(&layout, &options, || rng.next_f32())
+            .expect("too many particles to fit in a buffer");
+
+        Self {
+            layout,
+            options,
+            noise,
+            generation: 0,
+            buffers: [data.clone(), data],
+        }
+    }
+
+    pub fn layout(&self) -> &ParticleLayout {
+        &self.layout
+    }
+
+    // The particle buffer holding the most recent state
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffers[self.generation % 2]
+    }
+
+    pub fn particles(&self) -> impl Iterator<Item = Particle> + '_ {
+        self.buffer()
+            .chunks_exact(self.layout.stride())
+            .map(move |bytes| self.layout.read_particle(bytes, self.options.origin))
+    }
+
+    pub fn step(&mut self, delta: f32) {
+        let read = self.generation % 2;
+        let stride = self.layout.stride();
+
+        let (first, second) = self.buffers.split_at_mut(1);
+        let (input, output) = if read == 0 {
+            (&first[0], &mut second[0])
+        } else {
+            (&second[0], &mut first[0])
+        };
+
+        for (id, (input, output)) in input
+            .chunks_exact(stride)
+            .zip(output.chunks_exact_mut(stride))
+            .enumerate()
+        {
+            let origin = self.options.origin;
+            let particle = self.layout.read_particle(input, origin);
+            let particle = update_particle(&self.options, &self.noise, id, particle, delta);
+            self.layout.write_particle(output, &particle, origin);
+        }
+
+        self.generation += 1;
+    }
+}
+
+// Mirrors `main` in `particle-update.glsl`
+fn update_particle(
+    options: &EmitterOptions,
+    noise: &[Vec3],
+    id: usize,
+    particle: Particle,
+    delta: f32,
+) -> Particle {
+    if particle.age >= particle.life {
+        let rand = noise[id % (NOISE_SIZE * NOISE_SIZE)];
+
+        let theta = options.min_theta + rand.x * (options.max_theta - options.min_theta);
+        let phi = options.min_theta + rand.y * (options.max_theta - options.min_theta);
+        let direction = if options.planar {
+            vec3(theta.cos(), theta.sin(), 0.0)
+        } else {
+            vec3(theta.cos(), (phi + theta).cos(), (-phi - theta).sin()).normalize()
+        };
+        let speed = options.min_speed + rand.z * (options.max_speed - options.min_speed);
+
+        Particle {
+            position: options.origin,
+            age: 0.0,
+            life: particle.life,
+            velocity: direction * speed,
+        }
+    } else {
+        let mut position = particle.position + particle.velocity * delta;
+        let mut velocity = particle.velocity + options.gravity * delta;
+        if options.planar {
+            position.z = options.origin.z;
+            velocity.z = 0.0;
+        }
+        Particle {
+            position,
+            age: particle.age + delta,
+            life: particle.life,
+            velocity,
+        }
+    }
+}
+
+// Small deterministic generator so the simulator doesn't depend on `Math.random`
+struct XorShift(u32);
+
+impl XorShift {
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1 << 24) as f32
+    }
+}
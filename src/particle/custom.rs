@@ -0,0 +1,150 @@
+use super::layout::ParticleLayout;
+
+// An extra per-particle attribute an emitter can ask for on top of position, age, life and
+// velocity. Custom attributes are stored as 32-bit floats after the built-in ones, are written
+// back by the update shader like the rest of the particle, and are visible to the update shader
+// as `i_<Name>` and to the render shaders as `i_<Name>`/`v_<Name>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CustomAttribute {
+    // Rotation in radians, randomized on spawn and advanced by `AngularVelocity` if the layout
+    // has it
+    Rotation,
+    // Radians per second, randomized on spawn between -PI and PI
+    AngularVelocity,
+    // A random value in [0, 1] chosen on spawn, e.g. for varying colours between particles
+    ColorSeed,
+    // Multiplier for the rendered particle size, randomized on spawn between 0.5 and 1.5
+    Size,
+    // A vec4 named by the emitter; zero on spawn and left untouched by the built-in update, so
+    // custom update code can use it for anything
+    Custom(String),
+}
+
+impl CustomAttribute {
+    pub fn name(&self) -> &str {
+        match self {
+            CustomAttribute::Rotation => "Rotation",
+            CustomAttribute::AngularVelocity => "AngularVelocity",
+            CustomAttribute::ColorSeed => "ColorSeed",
+            CustomAttribute::Size => "Size",
+            CustomAttribute::Custom(name) => name,
+        }
+    }
+
+    pub fn components(&self) -> usize {
+        match self {
+            CustomAttribute::Custom(_) => 4,
+            _ => 1,
+        }
+    }
+
+    pub fn glsl_type(&self) -> &'static str {
+        match self {
+            CustomAttribute::Custom(_) => "vec4",
+            _ => "float",
+        }
+    }
+
+    // Defined in all particle shaders when the layout has this attribute
+    pub fn define(&self) -> Option<&'static str> {
+        match self {
+            CustomAttribute::Rotation => Some("HAS_ROTATION"),
+            CustomAttribute::AngularVelocity => Some("HAS_ANGULAR_VELOCITY"),
+            CustomAttribute::ColorSeed => Some("HAS_COLOR_SEED"),
+            CustomAttribute::Size => Some("HAS_SIZE"),
+            CustomAttribute::Custom(_) => None,
+        }
+    }
+
+    // GLSL expression for the value of a newborn particle. `rand` is a vec3 of random values
+    // that is different for each custom attribute.
+    fn spawn_value(&self) -> &'static str {
+        match self {
+            CustomAttribute::Rotation => "rand.r * 6.2831853",
+            CustomAttribute::AngularVelocity => "(rand.r * 2.0 - 1.0) * 3.1415927",
+            CustomAttribute::ColorSeed => "rand.r",
+            CustomAttribute::Size => "0.5 + rand.r",
+            CustomAttribute::Custom(_) => "vec4(0.0)",
+        }
+    }
+
+    // GLSL expression for the value after an update step
+    fn update_value(&self, layout: &ParticleLayout) -> String {
+        match self {
+            CustomAttribute::Rotation
+                if layout
+                    .custom_attributes()
+                    .contains(&CustomAttribute::AngularVelocity) =>
+            {
+                String::from("i_Rotation + i_AngularVelocity * u_TimeDelta")
+            }
+            _ => format!("i_{}", self.name()),
+        }
+    }
+}
+
+// Whether `name` can be declared in GLSL: an identifier that isn't one GLSL reserves, which start
+// with `gl_` or contain `__`
+pub(crate) fn is_glsl_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("gl_")
+        && !name.contains("__")
+}
+
+// Declarations and functions inserted into `particle-update.glsl`
+pub(crate) fn update_shader_code(layout: &ParticleLayout) -> String {
+    let mut code = String::new();
+    for attribute in layout.custom_attributes() {
+        let ty = attribute.glsl_type();
+        code += &format!("in {} i_{};\n", ty, attribute.name());
+        code += &format!("out {} v_{};\n", ty, attribute.name());
+    }
+
+    code += "\nvoid spawn_custom() {\n";
+    for (i, attribute) in layout.custom_attributes().iter().enumerate() {
+        code += &format!(
+            "  {{\n    vec3 rand = random3({});\n    v_{} = {};\n  }}\n",
+            i + 1,
+            attribute.name(),
+            attribute.spawn_value()
+        );
+    }
+    code += "}\n\nvoid update_custom() {\n";
+    for attribute in layout.custom_attributes() {
+        code += &format!(
+            "  v_{} = {};\n",
+            attribute.name(),
+            attribute.update_value(layout)
+        );
+    }
+    code += "}\n";
+    code
+}
+
+// Declarations and functions inserted into `particle-render-vert.glsl`
+pub(crate) fn render_vertex_shader_code(layout: &ParticleLayout) -> String {
+    let mut code = String::new();
+    for attribute in layout.custom_attributes() {
+        let ty = attribute.glsl_type();
+        code += &format!("in {} i_{};\n", ty, attribute.name());
+        code += &format!("out {} v_{};\n", ty, attribute.name());
+    }
+
+    code += "\nvoid forward_custom() {\n";
+    for attribute in layout.custom_attributes() {
+        code += &format!("  v_{0} = i_{0};\n", attribute.name());
+    }
+    code += "}\n";
+    code
+}
+
+// Declarations inserted into `particle-render-frag.glsl`
+pub(crate) fn render_fragment_shader_code(layout: &ParticleLayout) -> String {
+    let mut code = String::new();
+    for attribute in layout.custom_attributes() {
+        code += &format!("in {} v_{};\n", attribute.glsl_type(), attribute.name());
+    }
+    code
+}
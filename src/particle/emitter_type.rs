@@ -0,0 +1,133 @@
+use super::custom;
+use super::layout::ParticleLayout;
+use crate::light::MAX_LIGHTS;
+use crate::shader::{Preprocessor, ShaderError, ShaderSource};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// User GLSL injected into the particle shaders at their hook points, to change how a kind of
+// particle behaves without editing the shared shaders. Snippets can read the particle's inputs
+// (`i_Position`, custom attributes, ...) and the update shader's uniforms. In the packed layout
+// `i_Position` is relative to `u_Origin`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderSnippets {
+    // Defines `vec3 custom_force(vec3 pos, float age)` in the update shader, where `pos` is in
+    // world space. The result is added to the emitter's gravity. Defaults to no force.
+    pub custom_force: Option<String>,
+    // Defines `vec4 custom_color(vec4 base, float t)` in the render fragment shader, where `base`
+    // is the colour sampled from the gradient and `t` is age / life. Defaults to returning
+    // `base`.
+    pub custom_color: Option<String>,
+}
+
+const DEFAULT_CUSTOM_FORCE: &str = "vec3 custom_force(vec3 pos, float age) {
+  return vec3(0.0);
+}
+";
+
+const DEFAULT_CUSTOM_COLOR: &str = "vec4 custom_color(vec4 base, float t) {
+  return base;
+}
+";
+
+// Everything that determines the particle shaders an emitter needs. Emitters of the same type
+// share their `UpdateSystem` and `Render` (see `ProgramCache`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmitterType {
+    pub layout: ParticleLayout,
+    pub snippets: ShaderSnippets,
+}
+
+impl Default for EmitterType {
+    fn default() -> Self {
+        Self::new(ParticleLayout::standard())
+    }
+}
+
+impl EmitterType {
+    pub fn new(layout: ParticleLayout) -> Self {
+        Self {
+            layout,
+            snippets: ShaderSnippets::default(),
+        }
+    }
+
+    pub fn with_custom_force(mut self, source: impl Into<String>) -> Self {
+        self.snippets.custom_force = Some(source.into());
+        self
+    }
+
+    pub fn with_custom_color(mut self, source: impl Into<String>) -> Self {
+        self.snippets.custom_color = Some(source.into());
+        self
+    }
+
+    pub fn update_shader_source(&self) -> Result<ShaderSource, ShaderError> {
+        let custom_force = self
+            .snippets
+            .custom_force
+            .as_deref()
+            .unwrap_or(DEFAULT_CUSTOM_FORCE);
+        Preprocessor::new()
+            .defines(self.layout.defines())
+            .hook(
+                "custom_attributes",
+                custom::update_shader_code(&self.layout),
+            )
+            .hook("custom_force", custom_force)
+            .process("particle-update.glsl")
+    }
+
+    pub fn render_vertex_shader_source(&self) -> Result<ShaderSource, ShaderError> {
+        Preprocessor::new()
+            .defines(self.layout.defines())
+            .hook(
+                "custom_attributes",
+                custom::render_vertex_shader_code(&self.layout),
+            )
+            .process("particle-render-vert.glsl")
+    }
+
+    pub fn render_fragment_shader_source(&self) -> Result<ShaderSource, ShaderError> {
+        let custom_color = self
+            .snippets
+            .custom_color
+            .as_deref()
+            .unwrap_or(DEFAULT_CUSTOM_COLOR);
+        Preprocessor::new()
+            .define("MAX_LIGHTS", MAX_LIGHTS.to_string())
+            .defines(self.layout.defines())
+            .hook(
+                "custom_attributes",
+                custom::render_fragment_shader_code(&self.layout),
+            )
+            .hook("custom_color", custom_color)
+            .process("particle-render-frag.glsl")
+    }
+
+    // Key for the update program; types whose update shaders are identical share it
+    pub(crate) fn update_hash(&self) -> Result<u64, ShaderError> {
+        Ok(hash_sources(
+            &[&self.update_shader_source()?.source],
+            self.layout.varyings(),
+        ))
+    }
+
+    // Key for the render program; types whose render shaders are identical share it
+    pub(crate) fn render_hash(&self) -> Result<u64, ShaderError> {
+        Ok(hash_sources(
+            &[
+                &self.render_vertex_shader_source()?.source,
+                &self.render_fragment_shader_source()?.source,
+            ],
+            &[],
+        ))
+    }
+}
+
+fn hash_sources(sources: &[&str], varyings: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    sources.hash(&mut hasher);
+    varyings.hash(&mut hasher);
+    hasher.finish()
+}
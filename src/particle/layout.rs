@@ -0,0 +1,602 @@
+use super::custom::{is_glsl_identifier, CustomAttribute};
+use glam::Vec3;
+use std::fmt;
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+// Ages and lifetimes in the packed layout are stored as unsigned shorts normalized over this
+// many seconds, so packed emitters can't have longer lives.
+pub const PACKED_MAX_AGE: f32 = 16.0;
+
+// The attributes the render shader also reads from the newer state, as `i_Next<name>`
+const NEXT_ATTRIBUTES: [&str; 2] = ["Position", "Age"];
+
+// Names the particle shaders already use as `i_<name>` or `v_<name>` besides the layout's
+// attributes
+const RESERVED_NAMES: [&str; 7] = [
+    "Position",
+    "Age",
+    "Life",
+    "Velocity",
+    "WorldPosition",
+    "NextPosition",
+    "NextAge",
+];
+
+// How a single component of a particle attribute is stored in the particle buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AttributeFormat {
+    Float,
+    HalfFloat,
+    // Unsigned short normalized to [0, 1]
+    UnsignedShortNorm,
+}
+
+impl AttributeFormat {
+    pub fn size(self) -> usize {
+        match self {
+            AttributeFormat::Float => 4,
+            AttributeFormat::HalfFloat | AttributeFormat::UnsignedShortNorm => 2,
+        }
+    }
+
+    pub fn gl_type(self) -> u32 {
+        match self {
+            AttributeFormat::Float => WebGl2RenderingContext::FLOAT,
+            AttributeFormat::HalfFloat => WebGl2RenderingContext::HALF_FLOAT,
+            AttributeFormat::UnsignedShortNorm => WebGl2RenderingContext::UNSIGNED_SHORT,
+        }
+    }
+
+    pub fn normalized(self) -> bool {
+        self == AttributeFormat::UnsignedShortNorm
+    }
+}
+
+// One attribute of a particle, as read by the update and render shaders (`i_<name>`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParticleAttribute {
+    pub name: String,
+    pub components: usize,
+    pub format: AttributeFormat,
+    // Byte offset of the attribute from the start of a particle
+    pub offset: usize,
+    // Whether the render shader reads this attribute
+    pub render: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LayoutKind {
+    // Everything is stored as 32-bit floats
+    Standard,
+    // Position (relative to the emitter's origin) and velocity are stored as half floats and
+    // age/life as normalized unsigned shorts
+    Packed,
+}
+
+// Describes how particles are laid out in an emitter's buffers: the attributes the shaders read,
+// and the varyings the update shader writes back through transform feedback. Both describe the
+// same bytes; they differ for the packed layout, because transform feedback can only capture
+// 32-bit values, so the update shader packs the small fields into `uint`s.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParticleLayout {
+    kind: LayoutKind,
+    attributes: Vec<ParticleAttribute>,
+    custom: Vec<CustomAttribute>,
+    varyings: Vec<String>,
+    stride: usize,
+}
+
+// Why a custom attribute couldn't be added to a layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    // Not a GLSL identifier, or one GLSL reserves (starting with `gl_` or containing `__`)
+    InvalidName(String),
+    // The layout already has an attribute with the name, or the shaders use it for one of theirs
+    NameInUse(String),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::InvalidName(name) => {
+                write!(f, "{:?} can't be used as a GLSL identifier", name)
+            }
+            LayoutError::NameInUse(name) => {
+                write!(f, "Particle attribute name {:?} is already in use", name)
+            }
+        }
+    }
+}
+
+impl From<LayoutError> for JsValue {
+    fn from(error: LayoutError) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+// The CPU-side view of a single particle.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Particle {
+    pub position: Vec3,
+    pub age: f32,
+    pub life: f32,
+    pub velocity: Vec3,
+}
+
+impl ParticleLayout {
+    // 3 + 1 + 1 + 3 floats, 32 bytes per particle
+    pub fn standard() -> Self {
+        let mut layout = Self {
+            kind: LayoutKind::Standard,
+            attributes: Vec::new(),
+            custom: Vec::new(),
+            varyings: Vec::new(),
+            stride: 0,
+        };
+        layout.push_attribute("Position", 3, AttributeFormat::Float, true);
+        layout.push_attribute("Age", 1, AttributeFormat::Float, true);
+        layout.push_attribute("Life", 1, AttributeFormat::Float, true);
+        layout.push_attribute("Velocity", 3, AttributeFormat::Float, false);
+        layout.varyings = ["v_Position", "v_Age", "v_Life", "v_Velocity"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        layout
+    }
+
+    // 3 half floats each for position and velocity and 2 normalized unsigned shorts for age and
+    // life; 16 bytes per particle, half the standard layout. Positions are stored relative to the
+    // emitter's origin, where half floats are precise: to 1/32 of a unit within 64 units of it.
+    pub fn packed() -> Self {
+        let mut layout = Self {
+            kind: LayoutKind::Packed,
+            attributes: Vec::new(),
+            custom: Vec::new(),
+            varyings: Vec::new(),
+            stride: 0,
+        };
+        layout.push_attribute("Position", 3, AttributeFormat::HalfFloat, true);
+        layout.push_attribute("Velocity", 3, AttributeFormat::HalfFloat, false);
+        layout.push_attribute("Age", 1, AttributeFormat::UnsignedShortNorm, true);
+        layout.push_attribute("Life", 1, AttributeFormat::UnsignedShortNorm, true);
+        layout.varyings = [
+            "v_PositionXY",
+            "v_PositionZVelocityX",
+            "v_VelocityYZ",
+            "v_AgeLife",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        layout
+    }
+
+    // Adds a custom attribute after the built-in ones. Fails if its name isn't a usable GLSL
+    // identifier or clashes with an attribute or varying the shaders already have.
+    pub fn with_attribute(mut self, attribute: CustomAttribute) -> Result<Self, LayoutError> {
+        let name = attribute.name();
+        // A leading `_` would make `i_<name>` contain `__`
+        if !is_glsl_identifier(name) || name.starts_with('_') {
+            return Err(LayoutError::InvalidName(name.to_string()));
+        }
+        let in_use = RESERVED_NAMES.contains(&name)
+            || self.attributes.iter().any(|a| a.name == name)
+            || self.varyings.contains(&format!("v_{}", name));
+        if in_use {
+            return Err(LayoutError::NameInUse(name.to_string()));
+        }
+
+        self.push_attribute(name, attribute.components(), AttributeFormat::Float, true);
+        self.varyings.push(format!("v_{}", name));
+        self.custom.push(attribute);
+        Ok(self)
+    }
+
+    fn push_attribute(
+        &mut self,
+        name: &str,
+        components: usize,
+        format: AttributeFormat,
+        render: bool,
+    ) {
+        self.attributes.push(ParticleAttribute {
+            name: name.to_string(),
+            components,
+            format,
+            offset: self.stride,
+            render,
+        });
+        self.stride += components * format.size();
+    }
+
+    pub fn kind(&self) -> LayoutKind {
+        self.kind
+    }
+
+    // Size of a single particle in bytes
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn attributes(&self) -> &[ParticleAttribute] {
+        &self.attributes
+    }
+
+    pub fn custom_attributes(&self) -> &[CustomAttribute] {
+        &self.custom
+    }
+
+    pub fn varyings(&self) -> &[String] {
+        &self.varyings
+    }
+
+    // Preprocessor defines the particle shaders need to read and write this layout
+    pub fn defines(&self) -> Vec<(String, String)> {
+        let mut defines = match self.kind {
+            LayoutKind::Standard => Vec::new(),
+            LayoutKind::Packed => vec![
+                (String::from("PACKED_LAYOUT"), String::new()),
+                (
+                    String::from("PACKED_MAX_AGE"),
+                    format!("{:?}", PACKED_MAX_AGE),
+                ),
+            ],
+        };
+        defines.extend(
+            self.custom
+                .iter()
+                .filter_map(|attribute| attribute.define())
+                .map(|define| (define.to_string(), String::new())),
+        );
+        defines
+    }
+
+    // The `i_`-prefixed shader input of each attribute and the location it is bound to before
+    // linking. Attribute `i` of `attributes()` always lives at location `i`, so vertex arrays
+    // set up for a layout work with every program generated for it.
+    //
+    // The render shader's `i_NextPosition` and `i_NextAge`, read from the other buffer for
+    // interpolating between simulation steps, follow at `next_location`.
+    pub fn attribute_bindings(&self) -> Vec<(u32, String)> {
+        let next = NEXT_ATTRIBUTES
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (self.next_location() + i as u32, format!("i_Next{}", name)));
+        self.attributes
+            .iter()
+            .enumerate()
+            .map(|(i, attribute)| (i as u32, format!("i_{}", attribute.name)))
+            .chain(next)
+            .collect()
+    }
+
+    // The location of `i_NextPosition`; `i_NextAge` is at the one after
+    pub fn next_location(&self) -> u32 {
+        self.attributes.len() as u32
+    }
+
+    // Sets up the attribute pointers of the bound vertex array for the buffer bound to
+    // ARRAY_BUFFER. With `render`, only the attributes the render shaders read are enabled.
+    pub fn bind_attributes(&self, gl: &WebGl2RenderingContext, render: bool) {
+        for (i, attribute) in self.attributes.iter().enumerate() {
+            if render && !attribute.render {
+                continue;
+            }
+            let location = i as u32;
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_pointer_with_i32(
+                location,
+                attribute.components as i32,
+                attribute.format.gl_type(),
+                attribute.format.normalized(),
+                self.stride as i32,
+                attribute.offset as i32,
+            );
+        }
+    }
+
+    // Sets up `i_NextPosition` and `i_NextAge` in the bound vertex array, reading the buffer bound
+    // to ARRAY_BUFFER: the newer of the emitter's two states, when the render attributes come
+    // from the older one.
+    pub fn bind_next_attributes(&self, gl: &WebGl2RenderingContext) {
+        for (i, name) in NEXT_ATTRIBUTES.iter().enumerate() {
+            let attribute = self
+                .attributes
+                .iter()
+                .find(|attribute| attribute.name == *name)
+                .expect("particle layouts always have a position and an age");
+            let location = self.next_location() + i as u32;
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_pointer_with_i32(
+                location,
+                attribute.components as i32,
+                attribute.format.gl_type(),
+                attribute.format.normalized(),
+                self.stride as i32,
+                attribute.offset as i32,
+            );
+        }
+    }
+
+    // Writes the built-in attributes of `particle` into `bytes`, which must be `stride` bytes
+    // long. Custom attributes are left untouched. `origin` is the emitter's, which the packed
+    // layout stores positions relative to.
+    pub fn write_particle(&self, bytes: &mut [u8], particle: &Particle, origin: Vec3) {
+        match self.kind {
+            LayoutKind::Standard => {
+                write_f32s(&mut bytes[0..12], &particle.position.to_array());
+                write_f32s(&mut bytes[12..20], &[particle.age, particle.life]);
+                write_f32s(&mut bytes[20..32], &particle.velocity.to_array());
+            }
+            LayoutKind::Packed => {
+                let position = (particle.position - origin).to_array().map(f32_to_f16);
+                let velocity = particle.velocity.to_array().map(f32_to_f16);
+                let age_life =
+                    [particle.age, particle.life].map(|t| f32_to_unorm16(t / PACKED_MAX_AGE));
+                write_u16s(&mut bytes[0..6], &position);
+                write_u16s(&mut bytes[6..12], &velocity);
+                write_u16s(&mut bytes[12..16], &age_life);
+            }
+        }
+    }
+
+    // Reads the built-in attributes of a particle back from `bytes`, which must be `stride`
+    // bytes long
+    pub fn read_particle(&self, bytes: &[u8], origin: Vec3) -> Particle {
+        match self.kind {
+            LayoutKind::Standard => {
+                let [age, life] = read_f32s::<2>(&bytes[12..20]);
+                Particle {
+                    position: Vec3::from_slice(&read_f32s::<3>(&bytes[0..12])),
+                    age,
+                    life,
+                    velocity: Vec3::from_slice(&read_f32s::<3>(&bytes[20..32])),
+                }
+            }
+            LayoutKind::Packed => {
+                let position = read_u16s::<3>(&bytes[0..6]).map(f16_to_f32);
+                let velocity = read_u16s::<3>(&bytes[6..12]).map(f16_to_f32);
+                let [age, life] =
+                    read_u16s::<2>(&bytes[12..16]).map(|t| unorm16_to_f32(t) * PACKED_MAX_AGE);
+                Particle {
+                    position: origin + Vec3::from_slice(&position),
+                    age,
+                    life,
+                    velocity: Vec3::from_slice(&velocity),
+                }
+            }
+        }
+    }
+}
+
+fn write_f32s(bytes: &mut [u8], values: &[f32]) {
+    for (chunk, value) in bytes.chunks_exact_mut(4).zip(values) {
+        chunk.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn write_u16s(bytes: &mut [u8], values: &[u16]) {
+    for (chunk, value) in bytes.chunks_exact_mut(2).zip(values) {
+        chunk.copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn read_f32s<const N: usize>(bytes: &[u8]) -> [f32; N] {
+    let mut values = [0.0; N];
+    for (value, chunk) in values.iter_mut().zip(bytes.chunks_exact(4)) {
+        *value = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    values
+}
+
+fn read_u16s<const N: usize>(bytes: &[u8]) -> [u16; N] {
+    let mut values = [0; N];
+    for (value, chunk) in values.iter_mut().zip(bytes.chunks_exact(2)) {
+        *value = u16::from_le_bytes([chunk[0], chunk[1]]);
+    }
+    values
+}
+
+fn f32_to_unorm16(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+fn unorm16_to_f32(value: u16) -> f32 {
+    value as f32 / 65535.0
+}
+
+// Converts to an IEEE 754 half float, rounding to nearest with ties to even. Values too large
+// for a half become infinity, values too small become (signed) zero, and NaNs stay NaN.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        // infinity or NaN
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let (half, dropped, shift) = if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        // subnormal half
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - half_exponent) as u32;
+        (mantissa >> shift, mantissa & ((1 << shift) - 1), shift)
+    } else {
+        let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+        (half, mantissa & 0x1fff, 13)
+    };
+    let halfway = 1 << (shift - 1);
+    let round_up = dropped > halfway || (dropped == halfway && half & 1 == 1);
+    // rounding may carry into the exponent, which is the correct result
+    sign | (half + round_up as u32) as u16
+}
+
+pub fn f16_to_f32(value: u16) -> f32 {
+    let sign = ((value & 0x8000) as u32) << 16;
+    let exponent = ((value >> 10) & 0x1f) as u32;
+    let mantissa = (value & 0x03ff) as u32;
+
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // subnormal half, normalize it
+            let mut exponent = 127 - 15 + 1;
+            let mut mantissa = mantissa;
+            while mantissa & 0x0400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | (exponent << 23) | ((mantissa & 0x03ff) << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_layout_is_half_the_standard_one() {
+        assert_eq!(ParticleLayout::standard().stride(), 32);
+        assert_eq!(ParticleLayout::packed().stride(), 16);
+    }
+
+    #[test]
+    fn particles_round_trip() {
+        let origin = Vec3::new(40.0, -3.0, 2.0);
+        let particle = Particle {
+            position: origin + Vec3::new(1.5, -0.25, 3.0),
+            age: 0.5,
+            life: 1.25,
+            velocity: Vec3::new(-7.0, 0.125, 2.5),
+        };
+        for layout in [ParticleLayout::standard(), ParticleLayout::packed()] {
+            let mut bytes = vec![0; layout.stride()];
+            layout.write_particle(&mut bytes, &particle, origin);
+            let read = layout.read_particle(&bytes, origin);
+            assert!(read.position.abs_diff_eq(particle.position, 1e-3));
+            assert!(read.velocity.abs_diff_eq(particle.velocity, 1e-3));
+            assert!((read.age - particle.age).abs() < 1e-3);
+            assert!((read.life - particle.life).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn custom_attributes_follow_the_built_in_ones() {
+        let layout = ParticleLayout::packed()
+            .with_attribute(CustomAttribute::Size)
+            .and_then(|layout| layout.with_attribute(CustomAttribute::Custom("Heat".into())))
+            .unwrap();
+        let size = &layout.attributes()[4];
+        assert_eq!((size.name.as_str(), size.offset), ("Size", 16));
+        assert_eq!(layout.stride(), 16 + 4 + 16);
+        assert_eq!(layout.varyings().last().unwrap(), "v_Heat");
+    }
+
+    #[test]
+    fn custom_attribute_names_are_checked() {
+        let add = |layout: ParticleLayout, name: &str| {
+            layout.with_attribute(CustomAttribute::Custom(name.to_string()))
+        };
+        let invalid = |name: &str| Err(LayoutError::InvalidName(name.to_string()));
+        let in_use = |name: &str| Err(LayoutError::NameInUse(name.to_string()));
+
+        for name in ["", "2fast", "a-b", "gl_Thing", "my__value", "_hidden"] {
+            assert_eq!(add(ParticleLayout::standard(), name), invalid(name));
+        }
+        for name in [
+            "Position",
+            "Age",
+            "Life",
+            "Velocity",
+            "WorldPosition",
+            "NextAge",
+        ] {
+            assert_eq!(add(ParticleLayout::standard(), name), in_use(name));
+            assert_eq!(add(ParticleLayout::packed(), name), in_use(name));
+        }
+        assert_eq!(add(ParticleLayout::packed(), "AgeLife"), in_use("AgeLife"));
+        let layout = add(ParticleLayout::standard(), "Heat").unwrap();
+        assert_eq!(add(layout, "Heat"), in_use("Heat"));
+        assert!(ParticleLayout::standard()
+            .with_attribute(CustomAttribute::Rotation)
+            .unwrap()
+            .with_attribute(CustomAttribute::Rotation)
+            .is_err());
+    }
+
+    #[test]
+    fn halves_round_trip() {
+        for half in 0..=u16::MAX {
+            let value = f16_to_f32(half);
+            if value.is_nan() {
+                assert!(f16_to_f32(f32_to_f16(value)).is_nan());
+            } else {
+                assert_eq!(f32_to_f16(value), half, "{:#06x} ({})", half, value);
+            }
+        }
+    }
+
+    #[test]
+    fn subnormals() {
+        let smallest = 2.0f32.powi(-24);
+        assert_eq!(f32_to_f16(smallest), 0x0001);
+        assert_eq!(f16_to_f32(0x0001), smallest);
+        assert_eq!(f32_to_f16(-smallest), 0x8001);
+        assert_eq!(f16_to_f32(0x03ff), 1023.0 * smallest);
+        // Halfway to the smallest subnormal rounds to even, which is zero
+        assert_eq!(f32_to_f16(smallest / 2.0), 0x0000);
+        assert_eq!(f32_to_f16(smallest * 0.75), 0x0001);
+        assert_eq!(f32_to_f16(smallest / 4.0), 0x0000);
+        assert_eq!(f32_to_f16(-smallest / 4.0), 0x8000);
+    }
+
+    #[test]
+    fn infinities_and_nan() {
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+        assert!(f16_to_f32(f32_to_f16(-f32::NAN)).is_nan());
+    }
+
+    #[test]
+    fn values_too_large_become_infinity() {
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        // Below halfway to the next power of two rounds down to the largest half
+        assert_eq!(f32_to_f16(65519.0), 0x7bff);
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+        assert_eq!(f32_to_f16(-1.0e6), 0xfc00);
+        assert_eq!(f32_to_f16(f32::MAX), 0x7c00);
+    }
+
+    #[test]
+    fn ties_round_to_even() {
+        let ulp = 2.0f32.powi(-10);
+        // 1 + ulp / 2 is halfway between 1 (even) and 1 + ulp (odd)
+        assert_eq!(f32_to_f16(1.0 + ulp / 2.0), 0x3c00);
+        // 1 + 3 ulp / 2 is halfway between 1 + ulp (odd) and 1 + 2 ulp (even)
+        assert_eq!(f32_to_f16(1.0 + 3.0 * ulp / 2.0), 0x3c02);
+        // Just past halfway rounds up
+        assert_eq!(f32_to_f16(1.0 + ulp / 2.0 + ulp / 64.0), 0x3c01);
+        // Rounding up can carry into the exponent
+        assert_eq!(f32_to_f16(2.0 - ulp / 4.0), 0x4000);
+    }
+}
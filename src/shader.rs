@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+mod error;
+mod frame;
+mod reflect;
+#[cfg(feature = "hot-reload")]
+mod reload;
+mod uniforms;
+
+pub use error::{Diagnostic, Severity, ShaderError, ShaderStage};
+pub use frame::{
+    bind_frame_data, FrameData, FrameUniformBuffer, FRAME_DATA_BINDING, FRAME_DATA_BLOCK,
+    FRAME_DATA_CAPACITY, FRAME_DATA_SIZE,
+};
+pub use reflect::{type_name, ActiveVariable, ProgramInfo};
+#[cfg(feature = "hot-reload")]
+pub use reload::{library_generation, reset_library_source, set_library_source};
+pub use uniforms::{Sampler, UniformBinding, UniformField, UniformValue, UniformWriter, Uniforms};
+
+// Every GLSL file that can be compiled or `#include`d, by file name.
+const SHADER_LIBRARY: &[(&str, &str)] = &[
+    ("particle-update.glsl", include_str!("particle-update.glsl")),
+    (
+        "particle-render-vert.glsl",
+        include_str!("particle-render-vert.glsl"),
+    ),
+    (
+        "particle-render-frag.glsl",
+        include_str!("particle-render-frag.glsl"),
+    ),
+    ("passthru-frag.glsl", include_str!("passthru-frag.glsl")),
+    ("tilemap-vert.glsl", include_str!("tilemap-vert.glsl")),
+    ("tilemap-frag.glsl", include_str!("tilemap-frag.glsl")),
+    ("text-vert.glsl", include_str!("text-vert.glsl")),
+    ("text-frag.glsl", include_str!("text-frag.glsl")),
+    ("hash.glsl", include_str!("hash.glsl")),
+    ("color.glsl", include_str!("color.glsl")),
+    ("lighting.glsl", include_str!("lighting.glsl")),
+    ("frame.glsl", include_str!("frame.glsl")),
+];
+
+// The source of the library file `name`. With the `hot-reload` feature, sources pushed with
+// `set_library_source` take precedence over the baked-in ones.
+pub fn library_source(name: &str) -> Option<Cow<'static, str>> {
+    #[cfg(feature = "hot-reload")]
+    if let Some(source) = reload::library_override(name) {
+        return Some(Cow::Owned(source));
+    }
+    SHADER_LIBRARY
+        .iter()
+        .find(|(file, _)| *file == name)
+        .map(|(_, source)| Cow::Borrowed(*source))
+}
+
+// Where a line of preprocessed source came from. `file` is a library file name, or a name in
+// angle brackets for generated code (`<defines>`, `<hook custom_force>`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceLocation {
+    pub file: String,
+    // 1-based, like the line numbers in compile errors
+    pub line: usize,
+}
+
+// The output of the preprocessor: a single GLSL string ready to hand to `compile_shader`, and
+// the origin of each of its lines so compile errors can be reported against the original files.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShaderSource {
+    pub name: String,
+    pub source: String,
+    source_map: Vec<SourceLocation>,
+}
+
+impl ShaderSource {
+    // Maps a 1-based line of the preprocessed source back to where it came from
+    pub fn resolve(&self, line: usize) -> Option<&SourceLocation> {
+        line.checked_sub(1).and_then(|i| self.source_map.get(i))
+    }
+}
+
+// Looks up the source of a file `#include`d by name
+type Library<'a> = dyn Fn(&str) -> Option<Cow<'static, str>> + 'a;
+
+// A small GLSL preprocessor run on the Rust side before compiling. It handles:
+//
+// - `#include "file"`, resolved against the shader library. Each file is included at most once.
+// - `#define`s injected right after the `#version` line, for feature toggles and constants.
+// - `#pragma hook(<name>)` lines, replaced by the generated code given for that hook.
+//
+// Other directives (`#ifdef`, ...) are left for the GLSL compiler, so includes inside disabled
+// blocks are still expanded.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    defines: Vec<(String, String)>,
+    hooks: Vec<(String, String)>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.defines.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn defines<N, V>(mut self, defines: impl IntoIterator<Item = (N, V)>) -> Self
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        for (name, value) in defines {
+            self = self.define(name, value);
+        }
+        self
+    }
+
+    pub fn hook(mut self, name: impl Into<String>, code: impl Into<String>) -> Self {
+        self.hooks.push((name.into(), code.into()));
+        self
+    }
+
+    // Preprocesses the library file `name`
+    pub fn process(&self, name: &str) -> Result<ShaderSource, ShaderError> {
+        self.process_from(name, &library_source)
+    }
+
+    // Preprocesses `name`, looking files up with `library` instead of in the shader library
+    fn process_from(&self, name: &str, library: &Library) -> Result<ShaderSource, ShaderError> {
+        let mut output = ShaderSource {
+            name: name.to_string(),
+            source: String::new(),
+            source_map: Vec::new(),
+        };
+        let mut included = HashSet::new();
+        self.process_file(name, library, &mut output, &mut included, true)
+            .map_err(|message| ShaderError::Preprocess { message })?;
+        Ok(output)
+    }
+
+    fn process_file(
+        &self,
+        name: &str,
+        library: &Library,
+        output: &mut ShaderSource,
+        included: &mut HashSet<String>,
+        root: bool,
+    ) -> Result<(), String> {
+        let source = library(name)
+            .ok_or_else(|| format!("Could not find shader {:?} in the library", name))?;
+        included.insert(name.to_string());
+
+        for (i, line) in source.lines().enumerate() {
+            let location = SourceLocation {
+                file: name.to_string(),
+                line: i + 1,
+            };
+            let trimmed = line.trim();
+
+            if let Some(include) = parse_include(trimmed) {
+                let include =
+                    include.ok_or_else(|| format!("{}:{}: malformed #include", name, i + 1))?;
+                if !included.contains(include) {
+                    self.process_file(include, library, output, included, false)?;
+                }
+            } else if let Some(hook) = parse_hook(trimmed) {
+                if let Some((_, code)) = self.hooks.iter().find(|(name, _)| name == hook) {
+                    let file = format!("<hook {}>", hook);
+                    for (j, line) in code.lines().enumerate() {
+                        push_line(output, line, &file, j + 1);
+                    }
+                }
+            } else if trimmed.starts_with("#version") {
+                if !root {
+                    return Err(format!("{}:{}: #version in an included file", name, i + 1));
+                }
+                push_line(output, line, &location.file, location.line);
+                for (j, (define, value)) in self.defines.iter().enumerate() {
+                    push_line(
+                        output,
+                        &format!("#define {} {}", define, value),
+                        "<defines>",
+                        j + 1,
+                    );
+                }
+            } else {
+                push_line(output, line, &location.file, location.line);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn push_line(output: &mut ShaderSource, line: &str, file: &str, line_number: usize) {
+    output.source.push_str(line);
+    output.source.push('\n');
+    output.source_map.push(SourceLocation {
+        file: file.to_string(),
+        line: line_number,
+    });
+}
+
+// `Some(None)` for an `#include` without a quoted file name
+fn parse_include(line: &str) -> Option<Option<&str>> {
+    let rest = line.strip_prefix("#include")?;
+    Some(
+        rest.trim()
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"')),
+    )
+}
+
+fn parse_hook(line: &str) -> Option<&str> {
+    line.strip_prefix("#pragma hook(")?.strip_suffix(')')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(
+        files: &'static [(&'static str, &'static str)],
+    ) -> impl Fn(&str) -> Option<Cow<'static, str>> {
+        move |name| {
+            files
+                .iter()
+                .find(|(file, _)| *file == name)
+                .map(|(_, source)| Cow::Borrowed(*source))
+        }
+    }
+
+    fn location(file: &str, line: usize) -> SourceLocation {
+        SourceLocation {
+            file: file.to_string(),
+            line,
+        }
+    }
+
+    #[test]
+    fn include_cycles_include_each_file_once() {
+        let files = library(&[
+            (
+                "a.glsl",
+                "#version 300 es\n#include \"b.glsl\"\nvoid a() {}",
+            ),
+            (
+                "b.glsl",
+                "#include \"a.glsl\"\n#include \"b.glsl\"\nvoid b() {}",
+            ),
+        ]);
+        let output = Preprocessor::new().process_from("a.glsl", &files).unwrap();
+        assert_eq!(output.source, "#version 300 es\nvoid b() {}\nvoid a() {}\n");
+    }
+
+    #[test]
+    fn missing_and_malformed_includes_are_errors() {
+        let files = library(&[
+            ("missing.glsl", "#include \"nowhere.glsl\""),
+            ("malformed.glsl", "\n#include nowhere.glsl"),
+            ("nested.glsl", "#include \"version.glsl\""),
+            ("version.glsl", "#version 300 es"),
+        ]);
+        let error = |name| match Preprocessor::new().process_from(name, &files) {
+            Err(ShaderError::Preprocess { message }) => message,
+            other => panic!("expected a preprocess error, got {:?}", other),
+        };
+        assert!(error("missing.glsl").contains("nowhere.glsl"));
+        assert_eq!(
+            error("malformed.glsl"),
+            "malformed.glsl:2: malformed #include"
+        );
+        assert_eq!(
+            error("nested.glsl"),
+            "version.glsl:1: #version in an included file"
+        );
+    }
+
+    #[test]
+    fn defines_follow_the_version_line() {
+        let files = library(&[("main.glsl", "// header\n#version 300 es\nvoid main() {}")]);
+        let output = Preprocessor::new()
+            .define("MAX_LIGHTS", "8")
+            .defines([("PACKED_LAYOUT", "")])
+            .process_from("main.glsl", &files)
+            .unwrap();
+        assert_eq!(
+            output.source,
+            concat!(
+                "// header\n#version 300 es\n",
+                "#define MAX_LIGHTS 8\n#define PACKED_LAYOUT \n",
+                "void main() {}\n"
+            )
+        );
+        assert_eq!(output.resolve(3), Some(&location("<defines>", 1)));
+        assert_eq!(output.resolve(4), Some(&location("<defines>", 2)));
+        assert_eq!(output.resolve(5), Some(&location("main.glsl", 3)));
+    }
+
+    #[test]
+    fn lines_map_back_to_their_files() {
+        let files = library(&[
+            (
+                "main.glsl",
+                "#version 300 es\n#include \"common.glsl\"\n#pragma hook(body)\nvoid main() {}",
+            ),
+            ("common.glsl", "float a;\nfloat b;"),
+        ]);
+        let output = Preprocessor::new()
+            .define("X", "1")
+            .hook("body", "float c;\nfloat d;")
+            .hook("unused", "float e;")
+            .process_from("main.glsl", &files)
+            .unwrap();
+
+        let expected = [
+            location("main.glsl", 1),
+            location("<defines>", 1),
+            location("common.glsl", 1),
+            location("common.glsl", 2),
+            location("<hook body>", 1),
+            location("<hook body>", 2),
+            location("main.glsl", 4),
+        ];
+        assert_eq!(output.source.lines().count(), expected.len());
+        for (i, expected) in expected.iter().enumerate() {
+            assert_eq!(output.resolve(i + 1), Some(expected));
+        }
+        assert_eq!(output.resolve(0), None);
+        assert_eq!(output.resolve(expected.len() + 1), None);
+        assert!(!output.source.contains("float e;"));
+    }
+}
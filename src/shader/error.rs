@@ -0,0 +1,342 @@
+use super::ShaderSource;
+use std::fmt;
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    // A shader type WebGL doesn't have, kept as given
+    Unknown(u32),
+}
+
+impl ShaderStage {
+    pub fn from_gl(shader_type: u32) -> Self {
+        match shader_type {
+            WebGl2RenderingContext::VERTEX_SHADER => ShaderStage::Vertex,
+            WebGl2RenderingContext::FRAGMENT_SHADER => ShaderStage::Fragment,
+            _ => ShaderStage::Unknown(shader_type),
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderStage::Vertex => write!(f, "vertex"),
+            ShaderStage::Fragment => write!(f, "fragment"),
+            ShaderStage::Unknown(shader_type) => write!(f, "unknown ({:#x})", shader_type),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// One entry of a compiler info log, resolved to the original file and line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+    // The offending line of source, if it could be found
+    pub source_line: Option<String>,
+}
+
+impl Diagnostic {
+    // Parses the `ERROR: 0:<line>: <message>` entries of an info log. Entries without a location
+    // (like the "N compilation errors" summary) are skipped; they're still in the raw log.
+    pub fn parse_log(log: &str, source: &ShaderSource) -> Vec<Diagnostic> {
+        log.lines()
+            .filter_map(|line| Self::parse_line(line, source))
+            .collect()
+    }
+
+    fn parse_line(line: &str, source: &ShaderSource) -> Option<Diagnostic> {
+        let (severity, rest) = line.trim().split_once(": ")?;
+        let severity = match severity {
+            "ERROR" => Severity::Error,
+            "WARNING" => Severity::Warning,
+            _ => return None,
+        };
+        let mut parts = rest.splitn(3, ':');
+        let _source_string = parts.next()?;
+        let line_number: usize = parts.next()?.trim().parse().ok()?;
+        let message = parts.next()?.trim().to_string();
+
+        let (file, line) = match source.resolve(line_number) {
+            Some(location) => (location.file.clone(), location.line),
+            None => (source.name.clone(), line_number),
+        };
+        let source_line = source
+            .source
+            .lines()
+            .nth(line_number.checked_sub(1)?)
+            .map(str::to_string);
+
+        Some(Diagnostic {
+            severity,
+            file,
+            line,
+            message,
+            source_line,
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.file, self.line, severity, self.message
+        )?;
+        if let Some(source_line) = &self.source_line {
+            write!(f, "\n{:>6} | {}", self.line, source_line)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShaderError {
+    // The GL object for a shader or program could not be created, e.g. the context is lost
+    ShaderCreate,
+    // A shader source could not be preprocessed, e.g. a missing `#include`
+    Preprocess {
+        message: String,
+    },
+    Compile {
+        stage: ShaderStage,
+        source_name: String,
+        log: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+    Link {
+        log: String,
+    },
+    MissingUniform {
+        name: String,
+    },
+    // A uniform is declared with a different type than the Rust value bound to it
+    UniformType {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    // An array uniform is declared with a different number of elements than the Rust array
+    // bound to it
+    UniformArraySize {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    MissingAttribute {
+        name: String,
+    },
+    // An attribute ended up at a different location than the one it was bound to before linking
+    AttributeLocation {
+        name: String,
+        expected: u32,
+        found: u32,
+    },
+    // A uniform block is too small for the data the Rust side uploads, or too large for the
+    // buffer it is uploaded to
+    UniformBlockSize {
+        name: String,
+        min: usize,
+        max: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::ShaderCreate => write!(f, "Unable to create shader object"),
+            ShaderError::Preprocess { message } => {
+                write!(f, "Failed to preprocess shader: {}", message)
+            }
+            ShaderError::Compile {
+                stage,
+                source_name,
+                log,
+                diagnostics,
+            } => {
+                write!(f, "Failed to compile {} shader {}", stage, source_name)?;
+                if diagnostics.is_empty() {
+                    write!(f, ":\n{}", log)?;
+                }
+                for diagnostic in diagnostics {
+                    write!(f, "\n{}", diagnostic)?;
+                }
+                Ok(())
+            }
+            ShaderError::Link { log } => write!(f, "Failed to link program:\n{}", log),
+            ShaderError::MissingUniform { name } => {
+                write!(f, "Could not get uniform location for {:?}", name)
+            }
+            ShaderError::UniformType {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Uniform {:?} is declared as {} but bound to a {} value",
+                name, found, expected
+            ),
+            ShaderError::UniformArraySize {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Uniform {:?} has {} elements but is bound to {}",
+                name, found, expected
+            ),
+            ShaderError::MissingAttribute { name } => {
+                write!(f, "Could not get attribute location for {:?}", name)
+            }
+            ShaderError::AttributeLocation {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Attribute {:?} is at location {} but was bound to {}",
+                name, found, expected
+            ),
+            ShaderError::UniformBlockSize {
+                name,
+                min,
+                max,
+                found,
+            } if found < min => write!(
+                f,
+                "Uniform block {:?} is {} bytes, too small for the {} bytes uploaded",
+                name, found, min
+            ),
+            ShaderError::UniformBlockSize {
+                name, max, found, ..
+            } => write!(
+                f,
+                "Uniform block {:?} is {} bytes, larger than its {} byte buffer",
+                name, found, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+impl From<ShaderError> for JsValue {
+    fn from(error: ShaderError) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Preprocessor;
+    use super::*;
+    use std::borrow::Cow;
+
+    fn source() -> ShaderSource {
+        let library = |name: &str| match name {
+            "main.glsl" => Some(Cow::Borrowed(
+                "#version 300 es\n#include \"lib.glsl\"\nvoid main() {\n    lib();\n}",
+            )),
+            "lib.glsl" => Some(Cow::Borrowed("float lib() {\n    return missing;\n}")),
+            _ => None,
+        };
+        Preprocessor::new()
+            .process_from("main.glsl", &library)
+            .unwrap()
+    }
+
+    #[test]
+    fn log_lines_resolve_to_the_included_file() {
+        // Line 3 of the preprocessed output is line 2 of lib.glsl
+        let log = "ERROR: 0:3: 'missing' : undeclared identifier\n\
+                   WARNING: 0:6: 'lib' : unused result\n";
+        let diagnostics = Diagnostic::parse_log(log, &source());
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    severity: Severity::Error,
+                    file: "lib.glsl".to_string(),
+                    line: 2,
+                    message: "'missing' : undeclared identifier".to_string(),
+                    source_line: Some("    return missing;".to_string()),
+                },
+                Diagnostic {
+                    severity: Severity::Warning,
+                    file: "main.glsl".to_string(),
+                    line: 4,
+                    message: "'lib' : unused result".to_string(),
+                    source_line: Some("    lib();".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_without_a_location_are_skipped() {
+        let log = "ERROR: 2 compilation errors.  No code generated.\n\
+                   \n\
+                   INFO: 0:1: not a diagnostic\n\
+                   ERROR: 0:x: bad line number\n\
+                   ERROR: 0:0: line zero\n";
+        let diagnostics = Diagnostic::parse_log(log, &source());
+        assert_eq!(diagnostics, vec![]);
+    }
+
+    #[test]
+    fn lines_past_the_source_keep_the_raw_line_number() {
+        let diagnostics = Diagnostic::parse_log("ERROR: 0:99: past the end", &source());
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                severity: Severity::Error,
+                file: "main.glsl".to_string(),
+                line: 99,
+                message: "past the end".to_string(),
+                source_line: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn diagnostics_display_with_their_source_line() {
+        let diagnostics = Diagnostic::parse_log(
+            "ERROR: 0:3: 'missing' : undeclared identifier\nWARNING: 0:99: late",
+            &source(),
+        );
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "lib.glsl:2: error: 'missing' : undeclared identifier\n     2 |     return missing;"
+        );
+        assert_eq!(diagnostics[1].to_string(), "main.glsl:99: warning: late");
+    }
+
+    #[test]
+    fn unknown_stages_keep_their_type() {
+        let stage = ShaderStage::from_gl(0x1234);
+        assert_eq!(stage, ShaderStage::Unknown(0x1234));
+        assert_eq!(stage.to_string(), "unknown (0x1234)");
+        assert_eq!(
+            ShaderStage::from_gl(WebGl2RenderingContext::VERTEX_SHADER),
+            ShaderStage::Vertex
+        );
+    }
+}
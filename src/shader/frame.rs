@@ -0,0 +1,137 @@
+use super::ShaderError;
+use crate::gl::{Buffer, Gl};
+use glam::{Mat4, Vec2, Vec3};
+use wasm_bindgen::JsValue;
+use web_sys::{WebGl2RenderingContext, WebGlProgram};
+
+// The name of the uniform block declared by frame.glsl
+pub const FRAME_DATA_BLOCK: &str = "FrameData";
+
+// The uniform buffer binding point the frame data is bound to. Programs are pointed at it by
+// `bind_frame_data`.
+pub const FRAME_DATA_BINDING: u32 = 0;
+
+// Size of the std140 `FrameData` block: three mat4s, a vec3 padded to 16 bytes, a vec2, two
+// floats, a uint and a float, rounded up to a multiple of 16.
+pub const FRAME_DATA_SIZE: usize = 240;
+
+// Size of the uniform buffer holding the block. Drivers may pad the block beyond its std140 size,
+// and WebGL refuses to draw if the bound buffer is smaller than the block, so leave some room.
+pub const FRAME_DATA_CAPACITY: usize = 256;
+
+// Camera and timing data shared by every program for the duration of a frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameData {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub camera_position: Vec3,
+    // Size of the drawing buffer, in pixels
+    pub viewport_size: Vec2,
+    // Seconds since the first frame
+    pub time: f32,
+    // Seconds since the previous frame
+    pub delta: f32,
+    pub frame_index: u32,
+    // How far the frame is between the last two fixed simulation steps, from 0 to 1
+    pub interpolation: f32,
+}
+
+impl Default for FrameData {
+    fn default() -> Self {
+        Self {
+            view: Mat4::IDENTITY,
+            projection: Mat4::IDENTITY,
+            camera_position: Vec3::ZERO,
+            viewport_size: Vec2::ONE,
+            time: 0.0,
+            delta: 0.0,
+            frame_index: 0,
+            interpolation: 0.0,
+        }
+    }
+}
+
+impl FrameData {
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection * self.view
+    }
+
+    // The contents of the uniform buffer, laid out to match the block in frame.glsl
+    pub fn to_std140(&self) -> Vec<u8> {
+        let mut floats = Vec::with_capacity(FRAME_DATA_SIZE / 4);
+        floats.extend_from_slice(&self.view.to_cols_array());
+        floats.extend_from_slice(&self.projection.to_cols_array());
+        floats.extend_from_slice(&self.view_projection().to_cols_array());
+        floats.extend_from_slice(&self.camera_position.extend(0.0).to_array());
+        floats.extend_from_slice(&self.viewport_size.to_array());
+        floats.push(self.time);
+        floats.push(self.delta);
+
+        let mut bytes: Vec<u8> = floats.iter().flat_map(|f| f.to_le_bytes()).collect();
+        bytes.extend_from_slice(&self.frame_index.to_le_bytes());
+        bytes.extend_from_slice(&self.interpolation.to_le_bytes());
+        bytes.resize(FRAME_DATA_SIZE, 0);
+        bytes
+    }
+}
+
+// The uniform buffer holding the current `FrameData`. Update it once per frame, before drawing;
+// every program bound with `bind_frame_data` then reads from it.
+pub struct FrameUniformBuffer {
+    buffer: Buffer<u8>,
+}
+
+impl FrameUniformBuffer {
+    pub fn new(gl: &Gl) -> Result<Self, JsValue> {
+        let buffer = Buffer::with_len(
+            gl,
+            WebGl2RenderingContext::UNIFORM_BUFFER,
+            FRAME_DATA_CAPACITY,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        )?;
+        Ok(Self { buffer })
+    }
+
+    // Uploads `data` and binds the buffer to `FRAME_DATA_BINDING`
+    pub fn update(&self, gl: &Gl, data: &FrameData) {
+        self.buffer.update(0, &data.to_std140());
+        gl.bind_buffer_base(
+            WebGl2RenderingContext::UNIFORM_BUFFER,
+            FRAME_DATA_BINDING,
+            Some(self.buffer.raw()),
+        );
+    }
+}
+
+// Points the program's `FrameData` block at `FRAME_DATA_BINDING`, checking it can hold what we
+// upload and fits in the buffer. Returns false if the program doesn't use the block.
+pub fn bind_frame_data(
+    gl: &WebGl2RenderingContext,
+    program: &WebGlProgram,
+) -> Result<bool, ShaderError> {
+    let index = gl.get_uniform_block_index(program, FRAME_DATA_BLOCK);
+    if index == WebGl2RenderingContext::INVALID_INDEX {
+        return Ok(false);
+    }
+
+    let size = gl
+        .get_active_uniform_block_parameter(
+            program,
+            index,
+            WebGl2RenderingContext::UNIFORM_BLOCK_DATA_SIZE,
+        )
+        .ok()
+        .and_then(|size| size.as_f64())
+        .unwrap_or(0.0) as usize;
+    if !(FRAME_DATA_SIZE..=FRAME_DATA_CAPACITY).contains(&size) {
+        return Err(ShaderError::UniformBlockSize {
+            name: FRAME_DATA_BLOCK.to_string(),
+            min: FRAME_DATA_SIZE,
+            max: FRAME_DATA_CAPACITY,
+            found: size,
+        });
+    }
+
+    gl.uniform_block_binding(program, index, FRAME_DATA_BINDING);
+    Ok(true)
+}
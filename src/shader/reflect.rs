@@ -0,0 +1,124 @@
+use std::fmt;
+use web_sys::{WebGl2RenderingContext, WebGlActiveInfo, WebGlProgram};
+
+// An active attribute or uniform of a linked program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveVariable {
+    // Uniform arrays are reported with a `[0]` suffix, e.g. `u_LightPositions[0]`
+    pub name: String,
+    // GL type enum, e.g. `FLOAT_VEC3`
+    pub gl_type: u32,
+    // Number of array elements, 1 for non-arrays
+    pub size: i32,
+}
+
+impl ActiveVariable {
+    fn from_info(info: WebGlActiveInfo) -> Self {
+        Self {
+            name: info.name(),
+            gl_type: info.type_(),
+            size: info.size(),
+        }
+    }
+
+    // The name without an array suffix
+    pub fn base_name(&self) -> &str {
+        self.name.strip_suffix("[0]").unwrap_or(&self.name)
+    }
+}
+
+impl fmt::Display for ActiveVariable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", type_name(self.gl_type), self.base_name())?;
+        if self.size > 1 {
+            write!(f, "[{}]", self.size)?;
+        }
+        Ok(())
+    }
+}
+
+// The active attributes and uniforms of a linked program, as reported by GL. Variables the
+// compiler optimized out are not listed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ProgramInfo {
+    pub attributes: Vec<ActiveVariable>,
+    pub uniforms: Vec<ActiveVariable>,
+}
+
+impl ProgramInfo {
+    pub fn reflect(gl: &WebGl2RenderingContext, program: &WebGlProgram) -> Self {
+        let count = |parameter| {
+            gl.get_program_parameter(program, parameter)
+                .as_f64()
+                .unwrap_or(0.0) as u32
+        };
+
+        let attributes = (0..count(WebGl2RenderingContext::ACTIVE_ATTRIBUTES))
+            .filter_map(|i| gl.get_active_attrib(program, i))
+            .map(ActiveVariable::from_info)
+            .collect();
+        let uniforms = (0..count(WebGl2RenderingContext::ACTIVE_UNIFORMS))
+            .filter_map(|i| gl.get_active_uniform(program, i))
+            .map(ActiveVariable::from_info)
+            .collect();
+
+        Self {
+            attributes,
+            uniforms,
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&ActiveVariable> {
+        self.attributes.iter().find(|a| a.base_name() == name)
+    }
+
+    pub fn uniform(&self, name: &str) -> Option<&ActiveVariable> {
+        self.uniforms.iter().find(|u| u.base_name() == name)
+    }
+}
+
+impl fmt::Display for ProgramInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "attributes:")?;
+        for attribute in &self.attributes {
+            writeln!(f, "  {}", attribute)?;
+        }
+        writeln!(f, "uniforms:")?;
+        for uniform in &self.uniforms {
+            writeln!(f, "  {}", uniform)?;
+        }
+        Ok(())
+    }
+}
+
+// The GLSL name of a GL type enum
+pub fn type_name(gl_type: u32) -> &'static str {
+    match gl_type {
+        WebGl2RenderingContext::FLOAT => "float",
+        WebGl2RenderingContext::FLOAT_VEC2 => "vec2",
+        WebGl2RenderingContext::FLOAT_VEC3 => "vec3",
+        WebGl2RenderingContext::FLOAT_VEC4 => "vec4",
+        WebGl2RenderingContext::INT => "int",
+        WebGl2RenderingContext::INT_VEC2 => "ivec2",
+        WebGl2RenderingContext::INT_VEC3 => "ivec3",
+        WebGl2RenderingContext::INT_VEC4 => "ivec4",
+        WebGl2RenderingContext::UNSIGNED_INT => "uint",
+        WebGl2RenderingContext::UNSIGNED_INT_VEC2 => "uvec2",
+        WebGl2RenderingContext::UNSIGNED_INT_VEC3 => "uvec3",
+        WebGl2RenderingContext::UNSIGNED_INT_VEC4 => "uvec4",
+        WebGl2RenderingContext::BOOL => "bool",
+        WebGl2RenderingContext::BOOL_VEC2 => "bvec2",
+        WebGl2RenderingContext::BOOL_VEC3 => "bvec3",
+        WebGl2RenderingContext::BOOL_VEC4 => "bvec4",
+        WebGl2RenderingContext::FLOAT_MAT2 => "mat2",
+        WebGl2RenderingContext::FLOAT_MAT3 => "mat3",
+        WebGl2RenderingContext::FLOAT_MAT4 => "mat4",
+        WebGl2RenderingContext::SAMPLER_2D => "sampler2D",
+        WebGl2RenderingContext::SAMPLER_3D => "sampler3D",
+        WebGl2RenderingContext::SAMPLER_CUBE => "samplerCube",
+        WebGl2RenderingContext::SAMPLER_2D_ARRAY => "sampler2DArray",
+        WebGl2RenderingContext::INT_SAMPLER_2D => "isampler2D",
+        WebGl2RenderingContext::UNSIGNED_INT_SAMPLER_2D => "usampler2D",
+        _ => "<unknown>",
+    }
+}
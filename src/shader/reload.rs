@@ -0,0 +1,35 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+// Shader sources pushed at runtime, replacing the baked-in library files of the same name until
+// the page is reloaded. Only compiled in with the `hot-reload` feature.
+thread_local! {
+    static OVERRIDES: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    static GENERATION: Cell<u32> = const { Cell::new(0) };
+}
+
+// Replaces the library file `name`, or adds it if there isn't one. Programs pick the new source
+// up when they are next built or reloaded.
+pub fn set_library_source(name: &str, source: &str) {
+    OVERRIDES.with(|overrides| {
+        overrides
+            .borrow_mut()
+            .insert(name.to_string(), source.to_string())
+    });
+    GENERATION.with(|generation| generation.set(generation.get().wrapping_add(1)));
+}
+
+// Goes back to the baked-in source for `name`
+pub fn reset_library_source(name: &str) {
+    OVERRIDES.with(|overrides| overrides.borrow_mut().remove(name));
+    GENERATION.with(|generation| generation.set(generation.get().wrapping_add(1)));
+}
+
+// Changes whenever the library does; poll it to know when programs need reloading
+pub fn library_generation() -> u32 {
+    GENERATION.with(Cell::get)
+}
+
+pub(super) fn library_override(name: &str) -> Option<String> {
+    OVERRIDES.with(|overrides| overrides.borrow().get(name).cloned())
+}
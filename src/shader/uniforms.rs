@@ -0,0 +1,319 @@
+use super::{type_name, ActiveVariable, ProgramInfo, ShaderError};
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use std::marker::PhantomData;
+use web_sys::{WebGl2RenderingContext, WebGlProgram, WebGlUniformLocation};
+
+type Gl = WebGl2RenderingContext;
+
+// A Rust value that can be uploaded to a uniform of one of the GLSL types in `GL_TYPES`
+pub trait UniformValue {
+    const GL_TYPES: &'static [u32];
+    // The number of array elements the uniform must have, 1 for non-arrays
+    const SIZE: usize = 1;
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation);
+}
+
+// The texture unit a sampler uniform reads from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Sampler(pub u32);
+
+impl UniformValue for f32 {
+    const GL_TYPES: &'static [u32] = &[Gl::FLOAT];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform1f(Some(location), *self);
+    }
+}
+
+impl UniformValue for i32 {
+    const GL_TYPES: &'static [u32] = &[Gl::INT];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform1i(Some(location), *self);
+    }
+}
+
+impl UniformValue for u32 {
+    const GL_TYPES: &'static [u32] = &[Gl::UNSIGNED_INT];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform1ui(Some(location), *self);
+    }
+}
+
+impl UniformValue for bool {
+    const GL_TYPES: &'static [u32] = &[Gl::BOOL];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform1i(Some(location), *self as i32);
+    }
+}
+
+impl UniformValue for Vec2 {
+    const GL_TYPES: &'static [u32] = &[Gl::FLOAT_VEC2];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform2fv_with_f32_array(Some(location), &self.to_array());
+    }
+}
+
+impl UniformValue for Vec3 {
+    const GL_TYPES: &'static [u32] = &[Gl::FLOAT_VEC3];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform3fv_with_f32_array(Some(location), &self.to_array());
+    }
+}
+
+impl UniformValue for Vec4 {
+    const GL_TYPES: &'static [u32] = &[Gl::FLOAT_VEC4];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform4fv_with_f32_array(Some(location), &self.to_array());
+    }
+}
+
+impl UniformValue for Mat4 {
+    const GL_TYPES: &'static [u32] = &[Gl::FLOAT_MAT4];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform_matrix4fv_with_f32_array(Some(location), false, &self.to_cols_array());
+    }
+}
+
+impl UniformValue for Sampler {
+    const GL_TYPES: &'static [u32] = &[
+        Gl::SAMPLER_2D,
+        Gl::SAMPLER_3D,
+        Gl::SAMPLER_CUBE,
+        Gl::SAMPLER_2D_ARRAY,
+        Gl::INT_SAMPLER_2D,
+        Gl::UNSIGNED_INT_SAMPLER_2D,
+    ];
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform1i(Some(location), self.0 as i32);
+    }
+}
+
+impl<const N: usize> UniformValue for [f32; N] {
+    const GL_TYPES: &'static [u32] = &[Gl::FLOAT];
+    const SIZE: usize = N;
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        gl.uniform1fv_with_f32_array(Some(location), self);
+    }
+}
+
+impl<const N: usize> UniformValue for [Vec3; N] {
+    const GL_TYPES: &'static [u32] = &[Gl::FLOAT_VEC3];
+    const SIZE: usize = N;
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        let values: Vec<f32> = self.iter().flat_map(|v| v.to_array()).collect();
+        gl.uniform3fv_with_f32_array(Some(location), &values);
+    }
+}
+
+impl<const N: usize> UniformValue for [Vec4; N] {
+    const GL_TYPES: &'static [u32] = &[Gl::FLOAT_VEC4];
+    const SIZE: usize = N;
+
+    fn upload(&self, gl: &WebGl2RenderingContext, location: &WebGlUniformLocation) {
+        let values: Vec<f32> = self.iter().flat_map(|v| v.to_array()).collect();
+        gl.uniform4fv_with_f32_array(Some(location), &values);
+    }
+}
+
+// A field of a `Uniforms` struct: the GLSL uniform it is uploaded to and the types and array
+// size it accepts
+#[derive(Debug, Copy, Clone)]
+pub struct UniformField {
+    pub name: &'static str,
+    pub gl_types: &'static [u32],
+    pub size: usize,
+}
+
+impl UniformField {
+    // Checks the program's `active` uniform can take this field's values
+    fn check(&self, active: &ActiveVariable) -> Result<(), ShaderError> {
+        if !self.gl_types.contains(&active.gl_type) {
+            return Err(ShaderError::UniformType {
+                name: self.name.to_string(),
+                expected: type_name(self.gl_types[0]),
+                found: type_name(active.gl_type),
+            });
+        }
+        if usize::try_from(active.size) != Ok(self.size) {
+            return Err(ShaderError::UniformArraySize {
+                name: self.name.to_string(),
+                expected: self.size,
+                found: active.size.max(0) as usize,
+            });
+        }
+        Ok(())
+    }
+}
+
+// A struct of uniform values that is uploaded in one call. Implement it with the `uniforms!`
+// macro rather than by hand.
+pub trait Uniforms {
+    const FIELDS: &'static [UniformField];
+
+    // Uploads every field, in the order of `FIELDS`
+    fn upload(&self, writer: &mut UniformWriter);
+}
+
+pub struct UniformWriter<'a> {
+    gl: &'a WebGl2RenderingContext,
+    locations: std::slice::Iter<'a, WebGlUniformLocation>,
+}
+
+impl<'a> UniformWriter<'a> {
+    pub fn write<T: UniformValue>(&mut self, value: &T) {
+        let location = self
+            .locations
+            .next()
+            .expect("more uniform values written than declared");
+        value.upload(self.gl, location);
+    }
+}
+
+// The locations of a `Uniforms` struct's fields in a particular program, checked against the
+// program's active uniforms when created.
+pub struct UniformBinding<T> {
+    locations: Vec<WebGlUniformLocation>,
+    marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Uniforms> UniformBinding<T> {
+    pub fn new(
+        gl: &WebGl2RenderingContext,
+        program: &WebGlProgram,
+        info: &ProgramInfo,
+    ) -> Result<Self, ShaderError> {
+        let mut locations = Vec::with_capacity(T::FIELDS.len());
+        for field in T::FIELDS {
+            let missing = || ShaderError::MissingUniform {
+                name: field.name.to_string(),
+            };
+            field.check(info.uniform(field.name).ok_or_else(missing)?)?;
+            locations.push(
+                gl.get_uniform_location(program, field.name)
+                    .ok_or_else(missing)?,
+            );
+        }
+
+        Ok(Self {
+            locations,
+            marker: PhantomData,
+        })
+    }
+
+    // Uploads `values` to the program currently in use, which must be the one this binding was
+    // created for
+    pub fn upload(&self, gl: &WebGl2RenderingContext, values: &T) {
+        values.upload(&mut UniformWriter {
+            gl,
+            locations: self.locations.iter(),
+        });
+    }
+}
+
+// Declares a struct of uniform values and implements `Uniforms` for it, mapping each field to
+// the GLSL uniform named after the `=>`:
+//
+//     uniforms! {
+//         pub struct CameraUniforms {
+//             pub projection: Mat4 => "u_Projection",
+//             pub view: Mat4 => "u_View",
+//         }
+//     }
+//
+// Upload it with a `UniformBinding<CameraUniforms>` created for the program.
+#[macro_export]
+macro_rules! uniforms {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident: $ty:ty => $glsl:literal),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $ty),*
+        }
+
+        impl $crate::shader::Uniforms for $name {
+            const FIELDS: &'static [$crate::shader::UniformField] = &[
+                $($crate::shader::UniformField {
+                    name: $glsl,
+                    gl_types: <$ty as $crate::shader::UniformValue>::GL_TYPES,
+                    size: <$ty as $crate::shader::UniformValue>::SIZE,
+                }),*
+            ];
+
+            fn upload(&self, writer: &mut $crate::shader::UniformWriter) {
+                $(writer.write(&self.$field);)*
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active(name: &str, gl_type: u32, size: i32) -> ActiveVariable {
+        ActiveVariable {
+            name: name.to_string(),
+            gl_type,
+            size,
+        }
+    }
+
+    fn field<T: UniformValue>(name: &'static str) -> UniformField {
+        UniformField {
+            name,
+            gl_types: T::GL_TYPES,
+            size: T::SIZE,
+        }
+    }
+
+    #[test]
+    fn arrays_must_match_the_declared_size() {
+        let uniform = active("u_x[0]", Gl::FLOAT, 4);
+        assert_eq!(field::<[f32; 4]>("u_x").check(&uniform), Ok(()));
+        for (field, expected) in [
+            (field::<[f32; 8]>("u_x"), 8),
+            (field::<[f32; 2]>("u_x"), 2),
+            (field::<f32>("u_x"), 1),
+        ] {
+            assert_eq!(
+                field.check(&uniform),
+                Err(ShaderError::UniformArraySize {
+                    name: "u_x".to_string(),
+                    expected,
+                    found: 4,
+                })
+            );
+        }
+        let vectors = active("u_v[0]", Gl::FLOAT_VEC3, 3);
+        assert!(field::<[Vec3; 3]>("u_v").check(&vectors).is_ok());
+        assert!(field::<[Vec3; 4]>("u_v").check(&vectors).is_err());
+    }
+
+    #[test]
+    fn types_are_checked_before_sizes() {
+        let uniform = active("u_x[0]", Gl::FLOAT_VEC4, 4);
+        assert!(matches!(
+            field::<[f32; 8]>("u_x").check(&uniform),
+            Err(ShaderError::UniformType { .. })
+        ));
+        assert!(field::<[Vec4; 4]>("u_x").check(&uniform).is_ok());
+        assert!(field::<Mat4>("u_m")
+            .check(&active("u_m", Gl::FLOAT_MAT4, 1))
+            .is_ok());
+    }
+}
@@ -0,0 +1,13 @@
+#version 300 es
+precision mediump float;
+
+uniform sampler2D u_Atlas;
+
+in highp vec2 v_TexCoord;
+in vec4 v_Color;
+
+out vec4 o_FragColor;
+
+void main() {
+  o_FragColor = v_Color * texture(u_Atlas, v_TexCoord);
+}
@@ -0,0 +1,33 @@
+#version 300 es
+precision highp float;
+
+#include "frame.glsl"
+
+/* The font's image */
+uniform sampler2D u_Atlas;
+/* Drawing buffer pixels per CSS pixel */
+uniform float u_PixelRatio;
+
+/* Where the glyph goes: x, y, width and height in CSS pixels from the top
+   left of the canvas */
+in vec4 i_Rect;
+/* Where it comes from: x, y, width and height in pixels of the atlas */
+in vec4 i_Source;
+in vec4 i_Color;
+
+out vec2 v_TexCoord;
+out vec4 v_Color;
+
+void main() {
+  /* A triangle strip over the glyph: (0, 0), (1, 0), (0, 1), (1, 1), with y
+     going down like on the screen and in the atlas */
+  vec2 corner = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+
+  /* Glyphs start on whole pixels so they stay crisp */
+  vec2 pixel = floor(i_Rect.xy * u_PixelRatio + 0.5) + corner * i_Rect.zw * u_PixelRatio;
+  vec2 ndc = pixel / u_ViewportSize * 2.0 - 1.0;
+  gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);
+
+  v_TexCoord = (i_Source.xy + corner * i_Source.zw) / vec2(textureSize(u_Atlas, 0));
+  v_Color = i_Color;
+}
@@ -0,0 +1,158 @@
+use crate::font::GlyphQuad;
+use crate::gl::{Buffer, Gl, Program, VertexArray};
+use crate::shader::{
+    bind_frame_data, Preprocessor, ProgramInfo, Sampler, ShaderError, UniformBinding,
+};
+use crate::tilemap::TileAtlas;
+use glam::Vec4;
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+// Floats per glyph in a `TextBatch`: screen rectangle, atlas rectangle and colour
+const GLYPH_FLOATS: usize = 12;
+
+// The attributes `text-vert.glsl` reads per glyph, in the order they're stored
+const ATTRIBUTES: [&str; 3] = ["i_Rect", "i_Source", "i_Color"];
+
+// Glyphs laid out by `BitmapFont::layout`, uploaded for drawing. Positions are in CSS pixels
+// from the top left of the canvas.
+#[derive(Debug)]
+pub struct TextBatch {
+    buffer: Buffer<f32>,
+    vertex_array: VertexArray,
+    count: usize,
+}
+
+impl TextBatch {
+    pub fn new(gl: &Gl) -> Result<Self, JsValue> {
+        let buffer = Buffer::new(gl, WebGl2RenderingContext::ARRAY_BUFFER)?;
+        let vertex_array = VertexArray::new(gl)?;
+
+        let _state = gl.save_state();
+        vertex_array.bind();
+        buffer.bind();
+        let stride = (GLYPH_FLOATS * std::mem::size_of::<f32>()) as i32;
+        for location in 0..ATTRIBUTES.len() as u32 {
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_pointer_with_i32(
+                location,
+                4,
+                WebGl2RenderingContext::FLOAT,
+                false,
+                stride,
+                location as i32 * 16,
+            );
+            // One glyph per instance
+            gl.vertex_attrib_divisor(location, 1);
+        }
+
+        Ok(Self {
+            buffer,
+            vertex_array,
+            count: 0,
+        })
+    }
+
+    // Replaces the glyphs with `glyphs`, each drawn in its colour. The colour multiplies the
+    // atlas, so white fonts take it as is.
+    pub fn set(&mut self, glyphs: impl IntoIterator<Item = (GlyphQuad, Vec4)>) {
+        let mut data = Vec::new();
+        for (quad, color) in glyphs {
+            data.extend_from_slice(&quad.position.to_array());
+            data.extend_from_slice(&quad.size.to_array());
+            data.extend_from_slice(&quad.source.to_array());
+            data.extend_from_slice(&color.to_array());
+        }
+        self.count = data.len() / GLYPH_FLOATS;
+        self.buffer
+            .upload(&data, WebGl2RenderingContext::DYNAMIC_DRAW);
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+// Draws `TextBatch`es on top of the scene, e.g. for HUD messages, using the font of a
+// `TileAtlas`
+pub struct TextRender {
+    program: Program,
+
+    uniforms: UniformBinding<TextUniforms>,
+}
+
+crate::uniforms! {
+    struct TextUniforms {
+        atlas: Sampler => "u_Atlas",
+        pixel_ratio: f32 => "u_PixelRatio",
+    }
+}
+
+impl TextRender {
+    pub fn new(gl: &Gl) -> Result<Self, JsValue> {
+        let (program, uniforms) = Self::link(gl)?;
+        Ok(Self { program, uniforms })
+    }
+
+    fn link(gl: &Gl) -> Result<(Program, UniformBinding<TextUniforms>), ShaderError> {
+        let attributes: Vec<(u32, String)> = ATTRIBUTES
+            .iter()
+            .enumerate()
+            .map(|(location, name)| (location as u32, name.to_string()))
+            .collect();
+        let program = Program::link(
+            gl,
+            &Preprocessor::new().process("text-vert.glsl")?,
+            &Preprocessor::new().process("text-frag.glsl")?,
+            &attributes,
+            None,
+        )?;
+
+        bind_frame_data(gl, program.raw())?;
+        let info = ProgramInfo::reflect(gl, program.raw());
+        let uniforms = UniformBinding::new(gl, program.raw(), &info)?;
+        Ok((program, uniforms))
+    }
+
+    // The program's active attributes and uniforms, for diagnostics
+    pub fn program_info(&self, gl: &Gl) -> ProgramInfo {
+        ProgramInfo::reflect(gl, self.program.raw())
+    }
+
+    // `pixel_ratio` is the number of drawing buffer pixels per CSS pixel
+    pub fn render(&self, gl: &Gl, batch: &TextBatch, atlas: &TileAtlas, pixel_ratio: f32) {
+        if batch.is_empty() {
+            return;
+        }
+        let _state = gl.save_state();
+        self.program.bind();
+
+        gl.enable(WebGl2RenderingContext::BLEND);
+        gl.blend_func(
+            WebGl2RenderingContext::SRC_ALPHA,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
+
+        self.uniforms.upload(
+            gl,
+            &TextUniforms {
+                atlas: Sampler(0),
+                pixel_ratio,
+            },
+        );
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        atlas.texture().bind();
+
+        batch.vertex_array.bind();
+        gl.draw_arrays_instanced(
+            WebGl2RenderingContext::TRIANGLE_STRIP,
+            0,
+            4,
+            batch.count as i32,
+        );
+    }
+}
@@ -0,0 +1,42 @@
+#version 300 es
+precision highp float;
+
+/* The font or tile set the glyphs come from */
+uniform sampler2D u_Atlas;
+/* Where each glyph is in the atlas, in pixels, as two texels holding
+   little-endian ushorts: x and y, then width and height. Glyph n is at texel
+   2 (n % 256) of row n / 256. See `TileAtlas`. */
+uniform highp usampler2D u_Glyphs;
+
+flat in highp uint v_Glyph;
+flat in vec4 v_Foreground;
+flat in vec4 v_Background;
+in vec2 v_Local;
+
+out vec4 o_FragColor;
+
+void main() {
+  ivec2 entry = ivec2(int(v_Glyph % 256u) * 2, int(v_Glyph / 256u));
+  uvec4 position = texelFetch(u_Glyphs, entry, 0);
+  uvec4 size = texelFetch(u_Glyphs, entry + ivec2(1, 0), 0);
+  vec4 rect = vec4(position.r | (position.g << 8), position.b | (position.a << 8),
+                   size.r | (size.g << 8), size.b | (size.a << 8));
+
+  /* The glyph is stretched over the cell. Atlas rows go down while the map's
+     go up, and samples are kept half a texel inside the glyph so its
+     neighbours don't bleed in. */
+  vec4 texel = vec4(0.0);
+  if (rect.z > 0.0 && rect.w > 0.0) {
+    vec2 local = clamp(vec2(v_Local.x, 1.0 - v_Local.y) * rect.zw, vec2(0.5),
+                       rect.zw - 0.5);
+    texel = texture(u_Atlas, (rect.xy + local) / vec2(textureSize(u_Atlas, 0)));
+  }
+
+  /* White glyphs take the foreground colour; coloured tiles are tinted by
+     it. The glyph is laid over the background, which may be transparent. */
+  vec4 foreground = v_Foreground * texel;
+  float alpha = foreground.a + v_Background.a * (1.0 - foreground.a);
+  vec3 color = foreground.rgb * foreground.a
+             + v_Background.rgb * v_Background.a * (1.0 - foreground.a);
+  o_FragColor = vec4(alpha > 0.0 ? color / alpha : vec3(0.0), alpha);
+}
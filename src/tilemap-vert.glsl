@@ -0,0 +1,36 @@
+#version 300 es
+precision highp float;
+precision highp int;
+precision highp usampler2D;
+
+#include "frame.glsl"
+
+/* Three texels per cell, cell (x, y) taking texels 3x to 3x + 2 of row y:
+   the glyph index as a little-endian ushort, then the foreground and
+   background colours. See `Tilemap`. */
+uniform usampler2D u_Cells;
+/* Where the corner of cell (0, 0) is in the world */
+uniform vec3 u_MapOrigin;
+
+flat out uint v_Glyph;
+flat out vec4 v_Foreground;
+flat out vec4 v_Background;
+/* Position within the cell, from (0, 0) at the bottom left to (1, 1) */
+out vec2 v_Local;
+
+void main() {
+  int width = textureSize(u_Cells, 0).x / 3;
+  ivec2 cell = ivec2(gl_InstanceID % width, gl_InstanceID / width);
+
+  uvec4 glyph = texelFetch(u_Cells, ivec2(cell.x * 3, cell.y), 0);
+  v_Glyph = glyph.r | (glyph.g << 8);
+  v_Foreground = vec4(texelFetch(u_Cells, ivec2(cell.x * 3 + 1, cell.y), 0)) / 255.0;
+  v_Background = vec4(texelFetch(u_Cells, ivec2(cell.x * 3 + 2, cell.y), 0)) / 255.0;
+
+  /* A triangle strip over the cell: (0, 0), (1, 0), (0, 1), (1, 1) */
+  vec2 corner = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+  v_Local = corner;
+
+  vec3 position = u_MapOrigin + vec3(vec2(cell) + corner, 0.0);
+  gl_Position = u_ViewProjection * vec4(position, 1.0);
+}
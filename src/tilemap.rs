@@ -0,0 +1,462 @@
+use crate::font::{BitmapFont, Charset};
+use crate::gl::{Gl, Program, Texture2D, VertexArray};
+use crate::shader::{
+    bind_frame_data, Preprocessor, ProgramInfo, Sampler, ShaderError, UniformBinding,
+};
+use glam::{Vec3, Vec4};
+use wasm_bindgen::JsValue;
+use web_sys::WebGl2RenderingContext;
+
+// One cell of a tile map: a glyph from the atlas, drawn in the foreground colour over the
+// background colour
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cell {
+    pub glyph: u16,
+    pub foreground: Vec4,
+    // Transparent lets whatever is behind the map show through
+    pub background: Vec4,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            glyph: 0,
+            foreground: Vec4::ONE,
+            background: Vec4::ZERO,
+        }
+    }
+}
+
+impl Cell {
+    pub fn new(glyph: u16, foreground: Vec4, background: Vec4) -> Self {
+        Self {
+            glyph,
+            foreground,
+            background,
+        }
+    }
+
+    // The cell as the tile map shader reads it: three RGBA texels holding the glyph and the two
+    // colours
+    fn to_texels(self) -> [u8; 12] {
+        let [glyph_low, glyph_high] = self.glyph.to_le_bytes();
+        let [fr, fg, fb, fa] = rgba8(self.foreground);
+        let [br, bg, bb, ba] = rgba8(self.background);
+        [glyph_low, glyph_high, 0, 0, fr, fg, fb, fa, br, bg, bb, ba]
+    }
+}
+
+fn rgba8(color: Vec4) -> [u8; 4] {
+    (color.clamp(Vec4::ZERO, Vec4::ONE) * 255.0)
+        .round()
+        .to_array()
+        .map(|c| c as u8)
+}
+
+// A grid of cells drawn on the XY plane, one world unit (a tile, see `camera`) per cell, with
+// cell (0, 0) at the bottom left. The cells are kept here and mirrored in a data texture that
+// `TilemapRender` reads, so changes are batched up and uploaded by `flush`.
+#[derive(Debug)]
+pub struct Tilemap {
+    width: u32,
+    height: u32,
+    // Where the bottom left corner of cell (0, 0) is
+    pub origin: Vec3,
+    cells: Vec<Cell>,
+    texture: Texture2D,
+    // Rows changed since the last upload, as a range
+    dirty: Option<(u32, u32)>,
+}
+
+impl Tilemap {
+    // A `width` × `height` map of empty cells
+    pub fn new(gl: &Gl, width: u32, height: u32) -> Result<Self, JsValue> {
+        if width == 0 || height == 0 {
+            return Err("Tile map must be at least 1 × 1".into());
+        }
+        let len = width
+            .checked_mul(height)
+            .ok_or_else(|| format!("A {} × {} tile map is too large", width, height))?;
+        // Each cell takes three texels of the cell texture
+        check_texture_size(gl, "The tile map", u64::from(width) * 3, u64::from(height))?;
+        let cells = vec![Cell::default(); len as usize];
+        let texture = create_cell_texture(gl, width, height, &cells)?;
+        Ok(Self {
+            width,
+            height,
+            origin: Vec3::ZERO,
+            cells,
+            texture,
+            dirty: None,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Every cell, row by row from the bottom
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<&Cell> {
+        self.index(x, y).map(|i| &self.cells[i])
+    }
+
+    // Returns false if (`x`, `y`) is outside the map
+    pub fn set(&mut self, x: u32, y: u32, cell: Cell) -> bool {
+        match self.index(x, y) {
+            Some(i) => {
+                if self.cells[i] != cell {
+                    self.cells[i] = cell;
+                    self.mark_dirty(y, y);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn fill(&mut self, cell: Cell) {
+        self.cells.fill(cell);
+        self.mark_dirty(0, self.height - 1);
+    }
+
+    // Replaces every cell; `cells` must hold `width × height` of them, row by row from the
+    // bottom
+    pub fn set_cells(&mut self, cells: &[Cell]) -> Result<(), JsValue> {
+        if cells.len() != self.cells.len() {
+            return Err(format!(
+                "Expected {} cells for a {} × {} map, got {}",
+                self.cells.len(),
+                self.width,
+                self.height,
+                cells.len()
+            )
+            .into());
+        }
+        self.cells.copy_from_slice(cells);
+        self.mark_dirty(0, self.height - 1);
+        Ok(())
+    }
+
+    // Uploads the rows changed since the last flush
+    pub fn flush(&mut self) -> Result<(), JsValue> {
+        let (first, last) = match self.dirty.take() {
+            Some(rows) => rows,
+            None => return Ok(()),
+        };
+        let start = (first * self.width) as usize;
+        let end = ((last + 1) * self.width) as usize;
+        let texels: Vec<u8> = self.cells[start..end]
+            .iter()
+            .flat_map(|cell| cell.to_texels())
+            .collect();
+        self.texture.update(
+            0,
+            first as i32,
+            self.width as i32 * 3,
+            (last - first + 1) as i32,
+            WebGl2RenderingContext::RGBA_INTEGER,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            &texels,
+        )
+    }
+
+    // A copy of the map with its cell texture recreated, after the context has been restored
+    pub fn restored(&self, gl: &Gl) -> Result<Self, JsValue> {
+        Ok(Self {
+            width: self.width,
+            height: self.height,
+            origin: self.origin,
+            cells: self.cells.clone(),
+            texture: create_cell_texture(gl, self.width, self.height, &self.cells)?,
+            dirty: None,
+        })
+    }
+
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        (x < self.width && y < self.height).then(|| (y * self.width + x) as usize)
+    }
+
+    fn mark_dirty(&mut self, first: u32, last: u32) {
+        self.dirty = Some(match self.dirty {
+            Some((dirty_first, dirty_last)) => (dirty_first.min(first), dirty_last.max(last)),
+            None => (first, last),
+        });
+    }
+}
+
+// Checks the context can hold a `width` × `height` texture for `what`
+fn check_texture_size(gl: &Gl, what: &str, width: u64, height: u64) -> Result<(), JsValue> {
+    let max = u64::from(gl.max_texture_size());
+    if width > max || height > max {
+        return Err(format!(
+            "{} needs a {} × {} texture, but textures can be at most {} × {}",
+            what, width, height, max, max
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn create_cell_texture(
+    gl: &Gl,
+    width: u32,
+    height: u32,
+    cells: &[Cell],
+) -> Result<Texture2D, JsValue> {
+    let texels: Vec<u8> = cells.iter().flat_map(|cell| cell.to_texels()).collect();
+    let texture = Texture2D::new(
+        gl,
+        width as i32 * 3,
+        height as i32,
+        WebGl2RenderingContext::RGBA8UI,
+        WebGl2RenderingContext::RGBA_INTEGER,
+        WebGl2RenderingContext::UNSIGNED_BYTE,
+        Some(&texels),
+    )?;
+    // Integer textures can't be filtered
+    texture.set_filter(
+        WebGl2RenderingContext::NEAREST,
+        WebGl2RenderingContext::NEAREST,
+    );
+    Ok(texture)
+}
+
+// A font or tile set on the GPU: its image, and a table of where each glyph is in it for the
+// tile map shader. Glyph indices are the font's (see `BitmapFont`).
+#[derive(Debug)]
+pub struct TileAtlas {
+    texture: Texture2D,
+    glyph_table: Texture2D,
+    font: BitmapFont,
+}
+
+impl TileAtlas {
+    // `font` describes the glyphs in `texture`
+    pub fn new(gl: &Gl, texture: Texture2D, font: BitmapFont) -> Result<Self, JsValue> {
+        if font.glyph_count() == 0 {
+            return Err("Tile atlas has no glyphs".into());
+        }
+        texture.set_filter(
+            WebGl2RenderingContext::NEAREST,
+            WebGl2RenderingContext::NEAREST,
+        );
+        texture.set_wrap(
+            WebGl2RenderingContext::CLAMP_TO_EDGE,
+            WebGl2RenderingContext::CLAMP_TO_EDGE,
+        );
+        Ok(Self {
+            texture,
+            glyph_table: create_glyph_table(gl, &font)?,
+            font,
+        })
+    }
+
+    // An atlas of `columns` × `rows` equally sized glyphs in CP437 order, from `width` × `height`
+    // RGBA pixels, top row first
+    pub fn from_rgba(
+        gl: &Gl,
+        width: u32,
+        height: u32,
+        columns: u32,
+        rows: u32,
+        pixels: &[u8],
+    ) -> Result<Self, JsValue> {
+        let len = u64::from(width) * u64::from(height) * 4;
+        if pixels.len() as u64 != len {
+            return Err(format!(
+                "Expected {} bytes of RGBA pixels for a {} × {} atlas, got {}",
+                len,
+                width,
+                height,
+                pixels.len()
+            )
+            .into());
+        }
+        if columns == 0 || rows == 0 {
+            return Err("Tile atlas must have at least one column and row".into());
+        }
+        check_texture_size(gl, "The tile atlas", u64::from(width), u64::from(height))?;
+        let font = BitmapFont::grid(
+            width,
+            height,
+            columns,
+            rows,
+            width / columns,
+            height / rows,
+            Charset::Cp437,
+        )?;
+        let texture = Texture2D::new(
+            gl,
+            width as i32,
+            height as i32,
+            WebGl2RenderingContext::RGBA8,
+            WebGl2RenderingContext::RGBA,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(pixels),
+        )?;
+        Self::new(gl, texture, font)
+    }
+
+    // An atlas from an image the browser has decoded (see `Texture2D::from_image`)
+    pub fn from_image(gl: &Gl, image: &JsValue, font: BitmapFont) -> Result<Self, JsValue> {
+        let texture = Texture2D::from_image(gl, image)?;
+        if (texture.width() as u32, texture.height() as u32)
+            != (font.image_width(), font.image_height())
+        {
+            return Err(format!(
+                "The font is for a {} × {} image, not {} × {}",
+                font.image_width(),
+                font.image_height(),
+                texture.width(),
+                texture.height()
+            )
+            .into());
+        }
+        Self::new(gl, texture, font)
+    }
+
+    pub fn font(&self) -> &BitmapFont {
+        &self.font
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.font.glyph_count()
+    }
+
+    pub fn texture(&self) -> &Texture2D {
+        &self.texture
+    }
+}
+
+// Glyphs per row of the glyph table
+const GLYPH_TABLE_WIDTH: usize = 256;
+
+// Two RGBA texels per glyph holding its rectangle in the atlas as little-endian ushorts: x and y,
+// then width and height. Glyph n is at texel 2 (n % 256) of row n / 256.
+fn create_glyph_table(gl: &Gl, font: &BitmapFont) -> Result<Texture2D, JsValue> {
+    let rows = font.glyph_count().div_ceil(GLYPH_TABLE_WIDTH);
+    // Each glyph takes two texels of the table
+    check_texture_size(
+        gl,
+        "The glyph table",
+        GLYPH_TABLE_WIDTH as u64 * 2,
+        rows as u64,
+    )?;
+    let mut texels = vec![0; rows * GLYPH_TABLE_WIDTH * 8];
+    for (glyph, texel) in font.glyphs().iter().zip(texels.chunks_exact_mut(8)) {
+        for (i, value) in [glyph.x, glyph.y, glyph.width, glyph.height]
+            .into_iter()
+            .enumerate()
+        {
+            texel[i * 2..i * 2 + 2].copy_from_slice(&(value.min(0xffff) as u16).to_le_bytes());
+        }
+    }
+    let texture = Texture2D::new(
+        gl,
+        GLYPH_TABLE_WIDTH as i32 * 2,
+        rows as i32,
+        WebGl2RenderingContext::RGBA8UI,
+        WebGl2RenderingContext::RGBA_INTEGER,
+        WebGl2RenderingContext::UNSIGNED_BYTE,
+        Some(&texels),
+    )?;
+    texture.set_filter(
+        WebGl2RenderingContext::NEAREST,
+        WebGl2RenderingContext::NEAREST,
+    );
+    Ok(texture)
+}
+
+// Draws tile maps, every cell in a single instanced draw of one quad per cell. It reads the same
+// camera (the `FrameData` block) as the particle programs, so maps and particles line up.
+pub struct TilemapRender {
+    program: Program,
+    // Empty: the quads are generated from the vertex and instance ids
+    vertex_array: VertexArray,
+
+    uniforms: UniformBinding<TilemapUniforms>,
+}
+
+crate::uniforms! {
+    struct TilemapUniforms {
+        cells: Sampler => "u_Cells",
+        origin: Vec3 => "u_MapOrigin",
+        atlas: Sampler => "u_Atlas",
+        glyphs: Sampler => "u_Glyphs",
+    }
+}
+
+impl TilemapRender {
+    pub fn new(gl: &Gl) -> Result<Self, JsValue> {
+        let (program, uniforms) = Self::link(gl)?;
+        Ok(Self {
+            program,
+            vertex_array: VertexArray::new(gl)?,
+            uniforms,
+        })
+    }
+
+    fn link(gl: &Gl) -> Result<(Program, UniformBinding<TilemapUniforms>), ShaderError> {
+        let program = Program::link(
+            gl,
+            &Preprocessor::new().process("tilemap-vert.glsl")?,
+            &Preprocessor::new().process("tilemap-frag.glsl")?,
+            &[],
+            None,
+        )?;
+
+        bind_frame_data(gl, program.raw())?;
+        let info = ProgramInfo::reflect(gl, program.raw());
+        let uniforms = UniformBinding::new(gl, program.raw(), &info)?;
+        Ok((program, uniforms))
+    }
+
+    // The program's active attributes and uniforms, for diagnostics
+    pub fn program_info(&self, gl: &Gl) -> ProgramInfo {
+        ProgramInfo::reflect(gl, self.program.raw())
+    }
+
+    // Draws `tilemap` as of its last `flush`
+    pub fn render(&self, gl: &Gl, tilemap: &Tilemap, atlas: &TileAtlas) {
+        let _state = gl.save_state();
+        self.program.bind();
+
+        gl.enable(WebGl2RenderingContext::BLEND);
+        gl.blend_func(
+            WebGl2RenderingContext::SRC_ALPHA,
+            WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+        );
+
+        self.uniforms.upload(
+            gl,
+            &TilemapUniforms {
+                cells: Sampler(0),
+                origin: tilemap.origin,
+                atlas: Sampler(1),
+                glyphs: Sampler(2),
+            },
+        );
+        gl.active_texture(WebGl2RenderingContext::TEXTURE0);
+        tilemap.texture.bind();
+        gl.active_texture(WebGl2RenderingContext::TEXTURE1);
+        atlas.texture.bind();
+        gl.active_texture(WebGl2RenderingContext::TEXTURE2);
+        atlas.glyph_table.bind();
+
+        self.vertex_array.bind();
+        gl.draw_arrays_instanced(
+            WebGl2RenderingContext::TRIANGLE_STRIP,
+            0,
+            4,
+            (tilemap.width * tilemap.height) as i32,
+        );
+    }
+}
@@ -0,0 +1,280 @@
+// Turns variable frame times into a whole number of fixed-length simulation steps, so particles
+// behave the same whatever the frame rate. Time left over from the last whole step is reported
+// as an interpolation factor for drawing between the last two steps.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    // Seconds per step
+    step: f32,
+    // Steps run at most per frame. After a long hitch the excess is dropped rather than
+    // simulated, so the simulation slows down instead of falling ever further behind.
+    max_steps: u32,
+    // Multiplies frame times: below 1 for slow motion, 0 to pause
+    time_scale: f32,
+    // Simulated seconds not yet stepped
+    accumulator: f32,
+}
+
+// What to simulate for a frame
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Steps {
+    pub count: u32,
+    // Seconds per step
+    pub step: f32,
+    // How far the frame is between the last two steps, from 0 to 1
+    pub interpolation: f32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(60.0)
+    }
+}
+
+impl FixedTimestep {
+    // Panics unless `tick_rate` (steps per second) is positive
+    pub fn new(tick_rate: f32) -> Self {
+        let mut timestep = Self {
+            step: 0.0,
+            max_steps: 5,
+            time_scale: 1.0,
+            accumulator: 0.0,
+        };
+        timestep.set_tick_rate(tick_rate);
+        timestep
+    }
+
+    pub fn tick_rate(&self) -> f32 {
+        1.0 / self.step
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        assert!(
+            tick_rate.is_finite() && tick_rate > 0.0,
+            "tick rate must be positive"
+        );
+        self.step = 1.0 / tick_rate;
+        self.accumulator = self.accumulator.min(self.step);
+    }
+
+    pub fn step(&self) -> f32 {
+        self.step
+    }
+
+    pub fn max_steps(&self) -> u32 {
+        self.max_steps
+    }
+
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        self.max_steps = max_steps.max(1);
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    // Negative scales are treated as 0
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    // How far the simulation is between its last two steps, from 0 to 1
+    pub fn interpolation(&self) -> f32 {
+        (self.accumulator / self.step).min(1.0)
+    }
+
+    // Number of whole steps covering `seconds`
+    pub fn steps_for(&self, seconds: f32) -> u32 {
+        (seconds.max(0.0) / self.step).ceil() as u32
+    }
+
+    // Forgets partially accumulated time, e.g. when a simulation is restarted
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+
+    // Accumulates `delta` seconds of wall time and returns the steps to run for it
+    pub fn advance(&mut self, delta: f32) -> Steps {
+        self.accumulator += delta.max(0.0) * self.time_scale;
+
+        let mut count = (self.accumulator / self.step) as u32;
+        if count > self.max_steps {
+            count = self.max_steps;
+            self.accumulator = self.step * count as f32;
+        }
+        self.accumulator = (self.accumulator - self.step * count as f32).max(0.0);
+
+        Steps {
+            count,
+            step: self.step,
+            interpolation: self.interpolation(),
+        }
+    }
+}
+
+// Decides how much to simulate each frame for a turn-based game: effects play out in real time
+// (through a `FixedTimestep`), can be paused between turns, and can be fast-forwarded to skip
+// them. Fast-forwarding runs extra steps per frame instead of longer ones, so the simulation
+// looks the same, only sooner.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    timestep: FixedTimestep,
+    paused: bool,
+    // Seconds queued by `fast_forward` that haven't been simulated yet
+    fast_forward: f32,
+    // Steps spent on fast-forwarding per frame, on top of the regular ones
+    fast_forward_steps: u32,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(FixedTimestep::default())
+    }
+}
+
+impl Clock {
+    pub fn new(timestep: FixedTimestep) -> Self {
+        Self {
+            timestep,
+            paused: false,
+            fast_forward: 0.0,
+            fast_forward_steps: 64,
+        }
+    }
+
+    pub fn timestep(&self) -> &FixedTimestep {
+        &self.timestep
+    }
+
+    pub fn timestep_mut(&mut self) -> &mut FixedTimestep {
+        &mut self.timestep
+    }
+
+    // Stops wall time from advancing the simulation. Fast-forwarding still runs.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Queues `seconds` of simulation to run as quickly as `fast_forward_steps` allows, whether or
+    // not the clock is paused. Negative, infinite and NaN durations are ignored.
+    pub fn fast_forward(&mut self, seconds: f32) {
+        if seconds.is_finite() && seconds > 0.0 {
+            self.fast_forward += seconds;
+        }
+    }
+
+    pub fn is_fast_forwarding(&self) -> bool {
+        self.fast_forward > 0.0
+    }
+
+    // Drops whatever fast-forwarding is still queued
+    pub fn cancel_fast_forward(&mut self) {
+        self.fast_forward = 0.0;
+    }
+
+    pub fn fast_forward_steps(&self) -> u32 {
+        self.fast_forward_steps
+    }
+
+    pub fn set_fast_forward_steps(&mut self, steps: u32) {
+        self.fast_forward_steps = steps.max(1);
+    }
+
+    // Returns the steps to run for a frame that took `delta` seconds of wall time
+    pub fn advance(&mut self, delta: f32) -> Steps {
+        let mut steps = if self.paused {
+            Steps {
+                count: 0,
+                step: self.timestep.step(),
+                interpolation: self.timestep.interpolation(),
+            }
+        } else {
+            self.timestep.advance(delta)
+        };
+
+        if self.fast_forward > 0.0 {
+            let count = self
+                .timestep
+                .steps_for(self.fast_forward)
+                .min(self.fast_forward_steps);
+            self.fast_forward = (self.fast_forward - count as f32 * steps.step).max(0.0);
+            steps.count += count;
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_frames_run_at_most_max_steps() {
+        let mut timestep = FixedTimestep::new(4.0);
+        timestep.set_max_steps(3);
+
+        let steps = timestep.advance(10.0);
+        assert_eq!(steps.count, 3);
+        assert_eq!(steps.step, 0.25);
+        // the excess is dropped rather than carried into the next frames
+        assert_eq!(steps.interpolation, 0.0);
+        assert_eq!(timestep.advance(0.0).count, 0);
+        assert_eq!(timestep.advance(0.25).count, 1);
+    }
+
+    #[test]
+    fn leftover_time_becomes_interpolation() {
+        let mut timestep = FixedTimestep::new(4.0);
+
+        let steps = timestep.advance(0.625);
+        assert_eq!(steps.count, 2);
+        assert_eq!(steps.interpolation, 0.5);
+
+        let steps = timestep.advance(0.0625);
+        assert_eq!(steps.count, 0);
+        assert_eq!(steps.interpolation, 0.75);
+
+        // the leftover counts towards the next step
+        let steps = timestep.advance(0.125);
+        assert_eq!(steps.count, 1);
+        assert_eq!(steps.interpolation, 0.25);
+    }
+
+    #[test]
+    fn zero_time_scale_pauses_the_simulation() {
+        let mut timestep = FixedTimestep::new(4.0);
+        timestep.advance(0.125);
+
+        timestep.set_time_scale(0.0);
+        let steps = timestep.advance(10.0);
+        assert_eq!(steps.count, 0);
+        assert_eq!(steps.interpolation, 0.5);
+
+        timestep.set_time_scale(0.5);
+        let steps = timestep.advance(0.25);
+        assert_eq!(steps.count, 1);
+        assert_eq!(steps.interpolation, 0.0);
+    }
+
+    #[test]
+    fn fast_forward_ignores_invalid_durations() {
+        let mut clock = Clock::new(FixedTimestep::new(4.0));
+        for seconds in [-1.0, f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+            clock.fast_forward(seconds);
+            assert!(!clock.is_fast_forwarding());
+        }
+
+        clock.pause();
+        clock.fast_forward(1.0);
+        clock.fast_forward(f32::NAN);
+        assert_eq!(clock.advance(0.0).count, 4);
+        assert!(!clock.is_fast_forwarding());
+    }
+}
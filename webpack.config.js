@@ -22,7 +22,9 @@ module.exports = {
             template: 'index.html'
         }),
       new WasmPackPlugin({
-          crateDirectory: path.resolve(__dirname, ".")
+          crateDirectory: path.resolve(__dirname, "."),
+          // `HOT_RELOAD=1 npm run serve` builds with runtime shader replacement
+          extraArgs: process.env.HOT_RELOAD ? "-- --features hot-reload" : "",
       }),
         // Have this example work in Edge which doesn't ship `TextEncoder` or
         // `TextDecoder` at this time.